@@ -0,0 +1,254 @@
+use serde::{Serialize, Deserialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use crate::tokens::{TokenPermissions, TokenScope};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMethod {
+    // an openid connect jwt, from cognito or the custom authorizer
+    BearerToken,
+    ApiKey,
+    // a jwt from an issuer that isn't for people signing in, eg. github actions
+    CiToken,
+}
+
+impl AuthMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuthMethod::BearerToken => "bearer_token",
+            AuthMethod::ApiKey => "api_key",
+            AuthMethod::CiToken => "ci_token",
+        }
+    }
+}
+
+impl fmt::Display for AuthMethod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for AuthMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bearer_token" => Ok(AuthMethod::BearerToken),
+            "api_key" => Ok(AuthMethod::ApiKey),
+            "ci_token" => Ok(AuthMethod::CiToken),
+            _ => Err(format!("unknown auth method {:?}", s)),
+        }
+    }
+}
+
+// What a signed in user is, from rules on their bearer token's claims.
+// Api keys don't have roles, just the scopes they were made with.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    // can also yank and manage the owners of crates they don't own
+    Admin,
+    Publisher,
+    Reader,
+}
+
+impl Role {
+    pub const ALL: [Role; 3] = [Role::Admin, Role::Publisher, Role::Reader];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Admin => "admin",
+            Role::Publisher => "publisher",
+            Role::Reader => "reader",
+        }
+    }
+
+    pub fn scopes(&self) -> &'static [TokenScope] {
+        match self {
+            Role::Admin | Role::Publisher => &TokenScope::ALL,
+            Role::Reader => &[TokenScope::Read],
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Role::ALL
+            .iter()
+            .find(|role| role.as_str() == s)
+            .copied()
+            .ok_or_else(|| format!("unknown role {:?}", s))
+    }
+}
+
+// Who a request is from and what they can do, however they authenticated.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Identity {
+    pub principal_id: String,
+    pub auth_method: AuthMethod,
+    pub permissions: TokenPermissions,
+    pub roles: Vec<Role>,
+    pub token_id: Option<String>,
+    pub expires_at: Option<i64>,
+}
+
+impl Identity {
+    pub fn new(principal_id: &str, auth_method: AuthMethod) -> Self {
+        Identity {
+            principal_id: principal_id.to_owned(),
+            auth_method,
+            permissions: TokenPermissions::default(),
+            roles: vec![],
+            token_id: None,
+            expires_at: None,
+        }
+    }
+
+    pub fn has_role(&self, role: Role) -> bool {
+        self.roles.contains(&role)
+    }
+
+    // The custom authorizer's response context. Api gateway only takes flat string,
+    // number and boolean values, and hands them all on to the api as strings.
+    pub fn to_context(&self) -> Value {
+        let mut context = json!({
+            "principal_id": self.principal_id,
+            "auth_method": self.auth_method.as_str(),
+            "scopes": self.permissions.scopes_context(),
+            "crates": self.permissions.crates_context(),
+        });
+        if !self.roles.is_empty() {
+            let roles: Vec<&str> = self.roles.iter().map(Role::as_str).collect();
+            context["roles"] = json!(roles.join(" "));
+        }
+        if let Some(ref token_id) = self.token_id {
+            context["token_id"] = json!(token_id);
+        }
+        if let Some(expires_at) = self.expires_at {
+            context["expires_at"] = json!(expires_at);
+        }
+        context
+    }
+
+    // None if the request didn't come through the custom authorizer. Missing scopes
+    // mean no scopes, and an api key without them is an error rather than a key that
+    // can do nothing.
+    pub fn from_context(context: &HashMap<String, Value>) -> Result<Option<Self>, String> {
+        let get = |key: &str| context.get(key).and_then(|v| v.as_str());
+
+        // api gateway adds principalId itself
+        let principal_id = match get("principal_id").or_else(|| get("principalId")) {
+            Some(principal_id) => principal_id,
+            None => return Ok(None),
+        };
+        let auth_method = get("auth_method")
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(AuthMethod::ApiKey);
+        let permissions = match (get("scopes"), auth_method) {
+            (Some(scopes), _) => TokenPermissions::from_context(scopes, get("crates").unwrap_or_default()),
+            (None, AuthMethod::ApiKey) => return Err(format!("no scopes for api key {}", principal_id)),
+            (None, _) => TokenPermissions {
+                scopes: vec![],
+                crates: vec![],
+            },
+        };
+        let roles = get("roles")
+            .map(|roles| roles.split_whitespace().filter_map(|s| s.parse().ok()).collect())
+            .unwrap_or_default();
+        let expires_at = context
+            .get("expires_at")
+            .and_then(|v| v.as_i64().or_else(|| v.as_str().and_then(|s| s.parse().ok())));
+
+        Ok(Some(Identity {
+            principal_id: principal_id.to_owned(),
+            auth_method,
+            permissions,
+            roles,
+            token_id: get("token_id").map(|s| s.to_owned()),
+            expires_at,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // what api gateway does to the context on the way through
+    fn stringify(context: Value) -> HashMap<String, Value> {
+        context
+            .as_object()
+            .expect("object")
+            .iter()
+            .map(|(k, v)| {
+                let v = match v {
+                    Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                };
+                (k.clone(), Value::String(v))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_identity_context_round_trip() {
+        let identity = Identity {
+            principal_id: "user".to_string(),
+            auth_method: AuthMethod::ApiKey,
+            permissions: TokenPermissions {
+                scopes: vec![TokenScope::Read, TokenScope::Publish],
+                crates: vec!["foo-*".to_string()],
+            },
+            roles: vec![],
+            token_id: Some("0123456789abcdef".to_string()),
+            expires_at: Some(1000),
+        };
+        assert_eq!(Identity::from_context(&stringify(identity.to_context())), Ok(Some(identity)));
+
+        let mut identity = Identity::new("user", AuthMethod::BearerToken);
+        let context = identity.to_context();
+        assert!(context.get("token_id").is_none());
+        assert!(context.get("roles").is_none());
+        assert_eq!(Identity::from_context(&stringify(context)), Ok(Some(identity.clone())));
+
+        identity.roles = vec![Role::Admin, Role::Reader];
+        let context = identity.to_context();
+        assert_eq!(context["roles"], json!("admin reader"));
+        assert_eq!(Identity::from_context(&stringify(context)), Ok(Some(identity)));
+    }
+
+    #[test]
+    fn test_identity_from_context_without_scopes() {
+        let mut context = HashMap::new();
+        assert_eq!(Identity::from_context(&context), Ok(None));
+
+        context.insert("principalId".to_string(), json!("user"));
+        assert!(Identity::from_context(&context).is_err());
+
+        context.insert("auth_method".to_string(), json!("bearer_token"));
+        let identity = Identity::from_context(&context).expect("identity").expect("some");
+        assert_eq!(identity.auth_method, AuthMethod::BearerToken);
+        assert!(identity.permissions.scopes.is_empty());
+        assert!(!identity.permissions.allows(TokenScope::Read, None));
+    }
+
+    #[test]
+    fn test_role_scopes() {
+        assert_eq!("publisher".parse::<Role>(), Ok(Role::Publisher));
+        assert!("owner".parse::<Role>().is_err());
+        assert_eq!(serde_json::to_value(Role::Admin).expect("to_value"), json!("admin"));
+        assert_eq!(Role::Reader.scopes(), &[TokenScope::Read]);
+        assert_eq!(Role::Publisher.scopes(), &TokenScope::ALL);
+    }
+}
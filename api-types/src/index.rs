@@ -0,0 +1,237 @@
+use serde::{Serialize, Deserialize};
+use crate::create::{CreateCrateInput, CreateCrateInputDependency, CreateCrateInputDependencyKind, FeaturesMap};
+
+/*
+{
+  "name": "foo",
+  "vers": "0.1.0",
+  "deps": [
+    {
+      "name": "rand",
+      "req": "^0.6",
+      "features": ["i128_support"],
+      "optional": false,
+      "default_features": true,
+      "target": null,
+      "kind": "normal",
+      "registry": null,
+      "package": null
+    }
+  ],
+  "cksum": "d867001db0e2b6e0496f9fac96930e2d42233ecd3ca0413e0753d4c7695d289c",
+  "features": { "extras": ["rand/simd_support"] },
+  "yanked": false,
+  "links": null
+}
+*/
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct IndexEntry {
+    pub name: String,
+    pub vers: String,
+    pub deps: Vec<IndexDependency>,
+    pub cksum: String,
+    pub features: FeaturesMap,
+    pub yanked: bool,
+    pub links: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct IndexDependency {
+    pub name: String,
+    pub req: String,
+    pub features: Vec<String>,
+    pub optional: bool,
+    pub default_features: bool,
+    pub target: Option<String>,
+    pub kind: CreateCrateInputDependencyKind,
+    pub registry: Option<String>,
+    pub package: Option<String>,
+}
+
+// The index's config.json, telling cargo where to download crates and find the api.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct IndexConfig {
+    pub dl: String,
+    pub api: Option<String>,
+    #[serde(rename="auth-required", default)]
+    pub auth_required: bool,
+}
+
+impl IndexConfig {
+    // Downloads go through the api's download endpoint.
+    pub fn new(api_url: &str, auth_required: bool) -> Self {
+        let api_url = api_url.trim_end_matches('/');
+
+        IndexConfig {
+            dl: format!("{}/api/v1/crates", api_url),
+            api: Some(api_url.to_string()),
+            auth_required,
+        }
+    }
+}
+
+impl IndexEntry {
+    pub fn from_create_crate_input(input: &CreateCrateInput, cksum: &str) -> Self {
+        IndexEntry {
+            name: input.name.clone(),
+            vers: input.vers.clone(),
+            deps: input.deps.iter().map(IndexDependency::from).collect(),
+            cksum: cksum.to_owned(),
+            features: input.features.clone(),
+            yanked: false,
+            links: input.links.clone(),
+        }
+    }
+}
+
+impl From<&CreateCrateInputDependency> for IndexDependency {
+    // The publish api names a renamed dependency by its original package name
+    // and puts the new name in `explicit_name_in_toml`, whereas the index
+    // does it the other way around using `package` for the original name.
+    fn from(dep: &CreateCrateInputDependency) -> Self {
+        let (name, package) = match dep.explicit_name_in_toml {
+            Some(ref renamed) => (renamed.clone(), Some(dep.name.clone())),
+            None => (dep.name.clone(), None),
+        };
+
+        IndexDependency {
+            name,
+            req: dep.version_req.clone(),
+            features: dep.features.clone(),
+            optional: dep.optional,
+            default_features: dep.default_features,
+            target: dep.target.clone(),
+            kind: dep.kind,
+            registry: dep.registry.clone(),
+            package,
+        }
+    }
+}
+
+// Where a crate's entries live in the index, eg. `1/a`, `2/ab`, `3/a/abc` or `ab/cd/abcd`.
+pub fn index_path(name: &str) -> String {
+    let name = name.to_lowercase();
+
+    match name.len() {
+        1 => format!("1/{}", name),
+        2 => format!("2/{}", name),
+        3 => format!("3/{}/{}", &name[..1], name),
+        _ => format!("{}/{}/{}", &name[..2], &name[2..4], name),
+    }
+}
+
+// Crate names that only differ in case or `-` vs `_` are the same crate to cargo,
+// so only one of them may be published.
+pub fn canonical_crate_name(name: &str) -> String {
+    name.to_lowercase().replace('-', "_")
+}
+
+// An index file has one json entry per line, one line per version.
+pub fn parse_index_file(contents: &str) -> serde_json::Result<Vec<IndexEntry>> {
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(serde_json::from_str)
+        .collect()
+}
+
+pub fn index_file_contents(entries: &[IndexEntry]) -> serde_json::Result<String> {
+    let mut contents = String::new();
+    for entry in entries {
+        contents.push_str(&serde_json::to_string(entry)?);
+        contents.push('\n');
+    }
+    Ok(contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dependency(name: &str, explicit_name_in_toml: Option<&str>) -> CreateCrateInputDependency {
+        CreateCrateInputDependency {
+            name: name.to_string(),
+            version_req: "^0.6".to_string(),
+            features: vec![],
+            optional: false,
+            default_features: true,
+            target: None,
+            kind: CreateCrateInputDependencyKind::Normal,
+            registry: None,
+            explicit_name_in_toml: explicit_name_in_toml.map(|s| s.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_index_path() {
+        assert_eq!(index_path("a"), "1/a");
+        assert_eq!(index_path("ab"), "2/ab");
+        assert_eq!(index_path("abc"), "3/a/abc");
+        assert_eq!(index_path("abcd"), "ab/cd/abcd");
+        assert_eq!(index_path("Serde_JSON"), "se/rd/serde_json");
+    }
+
+    #[test]
+    fn test_canonical_crate_name() {
+        assert_eq!(canonical_crate_name("foo"), "foo");
+        assert_eq!(canonical_crate_name("Serde-JSON"), "serde_json");
+        assert_eq!(canonical_crate_name("serde_json"), "serde_json");
+    }
+
+    #[test]
+    fn test_index_file_round_trip() {
+        let input: CreateCrateInput = serde_json::from_value(serde_json::json!({
+            "name": "foo",
+            "vers": "0.1.0",
+            "deps": [],
+            "features": {},
+            "authors": [],
+            "description": null,
+            "documentation": null,
+            "homepage": null,
+            "readme": null,
+            "readme_file": null,
+            "keywords": [],
+            "categories": [],
+            "license": "MIT",
+            "license_file": null,
+            "repository": null,
+            "badges": {},
+            "links": null
+        })).expect("from_value");
+        let entries = vec![
+            IndexEntry::from_create_crate_input(&input, "abc"),
+            IndexEntry::from_create_crate_input(&CreateCrateInput { vers: "0.2.0".to_string(), ..input.clone() }, "def"),
+        ];
+        let contents = index_file_contents(&entries).expect("contents");
+        assert_eq!(contents.lines().count(), 2);
+        assert!(contents.starts_with(r#"{"name":"foo","vers":"0.1.0","deps":[],"cksum":"abc","features":{},"yanked":false,"links":null}"#));
+        assert_eq!(parse_index_file(&contents).expect("parse"), entries);
+    }
+
+    #[test]
+    fn test_index_config() {
+        let config = IndexConfig::new("https://example.com/", true);
+        assert_eq!(
+            serde_json::to_string(&config).expect("to_string"),
+            r#"{"dl":"https://example.com/api/v1/crates","api":"https://example.com","auth-required":true}"#
+        );
+        let config: IndexConfig = serde_json::from_str(r#"{"dl":"https://example.com/dl","api":null}"#).expect("from_str");
+        assert!(!config.auth_required);
+    }
+
+    #[test]
+    fn test_index_dependency_from_input() {
+        let dep = IndexDependency::from(&dependency("rand", None));
+        assert_eq!(dep.name, "rand");
+        assert_eq!(dep.req, "^0.6");
+        assert_eq!(dep.package, None);
+    }
+
+    #[test]
+    fn test_index_dependency_from_renamed_input() {
+        let dep = IndexDependency::from(&dependency("rand", Some("random")));
+        assert_eq!(dep.name, "random");
+        assert_eq!(dep.package, Some("rand".to_string()));
+    }
+}
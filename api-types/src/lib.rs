@@ -2,4 +2,8 @@ pub mod create;
 pub mod yank;
 pub mod owners;
 pub mod search;
-pub mod error;
\ No newline at end of file
+pub mod error;
+pub mod index;
+pub mod tokens;
+pub mod auth;
+pub mod trusted_publishing;
\ No newline at end of file
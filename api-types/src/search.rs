@@ -16,4 +16,32 @@ pub struct SearchCrateOutputItem {
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct SearchCrateOutputMeta {
     pub total: u32,
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path;
+
+    #[test]
+    fn test_search_crate_output_load() {
+        let mut d = path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("resources/test/search-crates-output.yaml");
+        let s = fs::read_to_string(&d).expect("read file");
+        let output: SearchCrateOutput = serde_yaml::from_str(&s).expect("from str");
+        let expected = SearchCrateOutput {
+            crates: vec![
+                SearchCrateOutputItem {
+                    name: "rand".to_string(),
+                    max_version: "0.6.1".to_string(),
+                    description: "Random number generators and other randomness functionality.\n".to_string(),
+                }
+            ],
+            meta: SearchCrateOutputMeta {
+                total: 119,
+            },
+        };
+        assert_eq!(output, expected);
+    }
+}
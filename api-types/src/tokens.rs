@@ -0,0 +1,261 @@
+use serde::{Serialize, Deserialize};
+use hmac::{Hmac, Mac, NewMac};
+use sha2::Sha256;
+use std::fmt;
+use std::str::FromStr;
+
+pub const MAX_CRATE_GLOB_LENGTH: usize = 64;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "kebab-case")]
+pub enum TokenScope {
+    // search, download, owners and the index
+    Read,
+    Publish,
+    Yank,
+    Owners,
+}
+
+impl TokenScope {
+    pub const ALL: [TokenScope; 4] = [
+        TokenScope::Read,
+        TokenScope::Publish,
+        TokenScope::Yank,
+        TokenScope::Owners,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenScope::Read => "read",
+            TokenScope::Publish => "publish",
+            TokenScope::Yank => "yank",
+            TokenScope::Owners => "owners",
+        }
+    }
+}
+
+impl fmt::Display for TokenScope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for TokenScope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        TokenScope::ALL
+            .iter()
+            .find(|scope| scope.as_str() == s)
+            .copied()
+            .ok_or_else(|| format!("unknown token scope {:?}", s))
+    }
+}
+
+// What a token may be used for. Tokens from before scopes existed can do everything.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TokenPermissions {
+    pub scopes: Vec<TokenScope>,
+    // Globs of the crate names the token applies to, empty for every crate.
+    #[serde(default)]
+    pub crates: Vec<String>,
+}
+
+impl Default for TokenPermissions {
+    fn default() -> Self {
+        TokenPermissions {
+            scopes: TokenScope::ALL.to_vec(),
+            crates: vec![],
+        }
+    }
+}
+
+impl TokenPermissions {
+    pub fn allows_crate(&self, name: &str) -> bool {
+        self.crates.is_empty() || self.crates.iter().any(|glob| crate_glob_matches(glob, name))
+    }
+
+    // `crate_name` is None for requests that aren't about a single crate, eg. search.
+    pub fn allows(&self, scope: TokenScope, crate_name: Option<&str>) -> bool {
+        self.scopes.contains(&scope) && crate_name.map(|name| self.allows_crate(name)).unwrap_or(true)
+    }
+
+    // The api gateway authorizer context can only hold strings.
+    pub fn scopes_context(&self) -> String {
+        self.scopes.iter().map(TokenScope::as_str).collect::<Vec<_>>().join(" ")
+    }
+
+    pub fn crates_context(&self) -> String {
+        self.crates.join(",")
+    }
+
+    pub fn from_context(scopes: &str, crates: &str) -> Self {
+        TokenPermissions {
+            scopes: scopes.split_whitespace().filter_map(|s| s.parse().ok()).collect(),
+            crates: crates.split(',').filter(|s| !s.is_empty()).map(|s| s.to_owned()).collect(),
+        }
+    }
+}
+
+pub fn validate_crate_glob(glob: &str) -> Result<(), String> {
+    let valid = !glob.is_empty()
+        && glob.len() <= MAX_CRATE_GLOB_LENGTH
+        && glob.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '*');
+
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("invalid crate pattern {:?}", glob))
+    }
+}
+
+// Case insensitive like crate names.
+pub fn crate_glob_matches(glob: &str, name: &str) -> bool {
+    glob_matches(&glob.to_ascii_lowercase(), &name.to_ascii_lowercase())
+}
+
+// `*` matches any run of characters.
+pub fn glob_matches(glob: &str, s: &str) -> bool {
+    let mut parts = glob.split('*');
+    let first = parts.next().unwrap_or_default();
+    if !s.starts_with(first) {
+        return false;
+    }
+    let mut rest = &s[first.len()..];
+
+    let parts: Vec<&str> = parts.collect();
+    let (last, middle) = match parts.split_last() {
+        Some(split) => split,
+        None => return rest.is_empty(),
+    };
+
+    for part in middle {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+
+    rest.ends_with(last)
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub struct CreateTokenInput {
+    pub name: Option<String>,
+    // Defaults to every scope.
+    pub scopes: Option<Vec<TokenScope>>,
+    // Defaults to every crate.
+    pub crates: Option<Vec<String>>,
+    // Seconds until the token stops working, never if not given.
+    pub expires_in: Option<i64>,
+}
+
+impl CreateTokenInput {
+    pub fn permissions(&self) -> TokenPermissions {
+        let default = TokenPermissions::default();
+
+        TokenPermissions {
+            scopes: self.scopes.clone().unwrap_or(default.scopes),
+            crates: self.crates.clone().unwrap_or(default.crates),
+        }
+    }
+}
+
+// The only time the secret is handed out.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CreateTokenOutput {
+    pub id: String,
+    pub name: String,
+    pub token: String,
+    pub scopes: Vec<TokenScope>,
+    pub crates: Vec<String>,
+    pub created_at: Option<i64>,
+    pub expires_at: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TokenInfo {
+    pub id: String,
+    pub name: String,
+    pub scopes: Vec<TokenScope>,
+    pub crates: Vec<String>,
+    pub created_at: Option<i64>,
+    pub last_used_at: Option<i64>,
+    pub expires_at: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ListTokensOutput {
+    pub tokens: Vec<TokenInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RevokeTokenOutput {
+    pub ok: bool,
+}
+
+// Only this keyed hash of a token is stored, so the tokens table
+// is no use to anyone without the key.
+pub fn hash_token(key: &[u8], token: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("hmac takes keys of any length");
+    mac.update(token.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_token() {
+        // rfc 4231 test case 2
+        assert_eq!(
+            hash_token(b"Jefe", "what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert_ne!(hash_token(b"key", "token"), hash_token(b"other key", "token"));
+    }
+
+    #[test]
+    fn test_crate_glob_matches() {
+        assert!(crate_glob_matches("foo", "foo"));
+        assert!(crate_glob_matches("foo", "Foo"));
+        assert!(!crate_glob_matches("foo", "foo-bar"));
+        assert!(crate_glob_matches("foo-*", "foo-bar"));
+        assert!(crate_glob_matches("foo-*", "foo-"));
+        assert!(!crate_glob_matches("foo-*", "foo"));
+        assert!(crate_glob_matches("*-sys", "openssl-sys"));
+        assert!(crate_glob_matches("a*b*c", "abbc"));
+        assert!(!crate_glob_matches("a*b*c", "acb"));
+        assert!(crate_glob_matches("*", "anything"));
+        assert!(glob_matches("refs/tags/v*", "refs/tags/v1.0.0"));
+        assert!(!glob_matches("refs/tags/v*", "refs/tags/V1.0.0"));
+
+        assert!(validate_crate_glob("foo-*").is_ok());
+        assert!(validate_crate_glob("").is_err());
+        assert!(validate_crate_glob("foo,bar").is_err());
+    }
+
+    #[test]
+    fn test_token_permissions() {
+        let permissions = CreateTokenInput {
+            name: None,
+            scopes: Some(vec![TokenScope::Publish, TokenScope::Yank]),
+            crates: Some(vec!["foo-*".to_string(), "bar".to_string()]),
+            expires_in: None,
+        }.permissions();
+
+        assert!(permissions.allows(TokenScope::Publish, Some("foo-core")));
+        assert!(permissions.allows(TokenScope::Yank, Some("bar")));
+        assert!(!permissions.allows(TokenScope::Publish, Some("baz")));
+        assert!(!permissions.allows(TokenScope::Owners, Some("bar")));
+        assert!(!permissions.allows(TokenScope::Read, None));
+
+        let context = TokenPermissions::from_context(&permissions.scopes_context(), &permissions.crates_context());
+        assert_eq!(context, permissions);
+
+        let all = CreateTokenInput::default().permissions();
+        assert_eq!(all, TokenPermissions::default());
+        assert!(all.allows(TokenScope::Owners, Some("anything")));
+    }
+}
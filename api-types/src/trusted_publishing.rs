@@ -0,0 +1,214 @@
+use serde::{Serialize, Deserialize};
+use crate::tokens::glob_matches;
+
+// Which github actions runs may exchange their oidc token for a publish token.
+// Set up per crate by its owners.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct GitHubTrustPolicy {
+    // owner/repo
+    pub repository: String,
+    // The ids github gives the repository and its owner. Unlike the names they
+    // can't be taken over by someone else after a rename or deletion.
+    pub repository_id: String,
+    pub repository_owner_id: String,
+    // The workflow file name under .github/workflows, eg. release.yml
+    pub workflow: String,
+    // Any environment if not set.
+    #[serde(default)]
+    pub environment: Option<String>,
+    // Any ref if not set. `*` matches any run of characters, eg. refs/tags/v*
+    #[serde(rename = "ref", default)]
+    pub git_ref: Option<String>,
+}
+
+impl GitHubTrustPolicy {
+    // Github treats repository and environment names case insensitively,
+    // but not workflow file names or refs.
+    pub fn matches(&self, claims: &GitHubClaims) -> bool {
+        self.repository_id == claims.repository_id
+            && self.repository_owner_id == claims.repository_owner_id
+            && self.repository.eq_ignore_ascii_case(&claims.repository)
+            && claims.workflow_file() == Some(&self.workflow[..])
+            && self.environment.as_ref()
+                .map(|environment| claims.environment.as_ref().map(|e| e.eq_ignore_ascii_case(environment)).unwrap_or(false))
+                .unwrap_or(true)
+            && self.git_ref.as_ref()
+                .map(|git_ref| glob_matches(git_ref, &claims.git_ref))
+                .unwrap_or(true)
+    }
+}
+
+// The claims we care about in a github actions oidc token.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct GitHubClaims {
+    pub sub: String,
+    pub repository: String,
+    pub repository_id: String,
+    pub repository_owner_id: String,
+    // eg. octo-org/octo-repo/.github/workflows/release.yml@refs/tags/v1.0.0
+    pub workflow_ref: String,
+    #[serde(default)]
+    pub environment: Option<String>,
+    #[serde(rename = "ref")]
+    pub git_ref: String,
+    #[serde(default)]
+    pub jti: Option<String>,
+}
+
+impl GitHubClaims {
+    // release.yml for the example above. The repository in the workflow ref is
+    // spelt the same as the repository claim, so neither needs case folding.
+    pub fn workflow_file(&self) -> Option<&str> {
+        let path = self.workflow_ref.split('@').next()?;
+        let prefix = format!("{}/.github/workflows/", self.repository);
+        let file = path.get(prefix.len()..)?;
+        if path.get(..prefix.len())? == prefix && !file.is_empty() && !file.contains('/') {
+            Some(file)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TrustPolicyInfo {
+    pub id: String,
+    #[serde(flatten)]
+    pub policy: GitHubTrustPolicy,
+    pub added_by: Option<String>,
+    pub added_at: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ListTrustPoliciesOutput {
+    pub policies: Vec<TrustPolicyInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AddTrustPolicyOutput {
+    pub ok: bool,
+    pub id: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RemoveTrustPolicyOutput {
+    pub ok: bool,
+}
+
+// Sent by a workflow with the token from ACTIONS_ID_TOKEN_REQUEST_URL.
+// The reply is a CreateTokenOutput for a short lived token that can only publish the crate.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ExchangeTokenInput {
+    pub jwt: String,
+    #[serde(rename = "crate")]
+    pub crate_name: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn claims() -> GitHubClaims {
+        serde_json::from_value(json!({
+            "sub": "repo:octo-org/octo-repo:environment:release",
+            "repository": "octo-org/octo-repo",
+            "repository_id": "123",
+            "repository_owner": "octo-org",
+            "repository_owner_id": "456",
+            "workflow": "Release",
+            "workflow_ref": "octo-org/octo-repo/.github/workflows/release.yml@refs/tags/v1.0.0",
+            "environment": "release",
+            "ref": "refs/tags/v1.0.0",
+            "jti": "example-id",
+        })).expect("claims")
+    }
+
+    fn policy() -> GitHubTrustPolicy {
+        GitHubTrustPolicy {
+            repository: "Octo-Org/octo-repo".to_string(),
+            repository_id: "123".to_string(),
+            repository_owner_id: "456".to_string(),
+            workflow: "release.yml".to_string(),
+            environment: None,
+            git_ref: None,
+        }
+    }
+
+    #[test]
+    fn test_workflow_file() {
+        let mut claims = claims();
+        assert_eq!(claims.workflow_file(), Some("release.yml"));
+
+        claims.workflow_ref = "other-org/other-repo/.github/workflows/release.yml@refs/heads/main".to_string();
+        assert_eq!(claims.workflow_file(), None);
+        claims.workflow_ref = "Octo-Org/octo-repo/.github/workflows/release.yml@refs/heads/main".to_string();
+        assert_eq!(claims.workflow_file(), None);
+        claims.workflow_ref = "octo-org/octo-repo/.github/workflows/nested/release.yml@refs/heads/main".to_string();
+        assert_eq!(claims.workflow_file(), None);
+        claims.workflow_ref = "octo-org/octo-repo/.github/workflows/@refs/heads/main".to_string();
+        assert_eq!(claims.workflow_file(), None);
+
+        // not a char boundary where the prefix ends
+        claims.workflow_ref = "octo-org/octo-repo/.github/workfloéé".to_string();
+        assert_eq!(claims.workflow_file(), None);
+        claims.workflow_ref = "octo".to_string();
+        assert_eq!(claims.workflow_file(), None);
+    }
+
+    #[test]
+    fn test_policy_matches() {
+        let claims = claims();
+        assert!(policy().matches(&claims));
+
+        let mut policy = policy();
+        policy.environment = Some("Release".to_string());
+        policy.git_ref = Some("refs/tags/v*".to_string());
+        assert!(policy.matches(&claims));
+
+        let mut other = policy.clone();
+        other.repository = "octo-org/other-repo".to_string();
+        assert!(!other.matches(&claims));
+
+        let mut other = policy.clone();
+        other.repository_id = "789".to_string();
+        assert!(!other.matches(&claims));
+
+        let mut other = policy.clone();
+        other.repository_owner_id = "789".to_string();
+        assert!(!other.matches(&claims));
+
+        let mut other = policy.clone();
+        other.workflow = "ci.yml".to_string();
+        assert!(!other.matches(&claims));
+
+        let mut other = policy.clone();
+        other.workflow = "Release.yml".to_string();
+        assert!(!other.matches(&claims));
+
+        let mut other = policy.clone();
+        other.git_ref = Some("refs/tags/V*".to_string());
+        assert!(!other.matches(&claims));
+
+        let mut other = policy.clone();
+        other.environment = Some("staging".to_string());
+        assert!(!other.matches(&claims));
+
+        let mut other = policy.clone();
+        other.git_ref = Some("refs/heads/main".to_string());
+        assert!(!other.matches(&claims));
+
+        let mut claims = claims;
+        claims.environment = None;
+        assert!(!policy.matches(&claims));
+    }
+
+    #[test]
+    fn test_parse_policy() {
+        let policy: GitHubTrustPolicy = serde_json::from_str(
+            r#"{"repository": "octo-org/octo-repo", "repository_id": "123", "repository_owner_id": "456", "workflow": "release.yml", "ref": "refs/tags/*"}"#
+        ).expect("policy");
+        assert_eq!(policy.git_ref.as_deref(), Some("refs/tags/*"));
+        assert_eq!(policy.environment, None);
+    }
+}
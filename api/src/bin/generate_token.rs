@@ -1,7 +1,9 @@
+use api::secrets::KmsSecrets;
 use api::tokens;
 
 #[tokio::main]
 async fn main() {
-    let token = tokens::generate_token().await.expect("generate");
+    let secrets = KmsSecrets::new(&std::env::var("TOKEN_HASH_KEY").unwrap_or_default());
+    let token = tokens::generate_token(&secrets).await.expect("generate");
     println!("{:?}", token);
-}
\ No newline at end of file
+}
@@ -0,0 +1,43 @@
+use rusoto_core::Region;
+use rusoto_dynamodb::DynamoDbClient;
+use std::collections::BTreeMap;
+
+use api::config::get_env_var;
+use api::packages::{DynamoDbPackages, PackageRepository};
+use api::summaries::{CrateSummary, DynamoDbSummaries, SummaryRepository};
+
+// Fills in what older versions are missing: canonical names for the name index,
+// index statuses for the indexer's pending index
+// and a search summary for each crate, including crates the indexer mirrored
+// from git before it kept their summaries up to date itself.
+#[tokio::main]
+async fn main() {
+    env_logger::init();
+    let table = get_env_var("PACKAGES_TABLE").expect("config");
+    let summaries_table = get_env_var("SUMMARIES_TABLE").expect("config");
+    let client = DynamoDbClient::new(Region::default());
+
+    let packages = DynamoDbPackages::new(client.clone(), &table);
+    let updated = packages.set_missing_canonical_names().await.expect("migrate");
+    println!("set canonical names on {} packages", updated);
+    let updated = packages.set_missing_index_statuses().await.expect("migrate");
+    println!("set index statuses on {} unindexed packages", updated);
+
+    let mut crates = BTreeMap::new();
+    for package in packages.scan_packages().await.expect("scan") {
+        crates.entry(package.name.clone()).or_insert_with(Vec::new).push(package);
+    }
+
+    let summaries = DynamoDbSummaries::new(client, &summaries_table);
+    let mut count = 0;
+    for (name, versions) in crates.iter() {
+        match CrateSummary::from_packages(versions) {
+            Some(summary) => {
+                summaries.put_summary(&summary).await.expect("put summary");
+                count += 1;
+            }
+            None => summaries.delete_summary(name).await.expect("delete summary"),
+        }
+    }
+    println!("saved summaries of {} crates", count);
+}
@@ -0,0 +1,24 @@
+use rusoto_core::Region;
+use rusoto_dynamodb::DynamoDbClient;
+use std::sync::Arc;
+
+use api::config::{get_env_var, maybe_get_env_var};
+use api::secrets::KmsSecrets;
+use api::tokens::DynamoDbTokens;
+
+#[tokio::main]
+async fn main() {
+    env_logger::init();
+    let table = get_env_var("TOKENS_TABLE").expect("config");
+    let legacy_table = maybe_get_env_var("LEGACY_TOKENS_TABLE").expect("config");
+    let token_hash_key = get_env_var("TOKEN_HASH_KEY").expect("config");
+
+    let tokens = DynamoDbTokens::new(
+        DynamoDbClient::new(Region::default()),
+        &table,
+        legacy_table.as_deref(),
+        Arc::new(KmsSecrets::new(&token_hash_key)),
+    );
+    let migrated = tokens.migrate_all_tokens().await.expect("migrate");
+    println!("migrated {} tokens", migrated);
+}
@@ -0,0 +1,88 @@
+use std::env;
+
+use crate::error::ApiError;
+use crate::result::ApiResult;
+
+pub const DEFAULT_TOKEN_EXPIRY_WARNING_DAYS: i64 = 7;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    pub tokens_table: String,
+    // The old table with a single token per user, which tokens are moved out of.
+    pub legacy_tokens_table: Option<String>,
+    pub packages_table: String,
+    // The packages table's index on canonical_name.
+    pub packages_name_index: String,
+    pub owners_table: String,
+    // One item per crate for search.
+    pub summaries_table: String,
+    // Where the api is reachable from cargo, eg. https://wagon.example.com
+    pub api_url: String,
+    pub index_auth_required: bool,
+    // Base64 kms ciphertext of the key tokens are hashed with.
+    pub token_hash_key: String,
+    pub token_expiry_warning_days: i64,
+    // The aud github actions oidc tokens must have to be exchanged for publish tokens.
+    // Trusted publishing is off if not set.
+    pub trusted_publishing_audience: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            tokens_table: "ApiTokens".to_string(),
+            legacy_tokens_table: None,
+            packages_table: "Packages".to_string(),
+            packages_name_index: "NameIndex".to_string(),
+            owners_table: "Owners".to_string(),
+            summaries_table: "CrateSummaries".to_string(),
+            api_url: "http://localhost".to_string(),
+            index_auth_required: true,
+            token_hash_key: String::new(),
+            token_expiry_warning_days: DEFAULT_TOKEN_EXPIRY_WARNING_DAYS,
+            trusted_publishing_audience: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn from_env() -> ApiResult<Config> {
+        let defaults = Config::default();
+
+        Ok(Config {
+            tokens_table: get_env_var("TOKENS_TABLE")?,
+            legacy_tokens_table: maybe_get_env_var("LEGACY_TOKENS_TABLE")?,
+            packages_table: get_env_var("PACKAGES_TABLE")?,
+            packages_name_index: maybe_get_env_var("PACKAGES_TABLE_NAME_INDEX")?
+                .unwrap_or(defaults.packages_name_index),
+            owners_table: get_env_var("OWNERS_TABLE")?,
+            summaries_table: get_env_var("SUMMARIES_TABLE")?,
+            api_url: get_env_var("API_URL")?,
+            index_auth_required: maybe_get_env_var("INDEX_AUTH_REQUIRED")?
+                .map(|v| v != "false")
+                .unwrap_or(defaults.index_auth_required),
+            token_hash_key: get_env_var("TOKEN_HASH_KEY")?,
+            token_expiry_warning_days: maybe_get_env_var("TOKEN_EXPIRY_WARNING_DAYS")?
+                .map(|v| {
+                    v.parse().map_err(|e| {
+                        ApiError::Other(format!("config key TOKEN_EXPIRY_WARNING_DAYS: {}", e))
+                    })
+                })
+                .transpose()?
+                .unwrap_or(defaults.token_expiry_warning_days),
+            trusted_publishing_audience: maybe_get_env_var("TRUSTED_PUBLISHING_AUDIENCE")?,
+        })
+    }
+}
+
+pub fn get_env_var(name: &str) -> ApiResult<String> {
+    env::var(name).map_err(|e| ApiError::Other(format!("config key {}: {}", name, e)))
+}
+
+pub fn maybe_get_env_var(name: &str) -> ApiResult<Option<String>> {
+    match env::var(name) {
+        Ok(value) => Ok(Some(value)),
+        Err(env::VarError::NotPresent) => Ok(None),
+        Err(e) => Err(ApiError::Other(format!("config key {}: {}", name, e))),
+    }
+}
@@ -0,0 +1,465 @@
+use api_types::create::{CreateCrateInput, CreateCrateOutput, CreateCrateOutputWarnings};
+use api_types::tokens::{TokenPermissions, TokenScope};
+use sha2::{Digest, Sha256};
+use std::convert::TryInto;
+use validator::Validate;
+
+use super::categories::{BADGES, CATEGORIES};
+use crate::error::ApiError;
+use crate::owners;
+use crate::packages::Package;
+use crate::result::ApiResult;
+use crate::state::AppState;
+use crate::storage;
+use crate::summaries;
+
+pub const MAX_CRATE_NAME_LENGTH: usize = 64;
+
+// The body of a cargo publish request is binary:
+//   u32 LE length of the json metadata
+//   json metadata (CreateCrateInput)
+//   u32 LE length of the .crate file
+//   .crate file (gzipped tarball)
+pub fn parse_create_crate_body(body: &[u8]) -> ApiResult<(CreateCrateInput, &[u8])> {
+    let (metadata, rest) = read_length_prefixed(body, "metadata")?;
+    let (tarball, rest) = read_length_prefixed(rest, "crate file")?;
+
+    if !rest.is_empty() {
+        return Err(ApiError::InvalidInput(format!(
+            "unexpected {} bytes after crate file",
+            rest.len()
+        )));
+    }
+
+    let input = serde_json::from_slice::<CreateCrateInput>(metadata)
+        .map_err(|e| ApiError::InvalidInput(format!("invalid crate metadata: {}", e)))?;
+
+    Ok((input, tarball))
+}
+
+fn read_length_prefixed<'a>(body: &'a [u8], what: &str) -> ApiResult<(&'a [u8], &'a [u8])> {
+    if body.len() < 4 {
+        return Err(ApiError::InvalidInput(format!("missing {} length", what)));
+    }
+
+    let (len_bytes, rest) = body.split_at(4);
+    let len = u32::from_le_bytes(len_bytes.try_into().expect("4 bytes")) as usize;
+
+    if rest.len() < len {
+        return Err(ApiError::InvalidInput(format!(
+            "{} length {} exceeds remaining {} bytes",
+            what,
+            len,
+            rest.len()
+        )));
+    }
+
+    Ok(rest.split_at(len))
+}
+
+pub fn validate_crate_name(name: &str) -> ApiResult<()> {
+    let valid = name.len() <= MAX_CRATE_NAME_LENGTH
+        && matches!(name.chars().next(), Some(c) if c.is_ascii_alphabetic())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+    if valid {
+        Ok(())
+    } else {
+        Err(ApiError::InvalidInput(format!("invalid crate name {:?}", name)))
+    }
+}
+
+pub fn validate_input(input: &CreateCrateInput) -> ApiResult<()> {
+    input
+        .validate()
+        .map_err(|e| ApiError::InvalidInput(format!("invalid crate metadata: {}", e)))?;
+
+    validate_crate_name(&input.name)?;
+
+    semver::Version::parse(&input.vers)
+        .map_err(|e| ApiError::InvalidInput(format!("invalid version {:?}: {}", input.vers, e)))?;
+
+    Ok(())
+}
+
+pub fn warnings(input: &CreateCrateInput) -> CreateCrateOutputWarnings {
+    CreateCrateOutputWarnings {
+        invalid_categories: input
+            .categories
+            .iter()
+            .filter(|c| !CATEGORIES.contains(&&c[..]))
+            .cloned()
+            .collect(),
+        invalid_badges: input
+            .badges
+            .keys()
+            .filter(|b| !BADGES.contains(&&b[..]))
+            .cloned()
+            .collect(),
+        other: vec![],
+    }
+}
+
+pub fn checksum(tarball: &[u8]) -> String {
+    hex::encode(Sha256::digest(tarball))
+}
+
+// The crate name is only known once the body is parsed, so the token's
+// crate restrictions can't be checked by the authorizer.
+pub async fn create_crate(
+    state: &AppState,
+    principal_id: &str,
+    permissions: &TokenPermissions,
+    body: &[u8],
+) -> ApiResult<CreateCrateOutput> {
+    let (input, tarball) = parse_create_crate_body(body)?;
+    validate_input(&input)?;
+
+    if !permissions.allows(TokenScope::Publish, Some(&input.name)) {
+        return Err(ApiError::Forbidden(format!(
+            "token does not have the publish scope for {}",
+            input.name
+        )));
+    }
+
+    if tarball.is_empty() {
+        return Err(ApiError::InvalidInput("empty crate file".to_string()));
+    }
+
+    let new_crate = owners::check_publisher(state, principal_id, &input.name).await?;
+
+    if state
+        .packages
+        .get_package(&input.name, &input.vers)
+        .await?
+        .is_some()
+    {
+        return Err(ApiError::Conflict(format!(
+            "crate version {} {} already exists",
+            input.name, input.vers
+        )));
+    }
+
+    let package = Package::new(&input, &checksum(tarball), principal_id);
+    log::info!("publishing {} {} for {}", package.name, package.version, principal_id);
+
+    // Only the publish that wins the conditional put of the version, and the claim on
+    // a new crate's name, gets to write the crate file. Writing it first would let the
+    // loser of a race overwrite the winner's file with one that doesn't match its cksum.
+    state.packages.put_new_package(&package).await?;
+
+    if new_crate {
+        if let Err(err) = owners::claim_new_crate(state, principal_id, &package.name).await {
+            log::info!("backing out {} {}: {:?}", package.name, package.version, err);
+            state.packages.delete_package(&package.name, &package.version).await?;
+            return Err(err);
+        }
+    }
+
+    let crate_key = storage::crate_key(&package.name, &package.version);
+    if let Err(err) = state.storage.put(&crate_key, tarball.to_vec()).await {
+        log::info!("backing out {} {}: {:?}", package.name, package.version, err);
+        state.packages.delete_package(&package.name, &package.version).await?;
+        return Err(err);
+    }
+
+    summaries::refresh_summary(state, &package.name).await?;
+
+    Ok(CreateCrateOutput {
+        warnings: warnings(&input),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::Config;
+    use crate::crates::download::{self, Download};
+    use crate::crates::yank;
+    use api_types::auth::{AuthMethod, Identity, Role};
+
+    fn body(metadata: &[u8], tarball: &[u8]) -> Vec<u8> {
+        let mut body = vec![];
+        body.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+        body.extend_from_slice(metadata);
+        body.extend_from_slice(&(tarball.len() as u32).to_le_bytes());
+        body.extend_from_slice(tarball);
+        body
+    }
+
+    fn metadata() -> serde_json::Value {
+        serde_json::json!({
+            "name": "foo",
+            "vers": "0.1.0",
+            "deps": [],
+            "features": {},
+            "authors": ["Alice <a@example.com>"],
+            "description": "A nice description.",
+            "documentation": null,
+            "homepage": null,
+            "readme": null,
+            "readme_file": null,
+            "keywords": [],
+            "categories": ["development-tools", "not-a-category"],
+            "license": "MIT",
+            "license_file": null,
+            "repository": null,
+            "badges": { "travis-ci": {}, "not-a-badge": {} },
+            "links": null
+        })
+    }
+
+    #[test]
+    fn test_parse_create_crate_body() {
+        let metadata = serde_json::to_vec(&metadata()).expect("to_vec");
+        let body = body(&metadata, b"tarball");
+        let (input, tarball) = parse_create_crate_body(&body).expect("parse");
+        assert_eq!(input.name, "foo");
+        assert_eq!(input.vers, "0.1.0");
+        assert_eq!(tarball, b"tarball");
+        validate_input(&input).expect("valid");
+    }
+
+    #[test]
+    fn test_parse_truncated_create_crate_body() {
+        let metadata = serde_json::to_vec(&metadata()).expect("to_vec");
+        let body = body(&metadata, b"tarball");
+        assert!(parse_create_crate_body(&body[..body.len() - 1]).is_err());
+        assert!(parse_create_crate_body(&body[..2]).is_err());
+    }
+
+    #[test]
+    fn test_create_crate_warnings() {
+        let input: CreateCrateInput = serde_json::from_value(metadata()).expect("from_value");
+        let warnings = warnings(&input);
+        assert_eq!(warnings.invalid_categories, vec!["not-a-category".to_string()]);
+        assert_eq!(warnings.invalid_badges, vec!["not-a-badge".to_string()]);
+    }
+
+    #[test]
+    fn test_validate_crate_name() {
+        assert!(validate_crate_name("foo-bar_2").is_ok());
+        assert!(validate_crate_name("2foo").is_err());
+        assert!(validate_crate_name("foo bar").is_err());
+        assert!(validate_crate_name("").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_create_crate_in_memory() {
+        let state = AppState::in_memory(Config::default());
+        let metadata = serde_json::to_vec(&metadata()).expect("to_vec");
+        let body = body(&metadata, b"tarball");
+        let all = TokenPermissions::default();
+
+        create_crate(&state, "alice", &all, &body).await.expect("publish");
+        let package = state
+            .packages
+            .get_package("foo", "0.1.0")
+            .await
+            .expect("get")
+            .expect("package");
+        assert_eq!(package.published_by.as_deref(), Some("alice"));
+        assert_eq!(package.cksum, checksum(b"tarball"));
+
+        assert!(matches!(
+            create_crate(&state, "alice", &all, &body).await,
+            Err(ApiError::Conflict(_))
+        ));
+        assert!(matches!(
+            create_crate(&state, "bob", &all, &body).await,
+            Err(ApiError::Forbidden(_))
+        ));
+        let read_only = TokenPermissions {
+            scopes: vec![TokenScope::Read],
+            crates: vec![],
+        };
+        assert!(matches!(
+            create_crate(&state, "alice", &read_only, &body).await,
+            Err(ApiError::Forbidden(_))
+        ));
+
+        match download::download_crate(&state, "foo", "0.1.0").await.expect("download") {
+            Download::Content(data) => assert_eq!(data, b"tarball"),
+            Download::Redirect(url) => panic!("unexpected redirect to {}", url),
+        }
+
+        let bob = Identity::new("bob", AuthMethod::ApiKey);
+        let alice = Identity::new("alice", AuthMethod::ApiKey);
+        assert!(matches!(
+            yank::set_yanked(&state, &bob, "foo", "0.1.0", true).await,
+            Err(ApiError::Forbidden(_))
+        ));
+        yank::set_yanked(&state, &alice, "foo", "0.1.0", true).await.expect("yank");
+        let package = state.packages.get_package("foo", "0.1.0").await.expect("get");
+        assert!(package.expect("package").yanked);
+
+        // admins can yank crates they don't own
+        let mut admin = Identity::new("carol", AuthMethod::BearerToken);
+        admin.roles = vec![Role::Admin];
+        yank::set_yanked(&state, &admin, "foo", "0.1.0", false).await.expect("unyank");
+        let package = state.packages.get_package("foo", "0.1.0").await.expect("get");
+        assert!(!package.expect("package").yanked);
+        assert!(matches!(
+            yank::set_yanked(&state, &admin, "bar", "0.1.0", true).await,
+            Err(ApiError::NotFound(_))
+        ));
+    }
+
+    fn named_body(name: &str, version: &str) -> Vec<u8> {
+        let mut metadata = metadata();
+        metadata["name"] = serde_json::json!(name);
+        metadata["vers"] = serde_json::json!(version);
+        body(&serde_json::to_vec(&metadata).expect("to_vec"), b"tarball")
+    }
+
+    #[tokio::test]
+    async fn test_create_crate_name_collisions() {
+        let state = AppState::in_memory(Config::default());
+        let all = TokenPermissions::default();
+
+        create_crate(&state, "alice", &all, &named_body("foo-bar", "0.1.0"))
+            .await
+            .expect("publish");
+        for name in &["Foo-Bar", "foo_bar", "FOO_BAR"] {
+            for principal_id in &["alice", "bob"] {
+                assert!(matches!(
+                    create_crate(&state, principal_id, &all, &named_body(name, "0.2.0")).await,
+                    Err(ApiError::Conflict(_))
+                ));
+            }
+        }
+        assert_eq!(
+            owners::get_owner_ids(&state, "Foo-Bar").await.expect("owners"),
+            Vec::<String>::new()
+        );
+
+        // published before names were claimed
+        let mut legacy = metadata();
+        legacy["name"] = serde_json::json!("Legacy");
+        let legacy: CreateCrateInput = serde_json::from_value(legacy).expect("from_value");
+        state
+            .packages
+            .put_new_package(&Package::new(&legacy, "cksum", "carol"))
+            .await
+            .expect("put");
+        assert!(matches!(
+            create_crate(&state, "alice", &all, &named_body("legacy", "0.1.0")).await,
+            Err(ApiError::Conflict(_))
+        ));
+        create_crate(&state, "carol", &all, &named_body("Legacy", "0.2.0"))
+            .await
+            .expect("publish legacy");
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_first_publishes() {
+        let state = AppState::in_memory(Config::default());
+
+        // both get past the check before either has claimed the name
+        assert!(owners::check_publisher(&state, "alice", "foo").await.expect("check"));
+        assert!(owners::check_publisher(&state, "bob", "foo").await.expect("check"));
+
+        owners::claim_new_crate(&state, "alice", "foo").await.expect("claim");
+        assert!(matches!(
+            owners::claim_new_crate(&state, "bob", "foo").await,
+            Err(ApiError::Forbidden(_))
+        ));
+        assert!(matches!(
+            owners::claim_new_crate(&state, "bob", "Foo").await,
+            Err(ApiError::Conflict(_))
+        ));
+        assert_eq!(
+            owners::get_owner_ids(&state, "foo").await.expect("owners"),
+            vec!["alice"]
+        );
+
+        // a retry after the claim is fine
+        owners::claim_new_crate(&state, "alice", "foo").await.expect("claim again");
+    }
+
+    // Gives the other publish a turn before each write.
+    #[derive(Default)]
+    struct SlowStorage(storage::MemoryStorage);
+
+    struct YieldOnce(bool);
+
+    impl std::future::Future for YieldOnce {
+        type Output = ();
+
+        fn poll(
+            mut self: std::pin::Pin<&mut Self>,
+            cx: &mut std::task::Context,
+        ) -> std::task::Poll<()> {
+            if self.0 {
+                std::task::Poll::Ready(())
+            } else {
+                self.0 = true;
+                cx.waker().wake_by_ref();
+                std::task::Poll::Pending
+            }
+        }
+    }
+
+    impl storage::Storage for SlowStorage {
+        fn put<'a>(&'a self, key: &'a str, data: Vec<u8>) -> crate::BoxFuture<'a, ApiResult<()>> {
+            Box::pin(async move {
+                YieldOnce(false).await;
+                self.0.put(key, data).await
+            })
+        }
+
+        fn get<'a>(&'a self, key: &'a str) -> crate::BoxFuture<'a, ApiResult<Option<Vec<u8>>>> {
+            self.0.get(key)
+        }
+
+        fn exists<'a>(&'a self, key: &'a str) -> crate::BoxFuture<'a, ApiResult<bool>> {
+            self.0.exists(key)
+        }
+
+        fn delete<'a>(&'a self, key: &'a str) -> crate::BoxFuture<'a, ApiResult<()>> {
+            self.0.delete(key)
+        }
+
+        fn presign<'a>(
+            &'a self,
+            key: &'a str,
+            expires_in: std::time::Duration,
+        ) -> crate::BoxFuture<'a, ApiResult<Option<String>>> {
+            self.0.presign(key, expires_in)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_publishes_keep_the_winners_crate_file() {
+        let mut state = AppState::in_memory(Config::default());
+        state.storage = Box::new(SlowStorage::default());
+        let metadata = serde_json::to_vec(&metadata()).expect("to_vec");
+        let first = body(&metadata, b"first tarball");
+        let second = body(&metadata, b"second tarball");
+        let all = TokenPermissions::default();
+
+        let (first, second) = tokio::join!(
+            create_crate(&state, "alice", &all, &first),
+            create_crate(&state, "alice", &all, &second),
+        );
+        assert!(first.is_ok());
+        assert!(matches!(second, Err(ApiError::Conflict(_))));
+
+        let package = state
+            .packages
+            .get_package("foo", "0.1.0")
+            .await
+            .expect("get")
+            .expect("package");
+        let stored = state
+            .storage
+            .get(&storage::crate_key("foo", "0.1.0"))
+            .await
+            .expect("get")
+            .expect("crate file");
+        assert_eq!(checksum(&stored), package.cksum);
+        assert_eq!(package.cksum, checksum(b"first tarball"));
+    }
+}
@@ -0,0 +1,41 @@
+use std::time::Duration;
+
+use crate::error::ApiError;
+use crate::result::ApiResult;
+use crate::state::AppState;
+use crate::storage;
+
+pub const DOWNLOAD_URL_EXPIRY: Duration = Duration::from_secs(300);
+
+pub enum Download {
+    Redirect(String),
+    Content(Vec<u8>),
+}
+
+pub async fn download_crate(state: &AppState, name: &str, version: &str) -> ApiResult<Download> {
+    let package = state
+        .packages
+        .get_package(name, version)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("crate {} version {} not found", name, version)))?;
+
+    if package.deleted {
+        return Err(ApiError::NotFound(format!(
+            "crate {} version {} has been deleted",
+            name, version
+        )));
+    }
+
+    let key = storage::crate_key(&package.name, &package.version);
+
+    if let Some(url) = state.storage.presign(&key, DOWNLOAD_URL_EXPIRY).await? {
+        return Ok(Download::Redirect(url));
+    }
+
+    state
+        .storage
+        .get(&key)
+        .await?
+        .map(Download::Content)
+        .ok_or_else(|| ApiError::NotFound(format!("crate file for {} {} not found", name, version)))
+}
@@ -1 +1,6 @@
+pub mod categories;
 pub mod create;
+pub mod download;
+pub mod owners;
+pub mod search;
+pub mod yank;
@@ -0,0 +1,105 @@
+use api_types::auth::Identity;
+use api_types::owners::{
+    AddOwnerInput, AddOwnerOutput, GetOwnersOutput, GetOwnersOutputUser, RemoveOwnerInput,
+    RemoveOwnerOutput,
+};
+
+use crate::error::ApiError;
+use crate::owners::{self, Owner};
+use crate::result::ApiResult;
+use crate::state::AppState;
+
+// Owners are identified by principal id, which doubles as the login
+// given to `cargo owner --add` and `cargo owner --remove`.
+pub async fn get_owners(state: &AppState, name: &str) -> ApiResult<GetOwnersOutput> {
+    let owner_ids = owners::get_owner_ids(state, name).await?;
+
+    if owner_ids.is_empty() {
+        return Err(ApiError::NotFound(format!("crate {} not found", name)));
+    }
+
+    Ok(GetOwnersOutput {
+        users: owner_ids
+            .into_iter()
+            .enumerate()
+            .map(|(i, user_id)| GetOwnersOutputUser {
+                id: i as u32 + 1,
+                login: user_id,
+                name: None,
+            })
+            .collect(),
+    })
+}
+
+pub async fn add_owners(
+    state: &AppState,
+    identity: &Identity,
+    name: &str,
+    input: &AddOwnerInput,
+) -> ApiResult<AddOwnerOutput> {
+    let owner_ids = owners::check_owner_or_admin(state, identity, name).await?;
+    let principal_id = identity.principal_id.as_str();
+
+    if let Some(user_id) = input.users.iter().find(|id| owners::is_reserved_user_id(id)) {
+        return Err(ApiError::InvalidInput(format!("invalid owner {}", user_id)));
+    }
+
+    // persist legacy owners so adding someone doesn't lock out existing publishers
+    for user_id in owner_ids.iter() {
+        state
+            .owners
+            .put_owner(&Owner::new(name, user_id, principal_id))
+            .await?;
+    }
+
+    for user_id in input.users.iter() {
+        if state
+            .owners
+            .put_owner(&Owner::new(name, user_id, principal_id))
+            .await?
+        {
+            log::info!("{} added {} as owner of {}", principal_id, user_id, name);
+        }
+    }
+
+    Ok(AddOwnerOutput {
+        ok: true,
+        msg: format!("user(s) {} added as owner(s) of crate {}", input.users.join(", "), name),
+    })
+}
+
+pub async fn remove_owners(
+    state: &AppState,
+    identity: &Identity,
+    name: &str,
+    input: &RemoveOwnerInput,
+) -> ApiResult<RemoveOwnerOutput> {
+    let owner_ids = owners::check_owner_or_admin(state, identity, name).await?;
+    let principal_id = identity.principal_id.as_str();
+
+    let remaining = owner_ids
+        .iter()
+        .filter(|id| !input.users.contains(id))
+        .count();
+    if remaining == 0 {
+        return Err(ApiError::InvalidInput(format!(
+            "cannot remove all owners of crate {}",
+            name
+        )));
+    }
+
+    for user_id in owner_ids.iter() {
+        if input.users.contains(user_id) {
+            state.owners.delete_owner(name, user_id).await?;
+            log::info!("{} removed {} as owner of {}", principal_id, user_id, name);
+        } else {
+            // as above, keep legacy owners who aren't being removed
+            state
+                .owners
+                .put_owner(&Owner::new(name, user_id, principal_id))
+                .await?;
+        }
+    }
+
+    Ok(RemoveOwnerOutput { ok: true })
+}
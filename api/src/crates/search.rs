@@ -0,0 +1,126 @@
+use api_types::search::{SearchCrateOutput, SearchCrateOutputItem, SearchCrateOutputMeta};
+
+use crate::result::ApiResult;
+use crate::state::AppState;
+use crate::summaries::CrateSummary;
+
+pub const DEFAULT_PER_PAGE: usize = 10;
+pub const MAX_PER_PAGE: usize = 100;
+
+pub async fn search_crates(
+    state: &AppState,
+    query: &str,
+    per_page: Option<usize>,
+) -> ApiResult<SearchCrateOutput> {
+    // one summary per crate, all of them so the total counts every match
+    let summaries = state.summaries.scan_summaries().await?;
+    Ok(search_summaries(&summaries, query, per_page))
+}
+
+pub fn search_summaries(summaries: &[CrateSummary], query: &str, per_page: Option<usize>) -> SearchCrateOutput {
+    let per_page = per_page.unwrap_or(DEFAULT_PER_PAGE).min(MAX_PER_PAGE);
+    let query = query.trim().to_lowercase();
+
+    let mut matches: Vec<&CrateSummary> = summaries
+        .iter()
+        .filter(|c| query.is_empty() || matches_query(c, &query))
+        .collect();
+
+    // exact name matches first, otherwise alphabetical
+    matches.sort_by(|a, b| a.name.cmp(&b.name));
+    matches.sort_by_key(|c| c.name.to_lowercase() != query);
+
+    SearchCrateOutput {
+        crates: matches
+            .iter()
+            .take(per_page)
+            .map(|c| SearchCrateOutputItem {
+                name: c.name.clone(),
+                max_version: c.max_version.clone(),
+                description: c.description.clone().unwrap_or_default(),
+            })
+            .collect(),
+        meta: SearchCrateOutputMeta {
+            total: matches.len() as u32,
+        },
+    }
+}
+
+fn matches_query(summary: &CrateSummary, query: &str) -> bool {
+    summary.name.to_lowercase().contains(query)
+        || summary
+            .description
+            .as_ref()
+            .map(|d| d.to_lowercase().contains(query))
+            .unwrap_or(false)
+        || summary
+            .keywords
+            .iter()
+            .any(|k| k.to_lowercase() == query)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::packages::Package;
+
+    fn package(name: &str, version: &str, yanked: bool, description: &str, keywords: &[&str]) -> Package {
+        Package {
+            name: name.to_string(),
+            version: version.to_string(),
+            deps: vec![],
+            cksum: "cksum".to_string(),
+            features: Default::default(),
+            yanked,
+            links: None,
+            description: Some(description.to_string()),
+            keywords: keywords.iter().map(|k| k.to_string()).collect(),
+            published_by: None,
+            published_at: None,
+            updated_at: None,
+            indexed: true,
+            deleted: false,
+        }
+    }
+
+    fn summaries() -> Vec<CrateSummary> {
+        vec![
+            vec![
+                package("rand", "0.6.1", false, "Random number generators", &["random"]),
+                package("rand", "0.6.10", true, "Random number generators", &["random"]),
+                package("rand", "0.6.2", false, "Random number generators", &["random"]),
+            ],
+            vec![package("serde", "1.0.0", false, "A serialization framework", &[])],
+            vec![package("getrandom", "0.2.0", false, "Get randomness from the OS", &["rng"])],
+            vec![package("fastrand", "1.0.0", false, "A simple and fast random number generator", &[])],
+        ]
+        .iter()
+        .filter_map(|packages| CrateSummary::from_packages(packages))
+        .collect()
+    }
+
+    #[test]
+    fn test_search_max_version_excludes_yanked() {
+        let output = search_summaries(&summaries(), "rand", None);
+        assert_eq!(output.crates[0].name, "rand");
+        assert_eq!(output.crates[0].max_version, "0.6.2");
+    }
+
+    #[test]
+    fn test_search_matches_name_description_and_keywords() {
+        let output = search_summaries(&summaries(), "random", None);
+        let names: Vec<&str> = output.crates.iter().map(|c| &c.name[..]).collect();
+        assert_eq!(names, vec!["fastrand", "getrandom", "rand"]);
+
+        let output = search_summaries(&summaries(), "rng", None);
+        let names: Vec<&str> = output.crates.iter().map(|c| &c.name[..]).collect();
+        assert_eq!(names, vec!["getrandom"]);
+    }
+
+    #[test]
+    fn test_search_per_page_and_total() {
+        let output = search_summaries(&summaries(), "", Some(2));
+        assert_eq!(output.crates.len(), 2);
+        assert_eq!(output.meta.total, 4);
+    }
+}
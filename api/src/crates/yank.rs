@@ -0,0 +1,33 @@
+use api_types::auth::Identity;
+
+use crate::error::ApiError;
+use crate::owners;
+use crate::result::ApiResult;
+use crate::state::AppState;
+use crate::summaries;
+
+pub async fn set_yanked(
+    state: &AppState,
+    identity: &Identity,
+    name: &str,
+    version: &str,
+    yanked: bool,
+) -> ApiResult<()> {
+    let package = state
+        .packages
+        .get_package(name, version)
+        .await?
+        .filter(|package| !package.deleted)
+        .ok_or_else(|| ApiError::NotFound(format!("crate {} version {} not found", name, version)))?;
+
+    owners::check_owner_or_admin(state, identity, name).await?;
+
+    if package.yanked == yanked {
+        log::info!("{} {} already has yanked={}", name, version, yanked);
+        return Ok(());
+    }
+
+    log::info!("setting yanked={} on {} {} for {}", yanked, name, version, identity.principal_id);
+    state.packages.set_yanked(name, version, yanked).await?;
+    summaries::refresh_summary(state, name).await
+}
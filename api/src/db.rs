@@ -0,0 +1,91 @@
+use rusoto_dynamodb::AttributeValue;
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::ApiError;
+use crate::result::ApiResult;
+
+pub type Item = HashMap<String, AttributeValue>;
+
+pub fn unix_timestamp() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+pub fn string_attr_value<S: Into<String>>(s: S) -> AttributeValue {
+    AttributeValue {
+        s: Some(s.into()),
+        ..Default::default()
+    }
+}
+
+pub fn maybe_string_attr_value<S: Into<String>>(s: Option<S>) -> AttributeValue {
+    match s {
+        Some(s) => string_attr_value(s),
+        None => null_attr_value(),
+    }
+}
+
+pub fn null_attr_value() -> AttributeValue {
+    AttributeValue {
+        null: Some(true),
+        ..Default::default()
+    }
+}
+
+pub fn long_attr_value(v: i64) -> AttributeValue {
+    AttributeValue {
+        n: Some(v.to_string()),
+        ..Default::default()
+    }
+}
+
+pub fn bool_attr_value(v: bool) -> AttributeValue {
+    AttributeValue {
+        bool: Some(v),
+        ..Default::default()
+    }
+}
+
+pub fn string_list_attr_value(v: &[String]) -> AttributeValue {
+    AttributeValue {
+        l: Some(v.iter().map(|s| string_attr_value(s.clone())).collect()),
+        ..Default::default()
+    }
+}
+
+pub fn get_string(item: &Item, key: &str) -> ApiResult<String> {
+    get_maybe_string(item, key)?
+        .ok_or_else(|| ApiError::Database(format!("missing attribute {}", key)))
+}
+
+pub fn get_maybe_string(item: &Item, key: &str) -> ApiResult<Option<String>> {
+    Ok(item.get(key).and_then(|attr| attr.s.clone()))
+}
+
+pub fn get_long(item: &Item, key: &str) -> ApiResult<i64> {
+    get_maybe_long(item, key)?
+        .ok_or_else(|| ApiError::Database(format!("missing attribute {}", key)))
+}
+
+pub fn get_maybe_long(item: &Item, key: &str) -> ApiResult<Option<i64>> {
+    item.get(key)
+        .and_then(|attr| attr.n.as_ref())
+        .map(|n| n.parse::<i64>())
+        .transpose()
+        .map_err(|e| ApiError::Database(format!("invalid attribute {}: {:?}", key, e)))
+}
+
+pub fn get_bool(item: &Item, key: &str) -> ApiResult<bool> {
+    Ok(item.get(key).and_then(|attr| attr.bool).unwrap_or_default())
+}
+
+pub fn get_string_list(item: &Item, key: &str) -> ApiResult<Vec<String>> {
+    Ok(item
+        .get(key)
+        .and_then(|attr| attr.l.as_ref())
+        .map(|l| l.iter().filter_map(|attr| attr.s.clone()).collect())
+        .unwrap_or_default())
+}
@@ -1,15 +1,51 @@
+use lambda_http::http::StatusCode;
 use std::fmt;
 use std::error;
 
 #[derive(Debug, Clone)]
 pub enum ApiError {
     NotAuthorized(String),
+    Forbidden(String),
+    NotFound(String),
+    Conflict(String),
     Other(String),
     SerializationError(String),
     Database(String),
+    Storage(String),
     InvalidInput(String),
 }
 
+impl ApiError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::NotAuthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::InvalidInput(_) => StatusCode::BAD_REQUEST,
+            ApiError::Other(_)
+            | ApiError::SerializationError(_)
+            | ApiError::Database(_)
+            | ApiError::Storage(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    // The message cargo shows the user. Server errors only say what went wrong in the logs.
+    pub fn detail(&self) -> String {
+        match self {
+            ApiError::NotAuthorized(_) => "unauthorized".to_string(),
+            ApiError::Forbidden(s) => format!("forbidden: {}", s),
+            ApiError::NotFound(s) => s.clone(),
+            ApiError::Conflict(s) => s.clone(),
+            ApiError::InvalidInput(s) => s.clone(),
+            ApiError::Other(_)
+            | ApiError::SerializationError(_)
+            | ApiError::Database(_)
+            | ApiError::Storage(_) => "internal server error".to_string(),
+        }
+    }
+}
+
 impl error::Error for ApiError {}
 
 impl fmt::Display for ApiError {
@@ -22,4 +58,4 @@ impl From<&str> for ApiError {
     fn from(s: &str) -> Self {
         ApiError::Other(s.to_owned())
     }
-}
\ No newline at end of file
+}
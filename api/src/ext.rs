@@ -2,11 +2,15 @@ use std::collections::HashMap;
 
 use crate::error::ApiError;
 use crate::result::ApiResult;
+use crate::state::AppState;
+use api_types::auth::{AuthMethod, Identity};
+use api_types::tokens::{TokenPermissions, TokenScope};
 use aws_lambda_events::apigw;
 use lambda_http::{Request, RequestExt};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashSet;
+use std::sync::Arc;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Claims {
@@ -53,6 +57,18 @@ pub trait AuthContext {
     fn apigw_request_context(&self) -> ApiResult<apigw::ApiGatewayProxyRequestContext>;
 
     fn claims(&self) -> ApiResult<Claims>;
+
+    fn identity(&self) -> ApiResult<Identity>;
+
+    fn user_identity(&self) -> ApiResult<Identity>;
+
+    fn principal_id(&self) -> ApiResult<String>;
+
+    fn token_permissions(&self) -> ApiResult<TokenPermissions>;
+
+    fn require_permission(&self, scope: TokenScope, crate_name: Option<&str>) -> ApiResult<()>;
+
+    fn token_expires_at(&self) -> ApiResult<Option<i64>>;
 }
 
 impl AuthContext for Request {
@@ -61,7 +77,7 @@ impl AuthContext for Request {
         {
             Ok(context)
         } else {
-            Err(ApiError::Other(format!("no apigw context")))
+            Err(ApiError::Other("no apigw context".to_string()))
         }
     }
 
@@ -70,9 +86,78 @@ impl AuthContext for Request {
             .apigw_request_context()?
             .authorizer
             .remove("claims")
-            .ok_or_else(|| ApiError::Other(format!("missing claims")))?;
+            .ok_or_else(|| ApiError::Other("missing claims".to_string()))?;
 
         serde_json::from_value(claims)
-            .map_err(|_e| ApiError::Other(format!("deserialisation error")))
+            .map_err(|_e| ApiError::Other("deserialisation error".to_string()))
+    }
+
+    // The custom authorizer puts the identity straight into the authorizer context,
+    // whereas the cognito authorizer only gives us the token claims.
+    fn identity(&self) -> ApiResult<Identity> {
+        let authorizer = self.apigw_request_context()?.authorizer;
+
+        match Identity::from_context(&authorizer) {
+            Ok(Some(identity)) => Ok(identity),
+            Err(e) => {
+                log::warn!("bad authorizer context: {}", e);
+                Err(ApiError::NotAuthorized("no scopes".to_string()))
+            }
+            Ok(None) => self
+                .claims()
+                .map(|claims| Identity::new(claims.principal_id_ref(), AuthMethod::BearerToken))
+                .map_err(|_e| ApiError::NotAuthorized("no principal".to_string())),
+        }
+    }
+
+    // Signed in users only, api keys and ci tokens can't manage tokens.
+    fn user_identity(&self) -> ApiResult<Identity> {
+        let identity = self.identity()?;
+
+        if identity.auth_method == AuthMethod::BearerToken {
+            Ok(identity)
+        } else {
+            Err(ApiError::Forbidden(format!(
+                "{} can't be used here",
+                identity.auth_method
+            )))
+        }
+    }
+
+    fn principal_id(&self) -> ApiResult<String> {
+        Ok(self.identity()?.principal_id)
+    }
+
+    // Users signed in with a bearer token can do anything their account can.
+    fn token_permissions(&self) -> ApiResult<TokenPermissions> {
+        Ok(self.identity()?.permissions)
+    }
+
+    fn require_permission(&self, scope: TokenScope, crate_name: Option<&str>) -> ApiResult<()> {
+        if self.token_permissions()?.allows(scope, crate_name) {
+            Ok(())
+        } else {
+            Err(ApiError::Forbidden(match crate_name {
+                Some(name) => format!("token does not have the {} scope for {}", scope, name),
+                None => format!("token does not have the {} scope", scope),
+            }))
+        }
+    }
+
+    fn token_expires_at(&self) -> ApiResult<Option<i64>> {
+        Ok(self.identity()?.expires_at)
+    }
+}
+
+pub trait StateContext {
+    fn state(&self) -> ApiResult<Arc<AppState>>;
+}
+
+impl StateContext for Request {
+    fn state(&self) -> ApiResult<Arc<AppState>> {
+        self.extensions()
+            .get::<Arc<AppState>>()
+            .cloned()
+            .ok_or_else(|| ApiError::Other("no app state".to_string()))
     }
 }
@@ -0,0 +1,556 @@
+use lambda_http::{http, Body, Request, RequestExt, Response};
+use serde::Serialize;
+use std::sync::Arc;
+
+use api_types::owners::{AddOwnerInput, RemoveOwnerInput};
+use api_types::tokens::{CreateTokenInput, TokenScope};
+use api_types::trusted_publishing::{ExchangeTokenInput, GitHubTrustPolicy};
+use api_types::yank::{UnYankCrateOutput, YankCrateOutput};
+
+use crate::crates::create;
+use crate::crates::download::{self, Download};
+use crate::crates::owners;
+use crate::crates::search;
+use crate::crates::yank;
+use crate::db::unix_timestamp;
+use crate::error::ApiError;
+use crate::ext::*;
+use crate::index;
+use crate::response::*;
+use crate::result::ApiResult;
+use crate::state::AppState;
+use crate::tokens;
+use crate::trusted_publishing;
+use crate::ApiFuture;
+use crate::{get_json_body, get_method, get_path};
+
+// Runs a request through the router with the app state attached,
+// turning errors into responses. The caller has already authenticated it.
+pub async fn handle(state: Arc<AppState>, mut request: Request) -> Response<Body> {
+    let warning_days = state.config.token_expiry_warning_days;
+    let expiry_warning = request
+        .token_expires_at()
+        .unwrap_or_default()
+        .and_then(|expires_at| tokens::expiry_warning(expires_at, unix_timestamp(), warning_days))
+        .and_then(|warning| http::HeaderValue::from_str(&warning).ok());
+
+    request.extensions_mut().insert(state);
+
+    let mut response = api_handler(request).await.unwrap_or_else(|err| {
+        log::error!("error: {:?}", err);
+        error_response(&err)
+    });
+
+    if let Some(warning) = expiry_warning {
+        response.headers_mut().insert(http::header::WARNING, warning);
+    }
+
+    response
+}
+
+pub async fn api_handler(req: Request) -> ApiResult<Response<Body>> {
+    log::info!("{:?} {:?}", req.method(), req.uri(),);
+    log::debug!("{:?}", req.lambda_context_ref());
+    log::debug!("{:?}", req.query_string_parameters());
+    log::debug!("{:?}", req.headers());
+
+    let router = router!(
+        GET / => get_root,
+        GET /api/token => get_token,
+        POST /api/token => create_token,
+        GET /api/tokens => list_tokens,
+        DELETE /api/tokens/{token_id: String} => revoke_token,
+        PUT /api/v1/crates/new => new_crate,
+        GET /api/v1/crates => search_crates,
+        GET /api/v1/crates/{crate_name: String}/{version: String}/download => download_crate,
+        DELETE /api/v1/crates/{crate_name: String}/{version: String}/yank => yank_crate,
+        PUT /api/v1/crates/{crate_name: String}/{version: String}/unyank => unyank_crate,
+        GET /api/v1/crates/{crate_name: String}/owners => get_crate_owners,
+        PUT /api/v1/crates/{crate_name: String}/owners => add_crate_owners,
+        DELETE /api/v1/crates/{crate_name: String}/owners => remove_crate_owners,
+        GET /api/v1/crates/{crate_name: String}/trusted_publishers => get_trust_policies,
+        PUT /api/v1/crates/{crate_name: String}/trusted_publishers => add_trust_policy,
+        DELETE /api/v1/crates/{crate_name: String}/trusted_publishers/{id: String} => remove_trust_policy,
+        POST /api/v1/trusted_publishing/tokens => exchange_trusted_publishing_token,
+        GET /index/{file: String} => get_index_config,
+        GET /index/{a: String}/{name: String} => get_index_file_2,
+        GET /index/{a: String}/{b: String}/{name: String} => get_index_file_3,
+        _ => not_found,
+    );
+
+    let method = get_method(&req)?;
+    let path = get_path(&req)?;
+
+    router(&req, method, &path).await
+}
+
+pub fn get_root<'a>(_req: &'a Request) -> ApiFuture<'a> {
+    Box::pin(async { not_implemented().await })
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct GetTokenResponse {
+    pub token: Option<String>,
+}
+
+pub fn get_token<'a>(req: &'a Request) -> ApiFuture<'a> {
+    Box::pin(async move {
+        let state = req.state()?;
+        let identity = req.user_identity()?;
+
+        let token = tokens::get_or_create_token(&state, &identity.principal_id, &identity.permissions).await?;
+
+        Ok(json_response(http::StatusCode::OK, GetTokenResponse { token }))
+    })
+}
+
+pub fn create_token<'a>(req: &'a Request) -> ApiFuture<'a> {
+    Box::pin(async move {
+        let state = req.state()?;
+        let identity = req.user_identity()?;
+        let input: CreateTokenInput = if req.body().as_ref().is_empty() {
+            CreateTokenInput::default()
+        } else {
+            get_json_body(req)?
+        };
+        let name = input.name.as_deref().unwrap_or(tokens::DEFAULT_TOKEN_NAME);
+
+        // issuers can be limited to some scopes, and so are the tokens their users make
+        let mut permissions = input.permissions();
+        permissions.scopes.retain(|scope| identity.permissions.scopes.contains(scope));
+        if permissions.scopes.is_empty() {
+            return Err(ApiError::Forbidden("none of the requested scopes are allowed".to_string()));
+        }
+
+        let output = tokens::create_user_token(
+            &state,
+            &identity.principal_id,
+            name,
+            permissions,
+            input.expires_in,
+        )
+        .await?;
+        Ok(json_response(http::StatusCode::CREATED, output))
+    })
+}
+
+pub fn list_tokens<'a>(req: &'a Request) -> ApiFuture<'a> {
+    Box::pin(async move {
+        let state = req.state()?;
+        let principal_id = req.user_identity()?.principal_id;
+
+        let output = tokens::list_tokens(&state, &principal_id).await?;
+        Ok(json_response(http::StatusCode::OK, output))
+    })
+}
+
+pub fn revoke_token<'a>(req: &'a Request, token_id: String) -> ApiFuture<'a> {
+    Box::pin(async move {
+        let state = req.state()?;
+        let principal_id = req.user_identity()?.principal_id;
+
+        let output = tokens::revoke_token(&state, &principal_id, &token_id).await?;
+        Ok(json_response(http::StatusCode::OK, output))
+    })
+}
+
+pub fn new_crate<'a>(req: &'a Request) -> ApiFuture<'a> {
+    Box::pin(async move {
+        let state = req.state()?;
+        let identity = req.identity()?;
+        log::info!(
+            "publish by {} with {} {:?}",
+            identity.principal_id,
+            identity.auth_method,
+            identity.token_id
+        );
+
+        let output = create::create_crate(
+            &state,
+            &identity.principal_id,
+            &identity.permissions,
+            req.body().as_ref(),
+        )
+        .await?;
+        Ok(json_response(http::StatusCode::OK, output))
+    })
+}
+
+pub fn search_crates<'a>(req: &'a Request) -> ApiFuture<'a> {
+    Box::pin(async move {
+        let state = req.state()?;
+        req.principal_id()?;
+        req.require_permission(TokenScope::Read, None)?;
+
+        let params = req.query_string_parameters();
+        let query = params.first("q").unwrap_or_default();
+        let per_page = params
+            .first("per_page")
+            .map(|s| s.parse::<usize>())
+            .transpose()
+            .map_err(|e| ApiError::InvalidInput(format!("invalid per_page: {}", e)))?;
+
+        let output = search::search_crates(&state, query, per_page).await?;
+        Ok(json_response(http::StatusCode::OK, output))
+    })
+}
+
+pub fn download_crate<'a>(req: &'a Request, crate_name: String, version: String) -> ApiFuture<'a> {
+    Box::pin(async move {
+        let state = req.state()?;
+        let principal_id = req.principal_id()?;
+        req.require_permission(TokenScope::Read, Some(&crate_name))?;
+        log::info!("download {} {} for {}", crate_name, version, principal_id);
+
+        Ok(match download::download_crate(&state, &crate_name, &version).await? {
+            Download::Redirect(url) => redirect_response(&url),
+            Download::Content(data) => {
+                binary_response(http::StatusCode::OK, APPLICATION_OCTET_STREAM, data)
+            }
+        })
+    })
+}
+
+pub fn yank_crate<'a>(req: &'a Request, crate_name: String, version: String) -> ApiFuture<'a> {
+    Box::pin(async move {
+        let state = req.state()?;
+        let identity = req.identity()?;
+        req.require_permission(TokenScope::Yank, Some(&crate_name))?;
+
+        yank::set_yanked(&state, &identity, &crate_name, &version, true).await?;
+        Ok(json_response(http::StatusCode::OK, YankCrateOutput { ok: true }))
+    })
+}
+
+pub fn unyank_crate<'a>(req: &'a Request, crate_name: String, version: String) -> ApiFuture<'a> {
+    Box::pin(async move {
+        let state = req.state()?;
+        let identity = req.identity()?;
+        req.require_permission(TokenScope::Yank, Some(&crate_name))?;
+
+        yank::set_yanked(&state, &identity, &crate_name, &version, false).await?;
+        Ok(json_response(http::StatusCode::OK, UnYankCrateOutput { ok: true }))
+    })
+}
+
+pub fn get_crate_owners<'a>(req: &'a Request, crate_name: String) -> ApiFuture<'a> {
+    Box::pin(async move {
+        let state = req.state()?;
+        req.principal_id()?;
+        req.require_permission(TokenScope::Read, Some(&crate_name))?;
+
+        let output = owners::get_owners(&state, &crate_name).await?;
+        Ok(json_response(http::StatusCode::OK, output))
+    })
+}
+
+pub fn add_crate_owners<'a>(req: &'a Request, crate_name: String) -> ApiFuture<'a> {
+    Box::pin(async move {
+        let state = req.state()?;
+        let identity = req.identity()?;
+        req.require_permission(TokenScope::Owners, Some(&crate_name))?;
+        let input: AddOwnerInput = get_json_body(req)?;
+
+        let output = owners::add_owners(&state, &identity, &crate_name, &input).await?;
+        Ok(json_response(http::StatusCode::OK, output))
+    })
+}
+
+pub fn remove_crate_owners<'a>(req: &'a Request, crate_name: String) -> ApiFuture<'a> {
+    Box::pin(async move {
+        let state = req.state()?;
+        let identity = req.identity()?;
+        req.require_permission(TokenScope::Owners, Some(&crate_name))?;
+        let input: RemoveOwnerInput = get_json_body(req)?;
+
+        let output = owners::remove_owners(&state, &identity, &crate_name, &input).await?;
+        Ok(json_response(http::StatusCode::OK, output))
+    })
+}
+
+pub fn get_trust_policies<'a>(req: &'a Request, crate_name: String) -> ApiFuture<'a> {
+    Box::pin(async move {
+        let state = req.state()?;
+        req.principal_id()?;
+        req.require_permission(TokenScope::Read, Some(&crate_name))?;
+
+        let output = trusted_publishing::list_trust_policies(&state, &crate_name).await?;
+        Ok(json_response(http::StatusCode::OK, output))
+    })
+}
+
+pub fn add_trust_policy<'a>(req: &'a Request, crate_name: String) -> ApiFuture<'a> {
+    Box::pin(async move {
+        let state = req.state()?;
+        let identity = req.identity()?;
+        req.require_permission(TokenScope::Owners, Some(&crate_name))?;
+        let input: GitHubTrustPolicy = get_json_body(req)?;
+
+        let output =
+            trusted_publishing::add_trust_policy(&state, &identity, &crate_name, input).await?;
+        Ok(json_response(http::StatusCode::OK, output))
+    })
+}
+
+pub fn remove_trust_policy<'a>(req: &'a Request, crate_name: String, id: String) -> ApiFuture<'a> {
+    Box::pin(async move {
+        let state = req.state()?;
+        let identity = req.identity()?;
+        req.require_permission(TokenScope::Owners, Some(&crate_name))?;
+
+        let output =
+            trusted_publishing::remove_trust_policy(&state, &identity, &crate_name, &id).await?;
+        Ok(json_response(http::StatusCode::OK, output))
+    })
+}
+
+// Anonymous, the github oidc token in the body is the credential.
+pub fn exchange_trusted_publishing_token<'a>(req: &'a Request) -> ApiFuture<'a> {
+    Box::pin(async move {
+        let state = req.state()?;
+        let input: ExchangeTokenInput = get_json_body(req)?;
+
+        let output = trusted_publishing::exchange_token(&state, &input).await?;
+        Ok(json_response(http::StatusCode::CREATED, output))
+    })
+}
+
+// config.json is the only file at the top level of the index
+pub fn get_index_config<'a>(req: &'a Request, file: String) -> ApiFuture<'a> {
+    Box::pin(async move {
+        let state = req.state()?;
+        if index::auth_required(&state) {
+            req.principal_id()?;
+            req.require_permission(TokenScope::Read, None)?;
+        }
+
+        if file != index::CONFIG_FILE {
+            return Err(ApiError::NotFound(format!("index file {} not found", file)));
+        }
+
+        Ok(index::get_index_config(&state)?.into_response(req.headers()))
+    })
+}
+
+// 1/a and 2/ab
+pub fn get_index_file_2<'a>(req: &'a Request, a: String, name: String) -> ApiFuture<'a> {
+    Box::pin(async move { get_index_file(req, &format!("{}/{}", a, name)).await })
+}
+
+// 3/a/abc and ab/cd/abcd
+pub fn get_index_file_3<'a>(req: &'a Request, a: String, b: String, name: String) -> ApiFuture<'a> {
+    Box::pin(async move { get_index_file(req, &format!("{}/{}/{}", a, b, name)).await })
+}
+
+async fn get_index_file(req: &Request, path: &str) -> ApiResult<Response<Body>> {
+    let state = req.state()?;
+    if index::auth_required(&state) {
+        req.principal_id()?;
+        req.require_permission(TokenScope::Read, path.rsplit('/').next())?;
+    }
+
+    Ok(index::get_index_file(&state, path).await?.into_response(req.headers()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::Config;
+    use api_types::auth::{AuthMethod, Identity, Role};
+    use api_types::create::CreateCrateOutput;
+    use api_types::tokens::{CreateTokenOutput, ListTokensOutput};
+    use aws_lambda_events::apigw::ApiGatewayProxyRequestContext;
+    use lambda_http::request::RequestContext;
+
+    fn request(state: &Arc<AppState>, identity: &Identity, body: impl Into<Body>) -> Request {
+        let authorizer = serde_json::from_value(identity.to_context()).expect("context");
+        let mut req = http::Request::builder()
+            .body(body.into())
+            .expect("request")
+            .with_request_context(RequestContext::ApiGatewayV1(ApiGatewayProxyRequestContext {
+                authorizer,
+                ..Default::default()
+            }));
+        req.extensions_mut().insert(state.clone());
+        req
+    }
+
+    fn json_body<T: serde::de::DeserializeOwned>(response: &Response<Body>) -> T {
+        serde_json::from_slice(response.body().as_ref()).expect("json body")
+    }
+
+    // What cargo publish sends: the metadata json and the .crate file, each length prefixed.
+    fn publish_body(metadata: &serde_json::Value, tarball: &[u8]) -> Vec<u8> {
+        let metadata = serde_json::to_vec(metadata).expect("to_vec");
+        let mut body = vec![];
+        body.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+        body.extend_from_slice(&metadata);
+        body.extend_from_slice(&(tarball.len() as u32).to_le_bytes());
+        body.extend_from_slice(tarball);
+        body
+    }
+
+    #[tokio::test]
+    async fn parse_create_crate_input() {
+        let state = Arc::new(AppState::in_memory(Config::default()));
+        let user = Identity::new("user", AuthMethod::ApiKey);
+        let metadata = serde_json::json!({
+            "name": "foo",
+            "vers": "0.1.0",
+            "deps": [{
+                "name": "rand",
+                "version_req": "^0.6",
+                "features": ["i128_support"],
+                "optional": false,
+                "default_features": true,
+                "target": null,
+                "kind": "normal",
+                "registry": null,
+                "explicit_name_in_toml": null
+            }],
+            "features": { "extras": ["rand/simd_support"] },
+            "authors": ["Alice <a@example.com>"],
+            "description": "A nice description.",
+            "documentation": null,
+            "homepage": null,
+            "readme": null,
+            "readme_file": null,
+            "keywords": ["foo"],
+            "categories": ["not-a-category"],
+            "license": "MIT",
+            "license_file": null,
+            "repository": null,
+            "badges": {},
+            "links": null
+        });
+
+        let response = new_crate(&request(&state, &user, publish_body(&metadata, b"tarball")))
+            .await
+            .expect("publish");
+        assert_eq!(response.status(), http::StatusCode::OK);
+        let output: CreateCrateOutput = json_body(&response);
+        assert_eq!(output.warnings.invalid_categories, vec!["not-a-category".to_string()]);
+
+        let package = state
+            .packages
+            .get_package("foo", "0.1.0")
+            .await
+            .expect("get")
+            .expect("package");
+        assert_eq!(package.deps.len(), 1);
+        assert_eq!(package.deps[0].name, "rand");
+        assert_eq!(package.deps[0].req, "^0.6");
+        assert_eq!(package.cksum, create::checksum(b"tarball"));
+        assert_eq!(package.published_by.as_deref(), Some("user"));
+        assert!(!package.indexed);
+
+        let err = new_crate(&request(&state, &user, "not a crate"))
+            .await
+            .expect_err("truncated");
+        assert_eq!(err.status(), http::StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_token_handlers() {
+        let state = Arc::new(AppState::in_memory(Config::default()));
+        let user = Identity::new("user", AuthMethod::BearerToken);
+
+        let response = create_token(&request(&state, &user, r#"{"name": "ci"}"#))
+            .await
+            .expect("create");
+        assert_eq!(response.status(), http::StatusCode::CREATED);
+        let created: CreateTokenOutput = json_body(&response);
+        assert_eq!(created.name, "ci");
+
+        let response = list_tokens(&request(&state, &user, "")).await.expect("list");
+        let listed: ListTokensOutput = json_body(&response);
+        assert_eq!(listed.tokens.len(), 1);
+        assert_eq!(listed.tokens[0].id, created.id);
+
+        revoke_token(&request(&state, &user, ""), created.id.clone())
+            .await
+            .expect("revoke");
+        let err = revoke_token(&request(&state, &user, ""), created.id)
+            .await
+            .expect_err("already revoked");
+        assert_eq!(err.status(), http::StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_ci_tokens_cannot_create_tokens() {
+        let state = Arc::new(AppState::in_memory(Config::default()));
+        let mut ci = Identity::new("github:octo-org/octo-repo", AuthMethod::CiToken);
+        ci.permissions.scopes = vec![TokenScope::Publish, TokenScope::Yank];
+
+        let err = create_token(&request(&state, &ci, ""))
+            .await
+            .expect_err("ci token");
+        assert_eq!(err.status(), http::StatusCode::FORBIDDEN);
+        assert!(state
+            .tokens
+            .get_tokens("github:octo-org/octo-repo")
+            .await
+            .expect("get")
+            .is_empty());
+
+        // people signing in to an issuer limited to some scopes only get those
+        let mut user = Identity::new("user", AuthMethod::BearerToken);
+        user.permissions.scopes = vec![TokenScope::Publish, TokenScope::Yank];
+        let response = create_token(&request(&state, &user, ""))
+            .await
+            .expect("create");
+        let created: CreateTokenOutput = json_body(&response);
+        assert_eq!(created.scopes, vec![TokenScope::Publish, TokenScope::Yank]);
+
+        let err = create_token(&request(&state, &user, r#"{"scopes": ["owners"]}"#))
+            .await
+            .expect_err("owners scope");
+        assert_eq!(err.status(), http::StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_readers_default_token_can_only_read() {
+        let state = Arc::new(AppState::in_memory(Config::default()));
+        let mut reader = Identity::new("reader", AuthMethod::BearerToken);
+        reader.roles = vec![Role::Reader];
+        reader.permissions.scopes = Role::Reader.scopes().to_vec();
+
+        let response = get_token(&request(&state, &reader, "")).await.expect("get token");
+        assert_eq!(response.status(), http::StatusCode::OK);
+
+        let stored = state.tokens.get_tokens("reader").await.expect("get");
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].name, tokens::DEFAULT_TOKEN_NAME);
+        assert_eq!(stored[0].permissions.scopes, vec![TokenScope::Read]);
+    }
+
+    #[tokio::test]
+    async fn test_api_keys_cannot_manage_tokens() {
+        let state = Arc::new(AppState::in_memory(Config::default()));
+        let api_key = Identity::new("user", AuthMethod::ApiKey);
+
+        let err = create_token(&request(&state, &api_key, ""))
+            .await
+            .expect_err("api key");
+        assert_eq!(err.status(), http::StatusCode::FORBIDDEN);
+        assert!(state.tokens.get_tokens("user").await.expect("get").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_scopes_are_checked() {
+        let state = Arc::new(AppState::in_memory(Config::default()));
+        let mut identity = Identity::new("user", AuthMethod::ApiKey);
+        identity.permissions.scopes = vec![TokenScope::Publish];
+
+        let err = search_crates(&request(&state, &identity, ""))
+            .await
+            .expect_err("no read scope");
+        assert_eq!(err.status(), http::StatusCode::FORBIDDEN);
+
+        identity.permissions.scopes = vec![TokenScope::Read];
+        let response = search_crates(&request(&state, &identity, ""))
+            .await
+            .expect("search");
+        assert_eq!(response.status(), http::StatusCode::OK);
+    }
+}
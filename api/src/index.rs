@@ -0,0 +1,273 @@
+use api_types::index::{canonical_crate_name, index_file_contents, index_path, IndexConfig};
+use lambda_http::http::header::{
+    HeaderMap, HeaderValue, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+};
+use lambda_http::http::StatusCode;
+use lambda_http::{Body, Response};
+use sha2::{Digest, Sha256};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::crates::create::validate_crate_name;
+use crate::error::ApiError;
+use crate::packages::Package;
+use crate::response::{not_modified_response, text_response, APPLICATION_JSON, TEXT_PLAIN};
+use crate::result::ApiResult;
+use crate::state::AppState;
+
+pub const CONFIG_FILE: &str = "config.json";
+
+// A file in the sparse index along with the validators cargo
+// sends back in If-None-Match and If-Modified-Since.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndexFile {
+    pub content_type: &'static str,
+    pub contents: String,
+    pub etag: String,
+    pub last_modified: Option<SystemTime>,
+}
+
+impl IndexFile {
+    pub fn new(
+        content_type: &'static str,
+        contents: String,
+        last_modified: Option<SystemTime>,
+    ) -> Self {
+        let etag = format!("\"{}\"", hex::encode(Sha256::digest(contents.as_bytes())));
+
+        IndexFile {
+            content_type,
+            contents,
+            etag,
+            last_modified,
+        }
+    }
+
+    pub fn is_fresh(&self, headers: &HeaderMap) -> bool {
+        // If-None-Match wins when both are present
+        if let Some(if_none_match) = headers.get(IF_NONE_MATCH) {
+            return if_none_match
+                .to_str()
+                .map(|v| {
+                    v.split(',')
+                        .map(|tag| tag.trim().trim_start_matches("W/"))
+                        .any(|tag| tag == "*" || tag == self.etag)
+                })
+                .unwrap_or(false);
+        }
+
+        let since = headers
+            .get(IF_MODIFIED_SINCE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| httpdate::parse_http_date(v).ok());
+
+        match (since, self.last_modified) {
+            (Some(since), Some(last_modified)) => last_modified <= since,
+            _ => false,
+        }
+    }
+
+    pub fn into_response(self, headers: &HeaderMap) -> Response<Body> {
+        let mut response = if self.is_fresh(headers) {
+            not_modified_response()
+        } else {
+            text_response(StatusCode::OK, self.content_type, self.contents)
+        };
+
+        if let Ok(etag) = HeaderValue::from_str(&self.etag) {
+            response.headers_mut().insert(ETAG, etag);
+        }
+        if let Some(last_modified) = self.last_modified {
+            if let Ok(last_modified) =
+                HeaderValue::from_str(&httpdate::fmt_http_date(last_modified))
+            {
+                response.headers_mut().insert(LAST_MODIFIED, last_modified);
+            }
+        }
+
+        response
+    }
+}
+
+pub fn auth_required(state: &AppState) -> bool {
+    state.config.index_auth_required
+}
+
+pub fn get_index_config(state: &AppState) -> ApiResult<IndexFile> {
+    let config = IndexConfig::new(&state.config.api_url, auth_required(state));
+    let contents = serde_json::to_string(&config)
+        .map_err(|e| ApiError::SerializationError(format!("index config: {:?}", e)))?;
+
+    Ok(IndexFile::new(APPLICATION_JSON, contents, None))
+}
+
+// Builds a crate's index file from its versions in the packages table,
+// so new versions show up without waiting for the indexer.
+pub fn sparse_index_file(packages: &[Package]) -> ApiResult<Option<IndexFile>> {
+    let mut packages: Vec<&Package> = packages.iter().filter(|p| !p.deleted).collect();
+    if packages.is_empty() {
+        return Ok(None);
+    }
+    packages.sort_by(|a, b| (a.published_at, &a.version).cmp(&(b.published_at, &b.version)));
+
+    let entries: Vec<_> = packages.iter().map(|p| p.index_entry()).collect();
+    let contents = index_file_contents(&entries)
+        .map_err(|e| ApiError::SerializationError(format!("index file: {:?}", e)))?;
+
+    let last_modified = packages
+        .iter()
+        .filter_map(|p| p.updated_at.or(p.published_at))
+        .max()
+        .map(|t| UNIX_EPOCH + Duration::from_secs(t as u64));
+
+    Ok(Some(IndexFile::new(TEXT_PLAIN, contents, last_modified)))
+}
+
+// `path` is relative to the index root, eg. `se/rd/serde`.
+pub async fn get_index_file(state: &AppState, path: &str) -> ApiResult<IndexFile> {
+    let not_found = || ApiError::NotFound(format!("index file {} not found", path));
+
+    let name = path.rsplit('/').next().unwrap_or_default();
+    validate_crate_name(name).map_err(|_| not_found())?;
+    if index_path(name) != path {
+        return Err(not_found());
+    }
+
+    // paths are lowercase, so look the crate up by its canonical name,
+    // then leave out crates that only differ in `-` vs `_` and so have another path
+    let mut packages: Vec<Package> = state
+        .packages
+        .get_packages_by_canonical_name(&canonical_crate_name(name))
+        .await?
+        .into_iter()
+        .filter(|p| p.name.to_lowercase() == name)
+        .collect();
+    // versions stored before canonical names were
+    if packages.is_empty() {
+        packages = state.packages.get_packages(name).await?;
+    }
+
+    // names differing only in case were published before that was checked,
+    // the file is the first one's like in the git index
+    if let Some(first) = packages
+        .iter()
+        .min_by_key(|p| p.published_at)
+        .map(|p| p.name.clone())
+    {
+        packages.retain(|p| p.name == first);
+    }
+
+    sparse_index_file(&packages)?.ok_or_else(not_found)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::Config;
+
+    fn package(version: &str, published_at: i64, deleted: bool) -> Package {
+        Package {
+            name: "foo".to_string(),
+            version: version.to_string(),
+            deps: vec![],
+            cksum: "cksum".to_string(),
+            features: Default::default(),
+            yanked: false,
+            links: None,
+            description: None,
+            keywords: vec![],
+            published_by: None,
+            published_at: Some(published_at),
+            updated_at: None,
+            indexed: true,
+            deleted,
+        }
+    }
+
+    #[test]
+    fn test_sparse_index_file() {
+        let packages = vec![
+            package("0.2.0", 200, false),
+            package("0.1.0", 100, false),
+            package("0.1.1", 150, true),
+        ];
+        let file = sparse_index_file(&packages)
+            .expect("index file")
+            .expect("some");
+        let versions: Vec<_> = file
+            .contents
+            .lines()
+            .map(|line| {
+                serde_json::from_str::<serde_json::Value>(line).expect("json")["vers"].clone()
+            })
+            .collect();
+        assert_eq!(versions, vec!["0.1.0", "0.2.0"]);
+        assert_eq!(
+            file.last_modified,
+            Some(UNIX_EPOCH + Duration::from_secs(200))
+        );
+
+        assert_eq!(
+            sparse_index_file(&[package("0.1.0", 100, true)]).expect("index file"),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_index_file_by_lowercase_path() {
+        let state = AppState::in_memory(Config::default());
+        let mut upper = package("0.1.0", 100, false);
+        upper.name = "Foo_Bar".to_string();
+        let mut hyphen = package("0.2.0", 200, false);
+        hyphen.name = "foo-bar".to_string();
+        for package in &[upper, hyphen] {
+            state.packages.put_new_package(package).await.expect("put");
+        }
+
+        let file = get_index_file(&state, "fo/o_/foo_bar")
+            .await
+            .expect("index file");
+        let entry: serde_json::Value =
+            serde_json::from_str(file.contents.lines().next().expect("line")).expect("json");
+        assert_eq!(entry["name"], "Foo_Bar");
+        assert_eq!(file.contents.lines().count(), 1);
+
+        let file = get_index_file(&state, "fo/o-/foo-bar")
+            .await
+            .expect("index file");
+        assert!(file.contents.contains("\"foo-bar\""));
+
+        assert!(get_index_file(&state, "fo/o_/Foo_Bar").await.is_err());
+        assert!(get_index_file(&state, "fo/o_/foo_baz").await.is_err());
+    }
+
+    #[test]
+    fn test_index_file_is_fresh() {
+        let file = IndexFile::new(
+            TEXT_PLAIN,
+            "contents".to_string(),
+            Some(UNIX_EPOCH + Duration::from_secs(1000)),
+        );
+
+        let mut headers = HeaderMap::new();
+        assert!(!file.is_fresh(&headers));
+
+        headers.insert(
+            IF_MODIFIED_SINCE,
+            HeaderValue::from_str(&httpdate::fmt_http_date(
+                UNIX_EPOCH + Duration::from_secs(1000),
+            ))
+            .unwrap(),
+        );
+        assert!(file.is_fresh(&headers));
+
+        headers.insert(IF_NONE_MATCH, HeaderValue::from_static("\"other\""));
+        assert!(!file.is_fresh(&headers));
+
+        headers.insert(IF_NONE_MATCH, HeaderValue::from_str(&file.etag).unwrap());
+        assert!(file.is_fresh(&headers));
+
+        let response = file.clone().into_response(&headers);
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers().get(ETAG).unwrap(), &file.etag[..]);
+    }
+}
@@ -1,16 +1,35 @@
+#[macro_use]
+extern crate http_router;
+
+use crate::error::ApiError;
 use crate::result::ApiResult;
-use lambda_http::{http, Request, RequestExt, Response};
+use lambda_http::{http, Body, Request, RequestExt, Response};
+use serde::de::DeserializeOwned;
 use serde_json;
 use std::future::Future;
 use std::pin::Pin;
 
+pub mod config;
+pub mod crates;
+pub mod db;
 pub mod error;
 pub mod ext;
+pub mod handlers;
+pub mod index;
+pub mod owners;
+pub mod packages;
 pub mod response;
 pub mod result;
+pub mod secrets;
+pub mod state;
+pub mod storage;
+pub mod summaries;
 pub mod tokens;
+pub mod trusted_publishing;
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
 
-pub type ApiFuture<'a> = Pin<Box<dyn Future<Output = ApiResult<Response<String>>> + Send + 'a>>;
+pub type ApiFuture<'a> = Pin<Box<dyn Future<Output = ApiResult<Response<Body>>> + Send + 'a>>;
 
 pub struct User {
     pub id: String,
@@ -38,3 +57,8 @@ pub fn get_method(req: &Request) -> ApiResult<http_router::Method> {
 pub fn get_path(req: &Request) -> ApiResult<&str> {
     Ok(req.raw_http_path())
 }
+
+pub fn get_json_body<T: DeserializeOwned>(req: &Request) -> ApiResult<T> {
+    serde_json::from_slice(req.body().as_ref())
+        .map_err(|e| ApiError::InvalidInput(format!("invalid json body: {}", e)))
+}
@@ -0,0 +1,688 @@
+use api_types::auth::{Identity, Role};
+use api_types::index::canonical_crate_name;
+use api_types::trusted_publishing::{GitHubTrustPolicy, TrustPolicyInfo};
+use maplit::hashmap;
+use rusoto_core::RusotoError;
+use rusoto_dynamodb::{
+    DeleteItemError, DeleteItemInput, DynamoDb, DynamoDbClient, GetItemInput, PutItemError,
+    PutItemInput, QueryInput,
+};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use crate::db::*;
+use crate::error::ApiError;
+use crate::result::ApiResult;
+use crate::state::AppState;
+use crate::BoxFuture;
+
+// Trust policies share the owners table, under sort keys no principal id has.
+pub const TRUST_POLICY_PREFIX: &str = "trust-policy:";
+
+// As does the claim on a crate's canonical name.
+pub const NAME_CLAIM_ID: &str = "name-claim:";
+
+// Tokens from trusted publishing belong to the policy rather than a user,
+// eg. trusted-publisher:0123456789abcdef
+pub const TRUSTED_PUBLISHER_PREFIX: &str = "trusted-publisher:";
+
+pub fn is_reserved_user_id(user_id: &str) -> bool {
+    user_id.starts_with(TRUST_POLICY_PREFIX)
+        || user_id.starts_with(NAME_CLAIM_ID)
+        || user_id.starts_with(TRUSTED_PUBLISHER_PREFIX)
+}
+
+// One item per owner of a crate, keyed by crate name and the owner's principal id.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Owner {
+    pub name: String,
+    pub user_id: String,
+    pub added_by: Option<String>,
+    pub added_at: Option<i64>,
+}
+
+impl Owner {
+    pub fn new(name: &str, user_id: &str, added_by: &str) -> Self {
+        Owner {
+            name: name.to_owned(),
+            user_id: user_id.to_owned(),
+            added_by: Some(added_by.to_owned()),
+            added_at: Some(unix_timestamp()),
+        }
+    }
+
+    pub fn key(name: &str, user_id: &str) -> Item {
+        hashmap! {
+            "name".to_string() => string_attr_value(name),
+            "user_id".to_string() => string_attr_value(user_id),
+        }
+    }
+
+    pub fn to_item(&self) -> Item {
+        let mut item = Self::key(&self.name, &self.user_id);
+        item.insert("added_by".to_string(), maybe_string_attr_value(self.added_by.clone()));
+        if let Some(added_at) = self.added_at {
+            item.insert("added_at".to_string(), long_attr_value(added_at));
+        }
+        item
+    }
+
+    pub fn from_item(item: &Item) -> ApiResult<Owner> {
+        Ok(Owner {
+            name: get_string(item, "name")?,
+            user_id: get_string(item, "user_id")?,
+            added_by: get_maybe_string(item, "added_by")?,
+            added_at: get_maybe_long(item, "added_at")?,
+        })
+    }
+}
+
+// A github actions trusted publisher of a crate, see `trusted_publishing`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrustPolicy {
+    pub name: String,
+    pub id: String,
+    pub policy: GitHubTrustPolicy,
+    pub added_by: Option<String>,
+    pub added_at: Option<i64>,
+}
+
+impl TrustPolicy {
+    // The id is a hash of the policy so adding the same one twice is a no-op.
+    pub fn new(name: &str, policy: GitHubTrustPolicy, added_by: &str) -> Self {
+        let mut hasher = Sha256::new();
+        for field in &[
+            Some(policy.repository.to_ascii_lowercase()),
+            Some(policy.repository_id.clone()),
+            Some(policy.repository_owner_id.clone()),
+            Some(policy.workflow.clone()),
+            policy.environment.as_ref().map(|s| s.to_ascii_lowercase()),
+            policy.git_ref.clone(),
+        ] {
+            let field = field.as_deref().unwrap_or_default();
+            hasher.update(&(field.len() as u32).to_le_bytes());
+            hasher.update(field.as_bytes());
+        }
+        let id = hex::encode(&hasher.finalize()[..8]);
+
+        TrustPolicy {
+            name: name.to_owned(),
+            id,
+            policy,
+            added_by: Some(added_by.to_owned()),
+            added_at: Some(unix_timestamp()),
+        }
+    }
+
+    pub fn key(name: &str, id: &str) -> Item {
+        Owner::key(name, &format!("{}{}", TRUST_POLICY_PREFIX, id))
+    }
+
+    pub fn to_item(&self) -> Item {
+        let mut item = Self::key(&self.name, &self.id);
+        item.insert("repository".to_string(), string_attr_value(self.policy.repository.clone()));
+        item.insert("repository_id".to_string(), string_attr_value(self.policy.repository_id.clone()));
+        item.insert("repository_owner_id".to_string(), string_attr_value(self.policy.repository_owner_id.clone()));
+        item.insert("workflow".to_string(), string_attr_value(self.policy.workflow.clone()));
+        item.insert("environment".to_string(), maybe_string_attr_value(self.policy.environment.clone()));
+        item.insert("ref".to_string(), maybe_string_attr_value(self.policy.git_ref.clone()));
+        item.insert("added_by".to_string(), maybe_string_attr_value(self.added_by.clone()));
+        if let Some(added_at) = self.added_at {
+            item.insert("added_at".to_string(), long_attr_value(added_at));
+        }
+        item
+    }
+
+    pub fn from_item(item: &Item) -> ApiResult<TrustPolicy> {
+        let user_id = get_string(item, "user_id")?;
+
+        Ok(TrustPolicy {
+            name: get_string(item, "name")?,
+            id: user_id.trim_start_matches(TRUST_POLICY_PREFIX).to_owned(),
+            policy: GitHubTrustPolicy {
+                repository: get_string(item, "repository")?,
+                repository_id: get_string(item, "repository_id")?,
+                repository_owner_id: get_string(item, "repository_owner_id")?,
+                workflow: get_string(item, "workflow")?,
+                environment: get_maybe_string(item, "environment")?,
+                git_ref: get_maybe_string(item, "ref")?,
+            },
+            added_by: get_maybe_string(item, "added_by")?,
+            added_at: get_maybe_long(item, "added_at")?,
+        })
+    }
+
+    pub fn info(&self) -> TrustPolicyInfo {
+        TrustPolicyInfo {
+            id: self.id.clone(),
+            policy: self.policy.clone(),
+            added_by: self.added_by.clone(),
+            added_at: self.added_at,
+        }
+    }
+}
+
+// Made by the first publish of a crate, keyed by its canonical name so that
+// no other crate can be published under a name cargo would confuse with it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NameClaim {
+    pub canonical_name: String,
+    // The crate's name as published.
+    pub name: String,
+    pub claimed_by: String,
+    pub claimed_at: i64,
+}
+
+impl NameClaim {
+    pub fn new(name: &str, claimed_by: &str) -> Self {
+        NameClaim {
+            canonical_name: canonical_crate_name(name),
+            name: name.to_owned(),
+            claimed_by: claimed_by.to_owned(),
+            claimed_at: unix_timestamp(),
+        }
+    }
+
+    pub fn key(canonical_name: &str) -> Item {
+        Owner::key(canonical_name, NAME_CLAIM_ID)
+    }
+
+    pub fn to_item(&self) -> Item {
+        let mut item = Self::key(&self.canonical_name);
+        item.insert("crate_name".to_string(), string_attr_value(self.name.clone()));
+        item.insert("claimed_by".to_string(), string_attr_value(self.claimed_by.clone()));
+        item.insert("claimed_at".to_string(), long_attr_value(self.claimed_at));
+        item
+    }
+
+    pub fn from_item(item: &Item) -> ApiResult<NameClaim> {
+        Ok(NameClaim {
+            canonical_name: get_string(item, "name")?,
+            name: get_string(item, "crate_name")?,
+            claimed_by: get_string(item, "claimed_by")?,
+            claimed_at: get_long(item, "claimed_at")?,
+        })
+    }
+}
+
+pub trait OwnerRepository: Send + Sync {
+    fn get_owners<'a>(&'a self, name: &'a str) -> BoxFuture<'a, ApiResult<Vec<Owner>>>;
+
+    // Returns false if the user was already an owner.
+    fn put_owner<'a>(&'a self, owner: &'a Owner) -> BoxFuture<'a, ApiResult<bool>>;
+
+    fn delete_owner<'a>(&'a self, name: &'a str, user_id: &'a str) -> BoxFuture<'a, ApiResult<()>>;
+
+    fn get_trust_policies<'a>(&'a self, name: &'a str) -> BoxFuture<'a, ApiResult<Vec<TrustPolicy>>>;
+
+    // Returns false if the crate already had the policy.
+    fn put_trust_policy<'a>(&'a self, policy: &'a TrustPolicy) -> BoxFuture<'a, ApiResult<bool>>;
+
+    // NotFound if the crate has no such policy.
+    fn delete_trust_policy<'a>(&'a self, name: &'a str, id: &'a str) -> BoxFuture<'a, ApiResult<()>>;
+
+    fn get_name_claim<'a>(&'a self, canonical_name: &'a str) -> BoxFuture<'a, ApiResult<Option<NameClaim>>>;
+
+    // Returns false if the canonical name was already claimed.
+    fn put_name_claim<'a>(&'a self, claim: &'a NameClaim) -> BoxFuture<'a, ApiResult<bool>>;
+}
+
+pub struct DynamoDbOwners {
+    client: DynamoDbClient,
+    table: String,
+}
+
+impl DynamoDbOwners {
+    pub fn new(client: DynamoDbClient, table: &str) -> Self {
+        DynamoDbOwners {
+            client,
+            table: table.to_owned(),
+        }
+    }
+}
+
+impl OwnerRepository for DynamoDbOwners {
+    fn get_owners<'a>(&'a self, name: &'a str) -> BoxFuture<'a, ApiResult<Vec<Owner>>> {
+        Box::pin(async move {
+            let mut owners = vec![];
+            let mut exclusive_start_key = None;
+
+            loop {
+                let output = self
+                    .client
+                    .query(QueryInput {
+                        key_condition_expression: Some("#N = :name".to_string()),
+                        expression_attribute_names: Some(hashmap! {
+                            "#N".to_string() => "name".to_string(),
+                        }),
+                        expression_attribute_values: Some(hashmap! {
+                            ":name".to_string() => string_attr_value(name),
+                        }),
+                        exclusive_start_key,
+                        table_name: self.table.clone(),
+                        ..Default::default()
+                    })
+                    .await
+                    .map_err(|err| {
+                        log::error!("query owners error for {}: {:?}", name, err);
+                        ApiError::Database("error fetching owners".to_string())
+                    })?;
+
+                for item in output.items.unwrap_or_default().iter() {
+                    let owner = Owner::from_item(item)?;
+                    if !is_reserved_user_id(&owner.user_id) {
+                        owners.push(owner);
+                    }
+                }
+
+                exclusive_start_key = output.last_evaluated_key;
+                if exclusive_start_key.is_none() {
+                    break;
+                }
+            }
+
+            Ok(owners)
+        })
+    }
+
+    fn put_owner<'a>(&'a self, owner: &'a Owner) -> BoxFuture<'a, ApiResult<bool>> {
+        Box::pin(async move {
+            let result = self
+                .client
+                .put_item(PutItemInput {
+                    item: owner.to_item(),
+                    condition_expression: Some("attribute_not_exists(user_id)".to_string()),
+                    table_name: self.table.clone(),
+                    ..Default::default()
+                })
+                .await;
+
+            match result {
+                Ok(_) => Ok(true),
+                Err(RusotoError::Service(PutItemError::ConditionalCheckFailed(_))) => Ok(false),
+                Err(err) => {
+                    log::error!("put owner error for {} {}: {:?}", owner.name, owner.user_id, err);
+                    Err(ApiError::Database("error saving owner".to_string()))
+                }
+            }
+        })
+    }
+
+    fn delete_owner<'a>(&'a self, name: &'a str, user_id: &'a str) -> BoxFuture<'a, ApiResult<()>> {
+        Box::pin(async move {
+            self.client
+                .delete_item(DeleteItemInput {
+                    key: Owner::key(name, user_id),
+                    table_name: self.table.clone(),
+                    ..Default::default()
+                })
+                .await
+                .map_err(|err| {
+                    log::error!("delete owner error for {} {}: {:?}", name, user_id, err);
+                    ApiError::Database("error deleting owner".to_string())
+                })?;
+
+            Ok(())
+        })
+    }
+
+    fn get_trust_policies<'a>(&'a self, name: &'a str) -> BoxFuture<'a, ApiResult<Vec<TrustPolicy>>> {
+        Box::pin(async move {
+            let mut policies = vec![];
+            let mut exclusive_start_key = None;
+
+            loop {
+                let output = self
+                    .client
+                    .query(QueryInput {
+                        key_condition_expression: Some(
+                            "#N = :name AND begins_with(user_id, :prefix)".to_string(),
+                        ),
+                        expression_attribute_names: Some(hashmap! {
+                            "#N".to_string() => "name".to_string(),
+                        }),
+                        expression_attribute_values: Some(hashmap! {
+                            ":name".to_string() => string_attr_value(name),
+                            ":prefix".to_string() => string_attr_value(TRUST_POLICY_PREFIX),
+                        }),
+                        exclusive_start_key,
+                        table_name: self.table.clone(),
+                        ..Default::default()
+                    })
+                    .await
+                    .map_err(|err| {
+                        log::error!("query trust policies error for {}: {:?}", name, err);
+                        ApiError::Database("error fetching trust policies".to_string())
+                    })?;
+
+                for item in output.items.unwrap_or_default().iter() {
+                    policies.push(TrustPolicy::from_item(item)?);
+                }
+
+                exclusive_start_key = output.last_evaluated_key;
+                if exclusive_start_key.is_none() {
+                    break;
+                }
+            }
+
+            Ok(policies)
+        })
+    }
+
+    fn put_trust_policy<'a>(&'a self, policy: &'a TrustPolicy) -> BoxFuture<'a, ApiResult<bool>> {
+        Box::pin(async move {
+            let result = self
+                .client
+                .put_item(PutItemInput {
+                    item: policy.to_item(),
+                    condition_expression: Some("attribute_not_exists(user_id)".to_string()),
+                    table_name: self.table.clone(),
+                    ..Default::default()
+                })
+                .await;
+
+            match result {
+                Ok(_) => Ok(true),
+                Err(RusotoError::Service(PutItemError::ConditionalCheckFailed(_))) => Ok(false),
+                Err(err) => {
+                    log::error!("put trust policy error for {} {}: {:?}", policy.name, policy.id, err);
+                    Err(ApiError::Database("error saving trust policy".to_string()))
+                }
+            }
+        })
+    }
+
+    fn delete_trust_policy<'a>(&'a self, name: &'a str, id: &'a str) -> BoxFuture<'a, ApiResult<()>> {
+        Box::pin(async move {
+            self.client
+                .delete_item(DeleteItemInput {
+                    key: TrustPolicy::key(name, id),
+                    condition_expression: Some("attribute_exists(user_id)".to_string()),
+                    table_name: self.table.clone(),
+                    ..Default::default()
+                })
+                .await
+                .map_err(|err| match err {
+                    RusotoError::Service(DeleteItemError::ConditionalCheckFailed(_)) => {
+                        ApiError::NotFound(format!("trust policy {} not found", id))
+                    }
+                    err => {
+                        log::error!("delete trust policy error for {} {}: {:?}", name, id, err);
+                        ApiError::Database("error deleting trust policy".to_string())
+                    }
+                })?;
+
+            Ok(())
+        })
+    }
+
+    fn get_name_claim<'a>(&'a self, canonical_name: &'a str) -> BoxFuture<'a, ApiResult<Option<NameClaim>>> {
+        Box::pin(async move {
+            let output = self
+                .client
+                .get_item(GetItemInput {
+                    key: NameClaim::key(canonical_name),
+                    consistent_read: Some(true),
+                    table_name: self.table.clone(),
+                    ..Default::default()
+                })
+                .await
+                .map_err(|err| {
+                    log::error!("get name claim error for {}: {:?}", canonical_name, err);
+                    ApiError::Database("error fetching name claim".to_string())
+                })?;
+
+            output.item.as_ref().map(NameClaim::from_item).transpose()
+        })
+    }
+
+    fn put_name_claim<'a>(&'a self, claim: &'a NameClaim) -> BoxFuture<'a, ApiResult<bool>> {
+        Box::pin(async move {
+            let result = self
+                .client
+                .put_item(PutItemInput {
+                    item: claim.to_item(),
+                    condition_expression: Some("attribute_not_exists(user_id)".to_string()),
+                    table_name: self.table.clone(),
+                    ..Default::default()
+                })
+                .await;
+
+            match result {
+                Ok(_) => Ok(true),
+                Err(RusotoError::Service(PutItemError::ConditionalCheckFailed(_))) => Ok(false),
+                Err(err) => {
+                    log::error!("put name claim error for {}: {:?}", claim.canonical_name, err);
+                    Err(ApiError::Database("error saving name claim".to_string()))
+                }
+            }
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryOwners {
+    owners: Mutex<BTreeMap<(String, String), Owner>>,
+    trust_policies: Mutex<BTreeMap<(String, String), TrustPolicy>>,
+    name_claims: Mutex<BTreeMap<String, NameClaim>>,
+}
+
+impl OwnerRepository for MemoryOwners {
+    fn get_owners<'a>(&'a self, name: &'a str) -> BoxFuture<'a, ApiResult<Vec<Owner>>> {
+        Box::pin(async move {
+            let owners = self.owners.lock().unwrap();
+            Ok(owners.values().filter(|o| o.name == name).cloned().collect())
+        })
+    }
+
+    fn put_owner<'a>(&'a self, owner: &'a Owner) -> BoxFuture<'a, ApiResult<bool>> {
+        Box::pin(async move {
+            let mut owners = self.owners.lock().unwrap();
+            let key = (owner.name.clone(), owner.user_id.clone());
+            if owners.contains_key(&key) {
+                return Ok(false);
+            }
+            owners.insert(key, owner.clone());
+            Ok(true)
+        })
+    }
+
+    fn delete_owner<'a>(&'a self, name: &'a str, user_id: &'a str) -> BoxFuture<'a, ApiResult<()>> {
+        Box::pin(async move {
+            self.owners.lock().unwrap().remove(&(name.to_owned(), user_id.to_owned()));
+            Ok(())
+        })
+    }
+
+    fn get_trust_policies<'a>(&'a self, name: &'a str) -> BoxFuture<'a, ApiResult<Vec<TrustPolicy>>> {
+        Box::pin(async move {
+            let policies = self.trust_policies.lock().unwrap();
+            Ok(policies.values().filter(|p| p.name == name).cloned().collect())
+        })
+    }
+
+    fn put_trust_policy<'a>(&'a self, policy: &'a TrustPolicy) -> BoxFuture<'a, ApiResult<bool>> {
+        Box::pin(async move {
+            let mut policies = self.trust_policies.lock().unwrap();
+            let key = (policy.name.clone(), policy.id.clone());
+            if policies.contains_key(&key) {
+                return Ok(false);
+            }
+            policies.insert(key, policy.clone());
+            Ok(true)
+        })
+    }
+
+    fn delete_trust_policy<'a>(&'a self, name: &'a str, id: &'a str) -> BoxFuture<'a, ApiResult<()>> {
+        Box::pin(async move {
+            self.trust_policies
+                .lock()
+                .unwrap()
+                .remove(&(name.to_owned(), id.to_owned()))
+                .map(|_| ())
+                .ok_or_else(|| ApiError::NotFound(format!("trust policy {} not found", id)))
+        })
+    }
+
+    fn get_name_claim<'a>(&'a self, canonical_name: &'a str) -> BoxFuture<'a, ApiResult<Option<NameClaim>>> {
+        Box::pin(async move { Ok(self.name_claims.lock().unwrap().get(canonical_name).cloned()) })
+    }
+
+    fn put_name_claim<'a>(&'a self, claim: &'a NameClaim) -> BoxFuture<'a, ApiResult<bool>> {
+        Box::pin(async move {
+            let mut claims = self.name_claims.lock().unwrap();
+            if claims.contains_key(&claim.canonical_name) {
+                return Ok(false);
+            }
+            claims.insert(claim.canonical_name.clone(), claim.clone());
+            Ok(true)
+        })
+    }
+}
+
+// Crates published before owners were tracked have no owner items,
+// so their owners are taken to be everyone who has published a version.
+pub async fn get_owner_ids(state: &AppState, name: &str) -> ApiResult<Vec<String>> {
+    let owners = state.owners.get_owners(name).await?;
+
+    if !owners.is_empty() {
+        return Ok(owners.into_iter().map(|owner| owner.user_id).collect());
+    }
+
+    let mut publishers: Vec<String> = state
+        .packages
+        .get_packages(name)
+        .await?
+        .into_iter()
+        .filter_map(|package| package.published_by)
+        .collect();
+    publishers.sort();
+    publishers.dedup();
+    Ok(publishers)
+}
+
+// Returns all the owner ids if the principal is one of them.
+pub async fn check_owner(state: &AppState, principal_id: &str, name: &str) -> ApiResult<Vec<String>> {
+    let owner_ids = get_owner_ids(state, name).await?;
+
+    if owner_ids.iter().any(|id| id == principal_id) {
+        Ok(owner_ids)
+    } else {
+        Err(ApiError::Forbidden(format!("not an owner of crate {}", name)))
+    }
+}
+
+// Admins can yank and manage the owners of crates they don't own.
+// Returns all the owner ids like check_owner.
+pub async fn check_owner_or_admin(state: &AppState, identity: &Identity, name: &str) -> ApiResult<Vec<String>> {
+    if !identity.has_role(Role::Admin) {
+        return check_owner(state, &identity.principal_id, name).await;
+    }
+
+    let owner_ids = get_owner_ids(state, name).await?;
+    if owner_ids.is_empty() {
+        return Err(ApiError::NotFound(format!("crate {} not found", name)));
+    }
+    if !owner_ids.contains(&identity.principal_id) {
+        log::info!("admin {} acting on crate {}", identity.principal_id, name);
+    }
+    Ok(owner_ids)
+}
+
+// Called before publishing. Returns true if the crate is new, in which case
+// the publisher has to claim it with `claim_new_crate` once the version is stored.
+// After that only its owners may publish.
+pub async fn check_publisher(state: &AppState, principal_id: &str, name: &str) -> ApiResult<bool> {
+    if let Some(policy_id) = principal_id.strip_prefix(TRUSTED_PUBLISHER_PREFIX) {
+        check_trusted_publisher(state, policy_id, name).await?;
+        return Ok(false);
+    }
+
+    let owner_ids = get_owner_ids(state, name).await?;
+
+    if owner_ids.iter().any(|id| id == principal_id) {
+        return Ok(false);
+    }
+    if !owner_ids.is_empty() {
+        return Err(ApiError::Forbidden(format!("not an owner of crate {}", name)));
+    }
+
+    let canonical_name = canonical_crate_name(name);
+    // the claimant can retry a publish that got as far as claiming the name
+    if let Some(claim) = state.owners.get_name_claim(&canonical_name).await? {
+        if claim.name != name || claim.claimed_by != principal_id {
+            return Err(name_taken(name, &claim));
+        }
+    }
+    // crates published before names were claimed
+    if let Some(package) = state
+        .packages
+        .get_packages_by_canonical_name(&canonical_name)
+        .await?
+        .into_iter()
+        .find(|package| package.name != name)
+    {
+        return Err(ApiError::Conflict(format!(
+            "crate name {} is taken by crate {}",
+            name, package.name
+        )));
+    }
+
+    Ok(true)
+}
+
+// Trusted publishing tokens can publish while their policy is still on the crate
+// and whoever added it is still an owner.
+pub async fn check_trusted_publisher(state: &AppState, policy_id: &str, name: &str) -> ApiResult<TrustPolicy> {
+    let policy = state
+        .owners
+        .get_trust_policies(name)
+        .await?
+        .into_iter()
+        .find(|policy| policy.id == policy_id)
+        .ok_or_else(|| ApiError::Forbidden(format!("trust policy {} is not on crate {}", policy_id, name)))?;
+    check_policy_added_by_owner(state, &policy).await?;
+    Ok(policy)
+}
+
+pub async fn check_policy_added_by_owner(state: &AppState, policy: &TrustPolicy) -> ApiResult<()> {
+    let added_by = policy
+        .added_by
+        .as_deref()
+        .ok_or_else(|| ApiError::Forbidden(format!("trust policy {} has no owner", policy.id)))?;
+    check_owner(state, added_by, &policy.name).await?;
+    Ok(())
+}
+
+// Makes the publisher the first owner of a new crate. Only one of several concurrent
+// first publishes can win the conditional put, the others get an error and should back out.
+pub async fn claim_new_crate(state: &AppState, principal_id: &str, name: &str) -> ApiResult<()> {
+    let claim = NameClaim::new(name, principal_id);
+
+    if !state.owners.put_name_claim(&claim).await? {
+        let existing = state
+            .owners
+            .get_name_claim(&claim.canonical_name)
+            .await?
+            .ok_or_else(|| ApiError::Conflict(format!("crate name {} is being claimed", name)))?;
+        if existing.name != name || existing.claimed_by != principal_id {
+            return Err(name_taken(name, &existing));
+        }
+    }
+
+    log::info!("{} is the first owner of crate {}", principal_id, name);
+    state
+        .owners
+        .put_owner(&Owner::new(name, principal_id, principal_id))
+        .await?;
+    Ok(())
+}
+
+fn name_taken(name: &str, claim: &NameClaim) -> ApiError {
+    if claim.name != name {
+        ApiError::Conflict(format!("crate name {} is taken by crate {}", name, claim.name))
+    } else {
+        ApiError::Forbidden(format!("not an owner of crate {}", name))
+    }
+}
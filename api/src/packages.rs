@@ -0,0 +1,607 @@
+use api_types::create::{CreateCrateInput, FeaturesMap};
+use api_types::index::{canonical_crate_name, IndexDependency, IndexEntry};
+use maplit::hashmap;
+use rusoto_core::RusotoError;
+use rusoto_dynamodb::{
+    DeleteItemInput, DynamoDb, DynamoDbClient, GetItemInput, PutItemError, PutItemInput,
+    QueryInput, ScanInput, UpdateItemError, UpdateItemInput,
+};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use crate::db::*;
+use crate::error::ApiError;
+use crate::result::ApiResult;
+use crate::BoxFuture;
+
+// One item per published version, keyed by name and version.
+// Mirrors the fields of the cargo index entry plus the metadata
+// the api needs for search and ownership checks.
+// Items also have a canonical_name, see `canonical_crate_name`, for the name index,
+// and while they're waiting for the indexer an index_status, for its sparse pending index.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Package {
+    pub name: String,
+    pub version: String,
+    pub deps: Vec<IndexDependency>,
+    pub cksum: String,
+    pub features: FeaturesMap,
+    pub yanked: bool,
+    pub links: Option<String>,
+    pub description: Option<String>,
+    pub keywords: Vec<String>,
+    pub published_by: Option<String>,
+    pub published_at: Option<i64>,
+    pub updated_at: Option<i64>,
+    pub indexed: bool,
+    pub deleted: bool,
+}
+
+// The index_status of versions the indexer hasn't written to git yet.
+// The indexer removes the attribute once it has, so only pending versions are in the index on it.
+pub const PENDING_INDEX_STATUS: &str = "pending";
+
+impl Package {
+    pub fn new(input: &CreateCrateInput, cksum: &str, published_by: &str) -> Self {
+        let entry = IndexEntry::from_create_crate_input(input, cksum);
+
+        let now = unix_timestamp();
+
+        Package {
+            name: entry.name,
+            version: entry.vers,
+            deps: entry.deps,
+            cksum: entry.cksum,
+            features: entry.features,
+            yanked: entry.yanked,
+            links: entry.links,
+            description: input.description.clone(),
+            keywords: input.keywords.clone(),
+            published_by: Some(published_by.to_owned()),
+            published_at: Some(now),
+            updated_at: Some(now),
+            indexed: false,
+            deleted: false,
+        }
+    }
+
+    pub fn index_entry(&self) -> IndexEntry {
+        IndexEntry {
+            name: self.name.clone(),
+            vers: self.version.clone(),
+            deps: self.deps.clone(),
+            cksum: self.cksum.clone(),
+            features: self.features.clone(),
+            yanked: self.yanked,
+            links: self.links.clone(),
+        }
+    }
+
+    pub fn key(name: &str, version: &str) -> Item {
+        hashmap! {
+            "name".to_string() => string_attr_value(name),
+            "version".to_string() => string_attr_value(version),
+        }
+    }
+
+    pub fn to_item(&self) -> ApiResult<Item> {
+        let deps = serde_json::to_string(&self.deps)
+            .map_err(|e| ApiError::SerializationError(format!("deps: {:?}", e)))?;
+        let features = serde_json::to_string(&self.features)
+            .map_err(|e| ApiError::SerializationError(format!("features: {:?}", e)))?;
+
+        let mut item = Self::key(&self.name, &self.version);
+        item.insert("canonical_name".to_string(), string_attr_value(canonical_crate_name(&self.name)));
+        item.insert("deps".to_string(), string_attr_value(deps));
+        item.insert("cksum".to_string(), string_attr_value(self.cksum.clone()));
+        item.insert("features".to_string(), string_attr_value(features));
+        item.insert("yanked".to_string(), bool_attr_value(self.yanked));
+        item.insert("links".to_string(), maybe_string_attr_value(self.links.clone()));
+        item.insert("description".to_string(), maybe_string_attr_value(self.description.clone()));
+        item.insert("keywords".to_string(), string_list_attr_value(&self.keywords));
+        item.insert("published_by".to_string(), maybe_string_attr_value(self.published_by.clone()));
+        if let Some(published_at) = self.published_at {
+            item.insert("published_at".to_string(), long_attr_value(published_at));
+        }
+        if let Some(updated_at) = self.updated_at {
+            item.insert("updated_at".to_string(), long_attr_value(updated_at));
+        }
+        item.insert("indexed".to_string(), bool_attr_value(self.indexed));
+        if !self.indexed {
+            item.insert("index_status".to_string(), string_attr_value(PENDING_INDEX_STATUS));
+        }
+        item.insert("deleted".to_string(), bool_attr_value(self.deleted));
+        Ok(item)
+    }
+
+    pub fn from_item(item: &Item) -> ApiResult<Package> {
+        let deps = serde_json::from_str(&get_string(item, "deps")?)
+            .map_err(|e| ApiError::SerializationError(format!("deps: {:?}", e)))?;
+        let features = serde_json::from_str(&get_string(item, "features")?)
+            .map_err(|e| ApiError::SerializationError(format!("features: {:?}", e)))?;
+
+        Ok(Package {
+            name: get_string(item, "name")?,
+            version: get_string(item, "version")?,
+            deps,
+            cksum: get_string(item, "cksum")?,
+            features,
+            yanked: get_bool(item, "yanked")?,
+            links: get_maybe_string(item, "links")?,
+            description: get_maybe_string(item, "description")?,
+            keywords: get_string_list(item, "keywords")?,
+            published_by: get_maybe_string(item, "published_by")?,
+            published_at: get_maybe_long(item, "published_at")?,
+            updated_at: get_maybe_long(item, "updated_at")?,
+            indexed: get_bool(item, "indexed")?,
+            deleted: get_bool(item, "deleted")?,
+        })
+    }
+}
+
+pub trait PackageRepository: Send + Sync {
+    fn get_package<'a>(&'a self, name: &'a str, version: &'a str) -> BoxFuture<'a, ApiResult<Option<Package>>>;
+
+    // Fails if the version has been published before, even if it was since yanked.
+    fn put_new_package<'a>(&'a self, package: &'a Package) -> BoxFuture<'a, ApiResult<()>>;
+
+    fn get_packages<'a>(&'a self, name: &'a str) -> BoxFuture<'a, ApiResult<Vec<Package>>>;
+
+    // The versions of every crate whose name has the canonical name,
+    // which is more than one crate only for names published before they were checked.
+    fn get_packages_by_canonical_name<'a>(
+        &'a self,
+        canonical_name: &'a str,
+    ) -> BoxFuture<'a, ApiResult<Vec<Package>>>;
+
+    // Only for backing out a publish that lost the race to claim a new crate's name.
+    fn delete_package<'a>(&'a self, name: &'a str, version: &'a str) -> BoxFuture<'a, ApiResult<()>>;
+
+    fn scan_packages<'a>(&'a self) -> BoxFuture<'a, ApiResult<Vec<Package>>>;
+
+    // Clearing the indexed flag queues the version for the indexer,
+    // which rewrites the crate's git index file with the new yanked state.
+    fn set_yanked<'a>(&'a self, name: &'a str, version: &'a str, yanked: bool) -> BoxFuture<'a, ApiResult<()>>;
+}
+
+pub struct DynamoDbPackages {
+    client: DynamoDbClient,
+    table: String,
+    // On canonical_name, projecting all attributes.
+    name_index: Option<String>,
+}
+
+impl DynamoDbPackages {
+    pub fn new(client: DynamoDbClient, table: &str) -> Self {
+        DynamoDbPackages {
+            client,
+            table: table.to_owned(),
+            name_index: None,
+        }
+    }
+
+    pub fn with_name_index(mut self, index: &str) -> Self {
+        self.name_index = Some(index.to_owned());
+        self
+    }
+
+    // Versions published before canonical names were stored aren't in the name index
+    // until this has been run. Returns the number of items updated.
+    pub async fn set_missing_canonical_names(&self) -> ApiResult<usize> {
+        let mut updated = 0;
+        let mut exclusive_start_key = None;
+
+        loop {
+            let output = self
+                .client
+                .scan(ScanInput {
+                    filter_expression: Some("attribute_not_exists(canonical_name)".to_string()),
+                    projection_expression: Some("#N, version".to_string()),
+                    expression_attribute_names: Some(hashmap! {
+                        "#N".to_string() => "name".to_string(),
+                    }),
+                    exclusive_start_key,
+                    table_name: self.table.clone(),
+                    ..Default::default()
+                })
+                .await
+                .map_err(|err| {
+                    log::error!("scan packages error: {:?}", err);
+                    ApiError::Database("error fetching packages".to_string())
+                })?;
+
+            for item in output.items.unwrap_or_default().iter() {
+                let name = get_string(item, "name")?;
+                let version = get_string(item, "version")?;
+
+                self.client
+                    .update_item(UpdateItemInput {
+                        key: Package::key(&name, &version),
+                        update_expression: Some("SET canonical_name = :canonical_name".to_string()),
+                        expression_attribute_values: Some(hashmap! {
+                            ":canonical_name".to_string() => string_attr_value(canonical_crate_name(&name)),
+                        }),
+                        table_name: self.table.clone(),
+                        ..Default::default()
+                    })
+                    .await
+                    .map_err(|err| {
+                        log::error!("update package error for {} {}: {:?}", name, version, err);
+                        ApiError::Database("error updating package".to_string())
+                    })?;
+                updated += 1;
+            }
+
+            exclusive_start_key = output.last_evaluated_key;
+            if exclusive_start_key.is_none() {
+                break;
+            }
+        }
+
+        Ok(updated)
+    }
+
+    // Versions left unindexed from before the index_status was stored aren't in the indexer's
+    // pending index until this has been run. Returns the number of items updated.
+    pub async fn set_missing_index_statuses(&self) -> ApiResult<usize> {
+        let mut updated = 0;
+        let mut exclusive_start_key = None;
+
+        loop {
+            let output = self
+                .client
+                .scan(ScanInput {
+                    filter_expression: Some("indexed = :indexed AND attribute_not_exists(index_status)".to_string()),
+                    projection_expression: Some("#N, version".to_string()),
+                    expression_attribute_names: Some(hashmap! {
+                        "#N".to_string() => "name".to_string(),
+                    }),
+                    expression_attribute_values: Some(hashmap! {
+                        ":indexed".to_string() => bool_attr_value(false),
+                    }),
+                    exclusive_start_key,
+                    table_name: self.table.clone(),
+                    ..Default::default()
+                })
+                .await
+                .map_err(|err| {
+                    log::error!("scan packages error: {:?}", err);
+                    ApiError::Database("error fetching packages".to_string())
+                })?;
+
+            for item in output.items.unwrap_or_default().iter() {
+                let name = get_string(item, "name")?;
+                let version = get_string(item, "version")?;
+
+                // unless the indexer got to it in the meantime
+                let result = self
+                    .client
+                    .update_item(UpdateItemInput {
+                        key: Package::key(&name, &version),
+                        update_expression: Some("SET index_status = :index_status".to_string()),
+                        condition_expression: Some("indexed = :indexed".to_string()),
+                        expression_attribute_values: Some(hashmap! {
+                            ":index_status".to_string() => string_attr_value(PENDING_INDEX_STATUS),
+                            ":indexed".to_string() => bool_attr_value(false),
+                        }),
+                        table_name: self.table.clone(),
+                        ..Default::default()
+                    })
+                    .await;
+                match result {
+                    Ok(_) => updated += 1,
+                    Err(RusotoError::Service(UpdateItemError::ConditionalCheckFailed(_))) => {}
+                    Err(err) => {
+                        log::error!("update package error for {} {}: {:?}", name, version, err);
+                        return Err(ApiError::Database("error updating package".to_string()));
+                    }
+                }
+            }
+
+            exclusive_start_key = output.last_evaluated_key;
+            if exclusive_start_key.is_none() {
+                break;
+            }
+        }
+
+        Ok(updated)
+    }
+}
+
+impl PackageRepository for DynamoDbPackages {
+    fn get_package<'a>(&'a self, name: &'a str, version: &'a str) -> BoxFuture<'a, ApiResult<Option<Package>>> {
+        Box::pin(async move {
+            let output = self
+                .client
+                .get_item(GetItemInput {
+                    key: Package::key(name, version),
+                    table_name: self.table.clone(),
+                    ..Default::default()
+                })
+                .await
+                .map_err(|err| {
+                    log::error!("get package error for {} {}: {:?}", name, version, err);
+                    ApiError::Database("error fetching package".to_string())
+                })?;
+
+            output.item.as_ref().map(Package::from_item).transpose()
+        })
+    }
+
+    fn put_new_package<'a>(&'a self, package: &'a Package) -> BoxFuture<'a, ApiResult<()>> {
+        Box::pin(async move {
+            self.client
+                .put_item(PutItemInput {
+                    item: package.to_item()?,
+                    condition_expression: Some("attribute_not_exists(#V)".to_string()),
+                    expression_attribute_names: Some(hashmap! {
+                        "#V".to_string() => "version".to_string(),
+                    }),
+                    table_name: self.table.clone(),
+                    ..Default::default()
+                })
+                .await
+                .map_err(|err| match err {
+                    RusotoError::Service(PutItemError::ConditionalCheckFailed(_)) => ApiError::Conflict(
+                        format!("crate version {} {} already exists", package.name, package.version),
+                    ),
+                    err => {
+                        log::error!("put package error for {} {}: {:?}", package.name, package.version, err);
+                        ApiError::Database("error saving package".to_string())
+                    }
+                })?;
+
+            Ok(())
+        })
+    }
+
+    fn get_packages<'a>(&'a self, name: &'a str) -> BoxFuture<'a, ApiResult<Vec<Package>>> {
+        Box::pin(async move {
+            let mut packages = vec![];
+            let mut exclusive_start_key = None;
+
+            loop {
+                let output = self
+                    .client
+                    .query(QueryInput {
+                        key_condition_expression: Some("#N = :name".to_string()),
+                        expression_attribute_names: Some(hashmap! {
+                            "#N".to_string() => "name".to_string(),
+                        }),
+                        expression_attribute_values: Some(hashmap! {
+                            ":name".to_string() => string_attr_value(name),
+                        }),
+                        exclusive_start_key,
+                        table_name: self.table.clone(),
+                        ..Default::default()
+                    })
+                    .await
+                    .map_err(|err| {
+                        log::error!("query packages error for {}: {:?}", name, err);
+                        ApiError::Database("error fetching packages".to_string())
+                    })?;
+
+                for item in output.items.unwrap_or_default().iter() {
+                    packages.push(Package::from_item(item)?);
+                }
+
+                exclusive_start_key = output.last_evaluated_key;
+                if exclusive_start_key.is_none() {
+                    break;
+                }
+            }
+
+            Ok(packages)
+        })
+    }
+
+    fn get_packages_by_canonical_name<'a>(
+        &'a self,
+        canonical_name: &'a str,
+    ) -> BoxFuture<'a, ApiResult<Vec<Package>>> {
+        Box::pin(async move {
+            let index = self
+                .name_index
+                .as_ref()
+                .ok_or_else(|| ApiError::Other("no package name index configured".to_string()))?;
+
+            let mut packages = vec![];
+            let mut exclusive_start_key = None;
+
+            loop {
+                let output = self
+                    .client
+                    .query(QueryInput {
+                        key_condition_expression: Some("canonical_name = :canonical_name".to_string()),
+                        expression_attribute_values: Some(hashmap! {
+                            ":canonical_name".to_string() => string_attr_value(canonical_name),
+                        }),
+                        index_name: Some(index.clone()),
+                        exclusive_start_key,
+                        table_name: self.table.clone(),
+                        ..Default::default()
+                    })
+                    .await
+                    .map_err(|err| {
+                        log::error!("query package name index error for {}: {:?}", canonical_name, err);
+                        ApiError::Database("error fetching packages".to_string())
+                    })?;
+
+                for item in output.items.unwrap_or_default().iter() {
+                    packages.push(Package::from_item(item)?);
+                }
+
+                exclusive_start_key = output.last_evaluated_key;
+                if exclusive_start_key.is_none() {
+                    break;
+                }
+            }
+
+            Ok(packages)
+        })
+    }
+
+    fn delete_package<'a>(&'a self, name: &'a str, version: &'a str) -> BoxFuture<'a, ApiResult<()>> {
+        Box::pin(async move {
+            self.client
+                .delete_item(DeleteItemInput {
+                    key: Package::key(name, version),
+                    table_name: self.table.clone(),
+                    ..Default::default()
+                })
+                .await
+                .map_err(|err| {
+                    log::error!("delete package error for {} {}: {:?}", name, version, err);
+                    ApiError::Database("error deleting package".to_string())
+                })?;
+
+            Ok(())
+        })
+    }
+
+    fn scan_packages<'a>(&'a self) -> BoxFuture<'a, ApiResult<Vec<Package>>> {
+        Box::pin(async move {
+            let mut packages = vec![];
+            let mut exclusive_start_key = None;
+
+            loop {
+                let output = self
+                    .client
+                    .scan(ScanInput {
+                        exclusive_start_key,
+                        table_name: self.table.clone(),
+                        ..Default::default()
+                    })
+                    .await
+                    .map_err(|err| {
+                        log::error!("scan packages error: {:?}", err);
+                        ApiError::Database("error fetching packages".to_string())
+                    })?;
+
+                for item in output.items.unwrap_or_default().iter() {
+                    packages.push(Package::from_item(item)?);
+                }
+
+                exclusive_start_key = output.last_evaluated_key;
+                if exclusive_start_key.is_none() {
+                    break;
+                }
+            }
+
+            Ok(packages)
+        })
+    }
+
+    fn set_yanked<'a>(&'a self, name: &'a str, version: &'a str, yanked: bool) -> BoxFuture<'a, ApiResult<()>> {
+        Box::pin(async move {
+            self.client
+                .update_item(UpdateItemInput {
+                    key: Package::key(name, version),
+                    update_expression: Some(
+                        "SET yanked = :yanked, indexed = :indexed, index_status = :index_status, \
+                         updated_at = :updated_at"
+                            .to_string(),
+                    ),
+                    condition_expression: Some("attribute_exists(#V)".to_string()),
+                    expression_attribute_names: Some(hashmap! {
+                        "#V".to_string() => "version".to_string(),
+                    }),
+                    expression_attribute_values: Some(hashmap! {
+                        ":yanked".to_string() => bool_attr_value(yanked),
+                        ":indexed".to_string() => bool_attr_value(false),
+                        ":index_status".to_string() => string_attr_value(PENDING_INDEX_STATUS),
+                        ":updated_at".to_string() => long_attr_value(unix_timestamp()),
+                    }),
+                    table_name: self.table.clone(),
+                    ..Default::default()
+                })
+                .await
+                .map_err(|err| match err {
+                    RusotoError::Service(UpdateItemError::ConditionalCheckFailed(_)) => {
+                        ApiError::NotFound(format!("crate {} version {} not found", name, version))
+                    }
+                    err => {
+                        log::error!("update package error for {} {}: {:?}", name, version, err);
+                        ApiError::Database("error updating package".to_string())
+                    }
+                })?;
+
+            Ok(())
+        })
+    }
+}
+
+// Keeps packages in memory, for tests and running without dynamodb.
+#[derive(Default)]
+pub struct MemoryPackages {
+    packages: Mutex<BTreeMap<(String, String), Package>>,
+}
+
+impl PackageRepository for MemoryPackages {
+    fn get_package<'a>(&'a self, name: &'a str, version: &'a str) -> BoxFuture<'a, ApiResult<Option<Package>>> {
+        Box::pin(async move {
+            let packages = self.packages.lock().unwrap();
+            Ok(packages.get(&(name.to_owned(), version.to_owned())).cloned())
+        })
+    }
+
+    fn put_new_package<'a>(&'a self, package: &'a Package) -> BoxFuture<'a, ApiResult<()>> {
+        Box::pin(async move {
+            let mut packages = self.packages.lock().unwrap();
+            let key = (package.name.clone(), package.version.clone());
+            if packages.contains_key(&key) {
+                return Err(ApiError::Conflict(format!(
+                    "crate version {} {} already exists",
+                    package.name, package.version
+                )));
+            }
+            packages.insert(key, package.clone());
+            Ok(())
+        })
+    }
+
+    fn get_packages<'a>(&'a self, name: &'a str) -> BoxFuture<'a, ApiResult<Vec<Package>>> {
+        Box::pin(async move {
+            let packages = self.packages.lock().unwrap();
+            Ok(packages.values().filter(|p| p.name == name).cloned().collect())
+        })
+    }
+
+    fn get_packages_by_canonical_name<'a>(
+        &'a self,
+        canonical_name: &'a str,
+    ) -> BoxFuture<'a, ApiResult<Vec<Package>>> {
+        Box::pin(async move {
+            let packages = self.packages.lock().unwrap();
+            Ok(packages
+                .values()
+                .filter(|p| canonical_crate_name(&p.name) == canonical_name)
+                .cloned()
+                .collect())
+        })
+    }
+
+    fn delete_package<'a>(&'a self, name: &'a str, version: &'a str) -> BoxFuture<'a, ApiResult<()>> {
+        Box::pin(async move {
+            self.packages.lock().unwrap().remove(&(name.to_owned(), version.to_owned()));
+            Ok(())
+        })
+    }
+
+    fn scan_packages<'a>(&'a self) -> BoxFuture<'a, ApiResult<Vec<Package>>> {
+        Box::pin(async move { Ok(self.packages.lock().unwrap().values().cloned().collect()) })
+    }
+
+    fn set_yanked<'a>(&'a self, name: &'a str, version: &'a str, yanked: bool) -> BoxFuture<'a, ApiResult<()>> {
+        Box::pin(async move {
+            let mut packages = self.packages.lock().unwrap();
+            let package = packages
+                .get_mut(&(name.to_owned(), version.to_owned()))
+                .ok_or_else(|| ApiError::NotFound(format!("crate {} version {} not found", name, version)))?;
+            package.yanked = yanked;
+            package.indexed = false;
+            package.updated_at = Some(unix_timestamp());
+            Ok(())
+        })
+    }
+}
@@ -1,32 +1,39 @@
 use crate::ApiFuture;
+use api_types::error::{ErrorItem, ErrorOutput};
 use lambda_http::http;
-use lambda_http::{Request, Response};
+use lambda_http::{Body, Request, Response};
 use serde::Serialize;
 
+use crate::error::ApiError;
 use crate::ApiResult;
 
 pub const APPLICATION_JSON: &'static str = "application/json";
+pub const APPLICATION_OCTET_STREAM: &'static str = "application/octet-stream";
 pub const TEXT_PLAIN: &'static str = "text/plain";
 
 pub fn not_found<'a>(_req: &'a Request) -> ApiFuture<'a> {
-    Box::pin(async {
-        Ok(text_response(
-            http::StatusCode::NOT_FOUND,
-            TEXT_PLAIN,
-            "Not Found",
-        ))
-    })
+    Box::pin(async { Ok(error_response(&ApiError::NotFound("not found".to_string()))) })
+}
+
+pub async fn not_implemented() -> ApiResult<Response<Body>> {
+    Ok(error_response(&ApiError::Other(
+        "not implemented".to_string(),
+    )))
 }
 
-pub async fn not_implemented() -> ApiResult<Response<String>> {
-    Ok(text_response(
-        http::StatusCode::INTERNAL_SERVER_ERROR,
-        TEXT_PLAIN,
-        "Not Implemented",
-    ))
+// Cargo shows the details of each error in the body to the user.
+pub fn error_response(err: &ApiError) -> Response<Body> {
+    json_response(
+        err.status(),
+        ErrorOutput {
+            errors: vec![ErrorItem {
+                detail: err.detail(),
+            }],
+        },
+    )
 }
 
-pub fn json_response<T: Serialize>(status: http::StatusCode, body: T) -> Response<String> {
+pub fn json_response<T: Serialize>(status: http::StatusCode, body: T) -> Response<Body> {
     match serde_json::to_string(&body) {
         Ok(json_body) => text_response(status, APPLICATION_JSON, json_body),
         Err(json_err) => text_response(
@@ -41,11 +48,71 @@ pub fn text_response<S: Into<String>>(
     status: http::StatusCode,
     content_type: &str,
     body: S,
-) -> Response<String> {
+) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("content-type", content_type)
+        .body(Body::Text(body.into()))
+        .map_err(Box::new)
+        .expect("failed to render response")
+}
+
+pub fn binary_response(
+    status: http::StatusCode,
+    content_type: &str,
+    body: Vec<u8>,
+) -> Response<Body> {
     Response::builder()
         .status(status)
         .header("content-type", content_type)
-        .body(body.into())
+        .body(Body::Binary(body))
         .map_err(Box::new)
         .expect("failed to render response")
 }
+
+pub fn redirect_response(location: &str) -> Response<Body> {
+    Response::builder()
+        .status(http::StatusCode::FOUND)
+        .header("location", location)
+        .body(Body::Empty)
+        .map_err(Box::new)
+        .expect("failed to render response")
+}
+
+pub fn not_modified_response() -> Response<Body> {
+    Response::builder()
+        .status(http::StatusCode::NOT_MODIFIED)
+        .body(Body::Empty)
+        .map_err(Box::new)
+        .expect("failed to render response")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_error_response() {
+        let response = error_response(&ApiError::Conflict(
+            "crate version foo 0.1.0 already exists".to_string(),
+        ));
+        assert_eq!(response.status(), http::StatusCode::CONFLICT);
+        assert_eq!(response.headers()["content-type"], APPLICATION_JSON);
+        match response.body() {
+            Body::Text(body) => assert_eq!(
+                body,
+                r#"{"errors":[{"detail":"crate version foo 0.1.0 already exists"}]}"#
+            ),
+            _ => panic!("expected a text body"),
+        }
+
+        let response = error_response(&ApiError::Database("error fetching package".to_string()));
+        assert_eq!(response.status(), http::StatusCode::INTERNAL_SERVER_ERROR);
+        match response.body() {
+            Body::Text(body) => {
+                assert_eq!(body, r#"{"errors":[{"detail":"internal server error"}]}"#)
+            }
+            _ => panic!("expected a text body"),
+        }
+    }
+}
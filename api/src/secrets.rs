@@ -0,0 +1,115 @@
+use double_checked_cell_async::DoubleCheckedCell;
+use rusoto_core::Region;
+use rusoto_kms::{DecryptRequest, GenerateRandomRequest, Kms, KmsClient};
+
+use crate::error::ApiError;
+use crate::result::ApiResult;
+use crate::BoxFuture;
+
+// Where token secrets come from and the key they are hashed with.
+pub trait Secrets: Send + Sync {
+    fn random_bytes<'a>(&'a self, n: usize) -> BoxFuture<'a, ApiResult<Vec<u8>>>;
+
+    fn token_hash_key<'a>(&'a self) -> BoxFuture<'a, ApiResult<&'a [u8]>>;
+}
+
+pub struct KmsSecrets {
+    client: KmsClient,
+    // base64 kms ciphertext, decrypted the first time it's needed
+    token_hash_key: String,
+    token_hash_key_cell: DoubleCheckedCell<Vec<u8>>,
+}
+
+impl KmsSecrets {
+    pub fn new(token_hash_key: &str) -> Self {
+        KmsSecrets {
+            client: KmsClient::new(Region::default()),
+            token_hash_key: token_hash_key.to_owned(),
+            token_hash_key_cell: DoubleCheckedCell::new(),
+        }
+    }
+}
+
+impl Secrets for KmsSecrets {
+    fn random_bytes<'a>(&'a self, n: usize) -> BoxFuture<'a, ApiResult<Vec<u8>>> {
+        Box::pin(async move {
+            let output = self
+                .client
+                .generate_random(GenerateRandomRequest {
+                    number_of_bytes: Some(n as i64),
+                    ..Default::default()
+                })
+                .await
+                .map_err(|err| {
+                    log::error!("generate random error: {:?}", err);
+                    ApiError::Database("error generating token".to_string())
+                })?;
+
+            output.plaintext.map(|b| b.to_vec()).ok_or_else(|| {
+                ApiError::Database("error generating token: no token returned".to_string())
+            })
+        })
+    }
+
+    fn token_hash_key<'a>(&'a self) -> BoxFuture<'a, ApiResult<&'a [u8]>> {
+        Box::pin(async move {
+            let key =
+                self.token_hash_key_cell
+                    .get_or_try_init(async {
+                        let ciphertext = base64::decode(&self.token_hash_key).map_err(|err| {
+                            log::error!("token hash key is not base64: {:?}", err);
+                            ApiError::Other("invalid token hash key".to_string())
+                        })?;
+
+                        let output = self
+                            .client
+                            .decrypt(DecryptRequest {
+                                ciphertext_blob: ciphertext.into(),
+                                ..Default::default()
+                            })
+                            .await
+                            .map_err(|err| {
+                                log::error!("decrypt token hash key error: {:?}", err);
+                                ApiError::Other("error loading token hash key".to_string())
+                            })?;
+
+                        output.plaintext.map(|b| b.to_vec()).ok_or_else(|| {
+                            ApiError::Other("error loading token hash key".to_string())
+                        })
+                    })
+                    .await?;
+
+            Ok(&key[..])
+        })
+    }
+}
+
+// A plain key and the os random number generator, for running without kms.
+pub struct LocalSecrets {
+    pub token_hash_key: Vec<u8>,
+}
+
+impl LocalSecrets {
+    pub fn new(token_hash_key: &[u8]) -> Self {
+        LocalSecrets {
+            token_hash_key: token_hash_key.to_vec(),
+        }
+    }
+}
+
+impl Secrets for LocalSecrets {
+    fn random_bytes<'a>(&'a self, n: usize) -> BoxFuture<'a, ApiResult<Vec<u8>>> {
+        Box::pin(async move {
+            let mut bytes = vec![0; n];
+            getrandom::getrandom(&mut bytes).map_err(|err| {
+                log::error!("getrandom error: {:?}", err);
+                ApiError::Other("error generating token".to_string())
+            })?;
+            Ok(bytes)
+        })
+    }
+
+    fn token_hash_key<'a>(&'a self) -> BoxFuture<'a, ApiResult<&'a [u8]>> {
+        Box::pin(async move { Ok(&self.token_hash_key[..]) })
+    }
+}
@@ -0,0 +1,76 @@
+use rusoto_core::Region;
+use rusoto_dynamodb::DynamoDbClient;
+use std::sync::Arc;
+
+use crate::config::Config;
+use crate::owners::{DynamoDbOwners, MemoryOwners, OwnerRepository};
+use crate::packages::{DynamoDbPackages, MemoryPackages, PackageRepository};
+use crate::result::ApiResult;
+use crate::secrets::{KmsSecrets, LocalSecrets, Secrets};
+use crate::storage::{self, MemoryStorage, Storage};
+use crate::summaries::{DynamoDbSummaries, MemorySummaries, SummaryRepository};
+use crate::tokens::{DynamoDbTokens, MemoryTokens, TokenRepository};
+use crate::trusted_publishing::{GitHubOidc, OidcVerifier};
+
+// Everything the handlers need, built once at startup and
+// handed to each request through the request extensions.
+pub struct AppState {
+    pub config: Config,
+    pub packages: Box<dyn PackageRepository>,
+    pub owners: Box<dyn OwnerRepository>,
+    pub summaries: Box<dyn SummaryRepository>,
+    pub tokens: Box<dyn TokenRepository>,
+    pub secrets: Arc<dyn Secrets>,
+    pub storage: Box<dyn Storage>,
+    // Only set if trusted publishing is enabled.
+    pub oidc_verifier: Option<Box<dyn OidcVerifier>>,
+}
+
+impl AppState {
+    pub fn from_env() -> ApiResult<AppState> {
+        let config = Config::from_env()?;
+        let dynamodb = DynamoDbClient::new(Region::default());
+        let secrets: Arc<dyn Secrets> = Arc::new(KmsSecrets::new(&config.token_hash_key));
+
+        Ok(AppState {
+            packages: Box::new(
+                DynamoDbPackages::new(dynamodb.clone(), &config.packages_table)
+                    .with_name_index(&config.packages_name_index),
+            ),
+            owners: Box::new(DynamoDbOwners::new(dynamodb.clone(), &config.owners_table)),
+            summaries: Box::new(DynamoDbSummaries::new(
+                dynamodb.clone(),
+                &config.summaries_table,
+            )),
+            tokens: Box::new(DynamoDbTokens::new(
+                dynamodb,
+                &config.tokens_table,
+                config.legacy_tokens_table.as_deref(),
+                secrets.clone(),
+            )),
+            secrets,
+            storage: storage::from_env()?,
+            oidc_verifier: GitHubOidc::from_config(&config),
+            config,
+        })
+    }
+
+    // No aws at all. The token hash key is used as is rather than decrypted.
+    pub fn in_memory(config: Config) -> AppState {
+        AppState {
+            packages: Box::new(MemoryPackages::default()),
+            owners: Box::new(MemoryOwners::default()),
+            summaries: Box::new(MemorySummaries::default()),
+            tokens: Box::new(MemoryTokens::default()),
+            secrets: Arc::new(LocalSecrets::new(config.token_hash_key.as_bytes())),
+            storage: Box::new(MemoryStorage::default()),
+            oidc_verifier: None,
+            config,
+        }
+    }
+
+    pub fn with_oidc_verifier(mut self, oidc_verifier: Box<dyn OidcVerifier>) -> Self {
+        self.oidc_verifier = Some(oidc_verifier);
+        self
+    }
+}
@@ -0,0 +1,125 @@
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+
+use super::Storage;
+use crate::error::ApiError;
+use crate::result::ApiResult;
+use crate::BoxFuture;
+
+// Keeps crate files in a directory on the local filesystem.
+// Useful for running the whole publish/download flow without aws.
+pub struct LocalStorage {
+    pub dir: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(dir: PathBuf) -> Self {
+        LocalStorage { dir }
+    }
+
+    pub fn path(&self, key: &str) -> ApiResult<PathBuf> {
+        let key_path = Path::new(key);
+
+        if key_path
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_)))
+        {
+            return Err(ApiError::InvalidInput(format!("invalid storage key {}", key)));
+        }
+
+        Ok(self.dir.join(key_path))
+    }
+}
+
+fn storage_error(key: &str, what: &str, err: io::Error) -> ApiError {
+    log::error!("{} error for {}: {:?}", what, key, err);
+    ApiError::Storage("error accessing crate file".to_string())
+}
+
+impl Storage for LocalStorage {
+    fn put<'a>(&'a self, key: &'a str, data: Vec<u8>) -> BoxFuture<'a, ApiResult<()>> {
+        Box::pin(async move {
+            let path = self.path(key)?;
+
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent)
+                    .await
+                    .map_err(|e| storage_error(key, "create dir", e))?;
+            }
+
+            tokio::fs::write(&path, data)
+                .await
+                .map_err(|e| storage_error(key, "write file", e))
+        })
+    }
+
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, ApiResult<Option<Vec<u8>>>> {
+        Box::pin(async move {
+            match tokio::fs::read(self.path(key)?).await {
+                Ok(data) => Ok(Some(data)),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(storage_error(key, "read file", e)),
+            }
+        })
+    }
+
+    fn exists<'a>(&'a self, key: &'a str) -> BoxFuture<'a, ApiResult<bool>> {
+        Box::pin(async move {
+            match tokio::fs::metadata(self.path(key)?).await {
+                Ok(metadata) => Ok(metadata.is_file()),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+                Err(e) => Err(storage_error(key, "stat file", e)),
+            }
+        })
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, ApiResult<()>> {
+        Box::pin(async move {
+            match tokio::fs::remove_file(self.path(key)?).await {
+                Ok(()) => Ok(()),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+                Err(e) => Err(storage_error(key, "remove file", e)),
+            }
+        })
+    }
+
+    fn presign<'a>(
+        &'a self,
+        _key: &'a str,
+        _expires_in: Duration,
+    ) -> BoxFuture<'a, ApiResult<Option<String>>> {
+        Box::pin(async { Ok(None) })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[tokio::test]
+    async fn test_local_storage_round_trip() {
+        let dir = TempDir::new("storage").expect("tempdir");
+        let storage = LocalStorage::new(dir.path().to_path_buf());
+        let key = "crates/foo/foo-0.1.0.crate";
+
+        assert!(!storage.exists(key).await.expect("exists"));
+        assert_eq!(storage.get(key).await.expect("get"), None);
+
+        storage.put(key, b"tarball".to_vec()).await.expect("put");
+        assert!(storage.exists(key).await.expect("exists"));
+        assert_eq!(storage.get(key).await.expect("get"), Some(b"tarball".to_vec()));
+
+        storage.delete(key).await.expect("delete");
+        assert!(!storage.exists(key).await.expect("exists"));
+    }
+
+    #[test]
+    fn test_local_storage_rejects_escaping_keys() {
+        let storage = LocalStorage::new(PathBuf::from("/tmp/crates"));
+        assert!(storage.path("../etc/passwd").is_err());
+        assert!(storage.path("/etc/passwd").is_err());
+        assert!(storage.path("crates/foo/foo-0.1.0.crate").is_ok());
+    }
+}
@@ -0,0 +1,45 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use super::Storage;
+use crate::result::ApiResult;
+use crate::BoxFuture;
+
+// Keeps crate files in memory, for tests.
+#[derive(Default)]
+pub struct MemoryStorage {
+    objects: Mutex<HashMap<String, Vec<u8>>>,
+}
+
+impl Storage for MemoryStorage {
+    fn put<'a>(&'a self, key: &'a str, data: Vec<u8>) -> BoxFuture<'a, ApiResult<()>> {
+        Box::pin(async move {
+            self.objects.lock().unwrap().insert(key.to_owned(), data);
+            Ok(())
+        })
+    }
+
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, ApiResult<Option<Vec<u8>>>> {
+        Box::pin(async move { Ok(self.objects.lock().unwrap().get(key).cloned()) })
+    }
+
+    fn exists<'a>(&'a self, key: &'a str) -> BoxFuture<'a, ApiResult<bool>> {
+        Box::pin(async move { Ok(self.objects.lock().unwrap().contains_key(key)) })
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, ApiResult<()>> {
+        Box::pin(async move {
+            self.objects.lock().unwrap().remove(key);
+            Ok(())
+        })
+    }
+
+    fn presign<'a>(
+        &'a self,
+        _key: &'a str,
+        _expires_in: Duration,
+    ) -> BoxFuture<'a, ApiResult<Option<String>>> {
+        Box::pin(async { Ok(None) })
+    }
+}
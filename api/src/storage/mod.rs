@@ -0,0 +1,57 @@
+use std::env;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::error::ApiError;
+use crate::result::ApiResult;
+use crate::BoxFuture;
+
+pub mod local;
+pub mod memory;
+pub mod s3;
+
+pub use local::LocalStorage;
+pub use memory::MemoryStorage;
+pub use s3::S3Storage;
+
+// Somewhere to keep .crate files, addressed by key.
+pub trait Storage: Send + Sync {
+    fn put<'a>(&'a self, key: &'a str, data: Vec<u8>) -> BoxFuture<'a, ApiResult<()>>;
+
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, ApiResult<Option<Vec<u8>>>>;
+
+    fn exists<'a>(&'a self, key: &'a str) -> BoxFuture<'a, ApiResult<bool>>;
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, ApiResult<()>>;
+
+    // Returns a url the client can fetch the object from directly,
+    // or None if the backend can't hand out urls and the object must be served by us.
+    fn presign<'a>(
+        &'a self,
+        key: &'a str,
+        expires_in: Duration,
+    ) -> BoxFuture<'a, ApiResult<Option<String>>>;
+}
+
+pub fn crate_key(name: &str, version: &str) -> String {
+    format!("crates/{}/{}-{}.crate", name, name, version)
+}
+
+pub fn from_env() -> ApiResult<Box<dyn Storage>> {
+    let backend = env::var("STORAGE_BACKEND").unwrap_or_else(|_| "s3".to_string());
+
+    match &backend[..] {
+        "s3" => {
+            let bucket = env::var("CRATES_BUCKET")
+                .map_err(|e| ApiError::Other(format!("config key CRATES_BUCKET: {}", e)))?;
+            Ok(Box::new(S3Storage::new(&bucket)))
+        }
+        "local" => {
+            let dir = env::var("CRATES_DIR")
+                .map_err(|e| ApiError::Other(format!("config key CRATES_DIR: {}", e)))?;
+            Ok(Box::new(LocalStorage::new(PathBuf::from(dir))))
+        }
+        "memory" => Ok(Box::new(MemoryStorage::default())),
+        other => Err(ApiError::Other(format!("unknown storage backend {}", other))),
+    }
+}
@@ -0,0 +1,160 @@
+use rusoto_core::credential::{DefaultCredentialsProvider, ProvideAwsCredentials};
+use rusoto_core::{Region, RusotoError};
+use rusoto_s3::util::{PreSignedRequest, PreSignedRequestOption};
+use rusoto_s3::{
+    DeleteObjectRequest, GetObjectError, GetObjectRequest, HeadObjectError, HeadObjectRequest,
+    PutObjectRequest, S3Client, S3,
+};
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+
+use super::Storage;
+use crate::error::ApiError;
+use crate::result::ApiResult;
+use crate::BoxFuture;
+
+pub const CRATE_CONTENT_TYPE: &str = "application/x-tar";
+
+pub struct S3Storage {
+    pub bucket: String,
+    region: Region,
+    client: S3Client,
+}
+
+impl S3Storage {
+    pub fn new(bucket: &str) -> Self {
+        let region = Region::default();
+        S3Storage {
+            bucket: bucket.to_owned(),
+            client: S3Client::new(region.clone()),
+            region,
+        }
+    }
+}
+
+impl Storage for S3Storage {
+    fn put<'a>(&'a self, key: &'a str, data: Vec<u8>) -> BoxFuture<'a, ApiResult<()>> {
+        Box::pin(async move {
+            self.client
+                .put_object(PutObjectRequest {
+                    bucket: self.bucket.clone(),
+                    key: key.to_owned(),
+                    content_length: Some(data.len() as i64),
+                    content_type: Some(CRATE_CONTENT_TYPE.to_string()),
+                    body: Some(data.into()),
+                    ..Default::default()
+                })
+                .await
+                .map_err(|err| {
+                    log::error!("put object error for {}: {:?}", key, err);
+                    ApiError::Storage("error saving crate file".to_string())
+                })?;
+            Ok(())
+        })
+    }
+
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, ApiResult<Option<Vec<u8>>>> {
+        Box::pin(async move {
+            let output = match self
+                .client
+                .get_object(GetObjectRequest {
+                    bucket: self.bucket.clone(),
+                    key: key.to_owned(),
+                    ..Default::default()
+                })
+                .await
+            {
+                Ok(output) => output,
+                Err(RusotoError::Service(GetObjectError::NoSuchKey(_))) => return Ok(None),
+                Err(err) => {
+                    log::error!("get object error for {}: {:?}", key, err);
+                    return Err(ApiError::Storage("error fetching crate file".to_string()));
+                }
+            };
+
+            let mut data = vec![];
+            if let Some(body) = output.body {
+                body.into_async_read()
+                    .read_to_end(&mut data)
+                    .await
+                    .map_err(|err| {
+                        log::error!("read object error for {}: {:?}", key, err);
+                        ApiError::Storage("error reading crate file".to_string())
+                    })?;
+            }
+            Ok(Some(data))
+        })
+    }
+
+    fn exists<'a>(&'a self, key: &'a str) -> BoxFuture<'a, ApiResult<bool>> {
+        Box::pin(async move {
+            match self
+                .client
+                .head_object(HeadObjectRequest {
+                    bucket: self.bucket.clone(),
+                    key: key.to_owned(),
+                    ..Default::default()
+                })
+                .await
+            {
+                Ok(_) => Ok(true),
+                Err(RusotoError::Service(HeadObjectError::NoSuchKey(_))) => Ok(false),
+                // head responses have no body so s3 can't tell us which error it was
+                Err(RusotoError::Unknown(ref resp)) if resp.status.as_u16() == 404 => Ok(false),
+                Err(err) => {
+                    log::error!("head object error for {}: {:?}", key, err);
+                    Err(ApiError::Storage("error checking crate file".to_string()))
+                }
+            }
+        })
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, ApiResult<()>> {
+        Box::pin(async move {
+            self.client
+                .delete_object(DeleteObjectRequest {
+                    bucket: self.bucket.clone(),
+                    key: key.to_owned(),
+                    ..Default::default()
+                })
+                .await
+                .map_err(|err| {
+                    log::error!("delete object error for {}: {:?}", key, err);
+                    ApiError::Storage("error deleting crate file".to_string())
+                })?;
+            Ok(())
+        })
+    }
+
+    fn presign<'a>(
+        &'a self,
+        key: &'a str,
+        expires_in: Duration,
+    ) -> BoxFuture<'a, ApiResult<Option<String>>> {
+        Box::pin(async move {
+            let credentials = DefaultCredentialsProvider::new()
+                .map_err(|err| {
+                    log::error!("credentials provider error: {:?}", err);
+                    ApiError::Storage("error signing crate file url".to_string())
+                })?
+                .credentials()
+                .await
+                .map_err(|err| {
+                    log::error!("credentials error: {:?}", err);
+                    ApiError::Storage("error signing crate file url".to_string())
+                })?;
+
+            let request = GetObjectRequest {
+                bucket: self.bucket.clone(),
+                key: key.to_owned(),
+                ..Default::default()
+            };
+
+            Ok(Some(request.get_presigned_url(
+                &self.region,
+                &credentials,
+                &PreSignedRequestOption { expires_in },
+            )))
+        })
+    }
+}
@@ -0,0 +1,257 @@
+use maplit::hashmap;
+use rusoto_dynamodb::{DeleteItemInput, DynamoDb, DynamoDbClient, PutItemInput, ScanInput};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use crate::db::*;
+use crate::error::ApiError;
+use crate::packages::Package;
+use crate::result::ApiResult;
+use crate::state::AppState;
+use crate::BoxFuture;
+
+// What search needs to know about a crate, one item per crate keyed by name,
+// so searching doesn't have to read every version ever published.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CrateSummary {
+    pub name: String,
+    // The newest version that hasn't been yanked.
+    pub max_version: String,
+    pub description: Option<String>,
+    pub keywords: Vec<String>,
+    pub updated_at: i64,
+}
+
+impl CrateSummary {
+    // None if every version has been yanked or deleted.
+    pub fn from_packages(packages: &[Package]) -> Option<CrateSummary> {
+        let (max_version, package) = packages
+            .iter()
+            .filter(|p| !p.yanked && !p.deleted)
+            .filter_map(|p| match semver::Version::parse(&p.version) {
+                Ok(version) => Some((version, p)),
+                Err(e) => {
+                    log::warn!("skipping {} {}: {}", p.name, p.version, e);
+                    None
+                }
+            })
+            .max_by(|a, b| a.0.cmp(&b.0))?;
+
+        Some(CrateSummary {
+            name: package.name.clone(),
+            max_version: max_version.to_string(),
+            description: package.description.clone(),
+            keywords: package.keywords.clone(),
+            updated_at: unix_timestamp(),
+        })
+    }
+
+    pub fn key(name: &str) -> Item {
+        hashmap! {
+            "name".to_string() => string_attr_value(name),
+        }
+    }
+
+    pub fn to_item(&self) -> Item {
+        let mut item = Self::key(&self.name);
+        item.insert("max_version".to_string(), string_attr_value(self.max_version.clone()));
+        item.insert("description".to_string(), maybe_string_attr_value(self.description.clone()));
+        item.insert("keywords".to_string(), string_list_attr_value(&self.keywords));
+        item.insert("updated_at".to_string(), long_attr_value(self.updated_at));
+        item
+    }
+
+    pub fn from_item(item: &Item) -> ApiResult<CrateSummary> {
+        Ok(CrateSummary {
+            name: get_string(item, "name")?,
+            max_version: get_string(item, "max_version")?,
+            description: get_maybe_string(item, "description")?,
+            keywords: get_string_list(item, "keywords")?,
+            updated_at: get_long(item, "updated_at")?,
+        })
+    }
+}
+
+pub trait SummaryRepository: Send + Sync {
+    fn put_summary<'a>(&'a self, summary: &'a CrateSummary) -> BoxFuture<'a, ApiResult<()>>;
+
+    fn delete_summary<'a>(&'a self, name: &'a str) -> BoxFuture<'a, ApiResult<()>>;
+
+    // Every summary, in no particular order.
+    fn scan_summaries<'a>(&'a self) -> BoxFuture<'a, ApiResult<Vec<CrateSummary>>>;
+}
+
+pub struct DynamoDbSummaries {
+    client: DynamoDbClient,
+    table: String,
+}
+
+impl DynamoDbSummaries {
+    pub fn new(client: DynamoDbClient, table: &str) -> Self {
+        DynamoDbSummaries {
+            client,
+            table: table.to_owned(),
+        }
+    }
+}
+
+impl SummaryRepository for DynamoDbSummaries {
+    fn put_summary<'a>(&'a self, summary: &'a CrateSummary) -> BoxFuture<'a, ApiResult<()>> {
+        Box::pin(async move {
+            self.client
+                .put_item(PutItemInput {
+                    item: summary.to_item(),
+                    table_name: self.table.clone(),
+                    ..Default::default()
+                })
+                .await
+                .map_err(|err| {
+                    log::error!("put summary error for {}: {:?}", summary.name, err);
+                    ApiError::Database("error saving crate summary".to_string())
+                })?;
+
+            Ok(())
+        })
+    }
+
+    fn delete_summary<'a>(&'a self, name: &'a str) -> BoxFuture<'a, ApiResult<()>> {
+        Box::pin(async move {
+            self.client
+                .delete_item(DeleteItemInput {
+                    key: CrateSummary::key(name),
+                    table_name: self.table.clone(),
+                    ..Default::default()
+                })
+                .await
+                .map_err(|err| {
+                    log::error!("delete summary error for {}: {:?}", name, err);
+                    ApiError::Database("error deleting crate summary".to_string())
+                })?;
+
+            Ok(())
+        })
+    }
+
+    fn scan_summaries<'a>(&'a self) -> BoxFuture<'a, ApiResult<Vec<CrateSummary>>> {
+        Box::pin(async move {
+            let mut summaries = vec![];
+            let mut exclusive_start_key = None;
+
+            loop {
+                let output = self
+                    .client
+                    .scan(ScanInput {
+                        exclusive_start_key,
+                        table_name: self.table.clone(),
+                        ..Default::default()
+                    })
+                    .await
+                    .map_err(|err| {
+                        log::error!("scan summaries error: {:?}", err);
+                        ApiError::Database("error fetching crate summaries".to_string())
+                    })?;
+
+                for item in output.items.unwrap_or_default().iter() {
+                    summaries.push(CrateSummary::from_item(item)?);
+                }
+
+                exclusive_start_key = output.last_evaluated_key;
+                if exclusive_start_key.is_none() {
+                    return Ok(summaries);
+                }
+            }
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct MemorySummaries {
+    summaries: Mutex<BTreeMap<String, CrateSummary>>,
+}
+
+impl SummaryRepository for MemorySummaries {
+    fn put_summary<'a>(&'a self, summary: &'a CrateSummary) -> BoxFuture<'a, ApiResult<()>> {
+        Box::pin(async move {
+            self.summaries
+                .lock()
+                .unwrap()
+                .insert(summary.name.clone(), summary.clone());
+            Ok(())
+        })
+    }
+
+    fn delete_summary<'a>(&'a self, name: &'a str) -> BoxFuture<'a, ApiResult<()>> {
+        Box::pin(async move {
+            self.summaries.lock().unwrap().remove(name);
+            Ok(())
+        })
+    }
+
+    fn scan_summaries<'a>(&'a self) -> BoxFuture<'a, ApiResult<Vec<CrateSummary>>> {
+        Box::pin(async move { Ok(self.summaries.lock().unwrap().values().cloned().collect()) })
+    }
+}
+
+// Called after anything that changes which version is newest, ie. publishing and yanking.
+pub async fn refresh_summary(state: &AppState, name: &str) -> ApiResult<()> {
+    let packages = state.packages.get_packages(name).await?;
+
+    match CrateSummary::from_packages(&packages) {
+        Some(summary) => state.summaries.put_summary(&summary).await,
+        None => state.summaries.delete_summary(name).await,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn package(version: &str, yanked: bool) -> Package {
+        Package {
+            name: "rand".to_string(),
+            version: version.to_string(),
+            deps: vec![],
+            cksum: "cksum".to_string(),
+            features: Default::default(),
+            yanked,
+            links: None,
+            description: Some(format!("rand {}", version)),
+            keywords: vec![],
+            published_by: None,
+            published_at: None,
+            updated_at: None,
+            indexed: true,
+            deleted: false,
+        }
+    }
+
+    #[test]
+    fn test_summary_max_version_excludes_yanked() {
+        let summary = CrateSummary::from_packages(&[
+            package("0.6.1", false),
+            package("0.6.10", true),
+            package("0.6.2", false),
+            package("not a version", false),
+        ])
+        .expect("summary");
+        assert_eq!(summary.max_version, "0.6.2");
+        assert_eq!(summary.description.as_deref(), Some("rand 0.6.2"));
+
+        assert_eq!(CrateSummary::from_packages(&[package("0.6.10", true)]), None);
+    }
+
+    #[tokio::test]
+    async fn test_memory_scan_summaries() {
+        let summaries = MemorySummaries::default();
+        for name in &["a", "b", "c"] {
+            let mut summary = CrateSummary::from_packages(&[package("0.1.0", false)]).expect("summary");
+            summary.name = name.to_string();
+            summaries.put_summary(&summary).await.expect("put");
+        }
+        summaries.delete_summary("b").await.expect("delete");
+
+        let scanned = summaries.scan_summaries().await.expect("scan");
+        let names: Vec<&str> = scanned.iter().map(|s| &s.name[..]).collect();
+        assert_eq!(names, vec!["a", "c"]);
+    }
+}
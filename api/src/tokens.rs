@@ -1,73 +1,975 @@
 use crate::result::ApiResult;
-use lazy_static::lazy_static;
-use std::env;
+use api_types::auth::{AuthMethod, Identity};
+use api_types::tokens::{
+    self as token_types, CreateTokenOutput, ListTokensOutput, RevokeTokenOutput, TokenInfo,
+    TokenPermissions,
+};
 use maplit::hashmap;
-use rusoto_core::Region;
-use rusoto_dynamodb::{AttributeValue, DynamoDb, DynamoDbClient, GetItemInput, PutItemInput};
-use rusoto_kms::{Kms, KmsClient, GenerateRandomRequest};
+use rusoto_core::RusotoError;
+use rusoto_dynamodb::{
+    DeleteItemError, DeleteItemInput, DynamoDb, DynamoDbClient, GetItemInput, PutItemInput,
+    QueryInput, ScanInput, UpdateItemInput,
+};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, UNIX_EPOCH};
 
+use crate::db::*;
 use crate::error::ApiError;
+use crate::secrets::Secrets;
+use crate::state::AppState;
+use crate::BoxFuture;
 
-lazy_static! {
-    static ref DYNAMODB_CLIENT: DynamoDbClient = DynamoDbClient::new(Region::default());
-    static ref TOKENS_TABLE: String = env::var("TOKENS_TABLE").unwrap();
-    static ref KMS_CLIENT: KmsClient = KmsClient::new(Region::default());
-}
-
-pub async fn get_or_create_token(user_id: &str) -> ApiResult<String> {
-    if let Some(token) = get_user_token(user_id).await? {
-        Ok(token)
-    } else {
-        create_user_token(user_id).await
-    }
-}
-
-pub async fn get_user_token(user_id: &str) -> ApiResult<Option<String>> {
-    let output = DYNAMODB_CLIENT.get_item(GetItemInput {
-        key: hashmap! {
-            "user_id".to_string() => AttributeValue { s: Some(user_id.to_owned()), ..Default::default() },
-        },
-        table_name: TOKENS_TABLE.clone(),
-        ..Default::default()
-    }).await
-        .map_err(|err| {
-            log::error!("get token error for user {}: {:?}", user_id, err);
-            ApiError::Database(format!("error fetching token"))
-        })?;
-
-    Ok(output.item.and_then(|mut attrs| attrs.remove("token")).and_then(|token_attr| token_attr.s))
-}
-
-pub async fn generate_token() -> ApiResult<String> {
-    let output = KMS_CLIENT.generate_random(GenerateRandomRequest {
-        number_of_bytes: Some(24),
-        ..Default::default()
-    }).await
-    .map_err(|err| {
-        log::error!("generate token error: {:?}", err);
-        ApiError::Database(format!("error generating token"))
-    })?;
-    
-    output.plaintext
-        .ok_or_else(|| ApiError::Database(format!("error generating token: no token returned")))
-        .map(|b| base64::encode(b))
-}
-
-pub async fn create_user_token(user_id: &str) -> ApiResult<String> {
-    let token = generate_token().await?;
-
-    DYNAMODB_CLIENT.put_item(PutItemInput {
-        item: hashmap! {
-            "user_id".to_string() => AttributeValue { s: Some(user_id.to_owned()), ..Default::default() },
-            "token".to_string() => AttributeValue { s: Some(token.clone()), ..Default::default() },
-        },
-        table_name: TOKENS_TABLE.to_owned(),
-        ..Default::default()
-    }).await
-        .map_err(|err| {
-            log::error!("put token error for user {}: {:?}", user_id, err);
-            ApiError::Database(format!("error saving token"))
-        })?;
-    
-    Ok(token)
-}
\ No newline at end of file
+pub const DEFAULT_TOKEN_NAME: &str = "default";
+pub const MAX_TOKEN_NAME_LENGTH: usize = 64;
+pub const MAX_TOKEN_EXPIRES_IN: i64 = 5 * 365 * SECONDS_PER_DAY;
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+// One item per token, keyed by the owning user and a random token id.
+// The secret itself is never stored, the token hash index on `token_hash`
+// lets the authorizer find the user.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Token {
+    pub user_id: String,
+    pub token_id: String,
+    pub name: String,
+    pub token_hash: String,
+    pub permissions: TokenPermissions,
+    pub created_at: Option<i64>,
+    pub last_used_at: Option<i64>,
+    // Also the table's ttl attribute, so expired tokens get cleaned up.
+    pub expires_at: Option<i64>,
+}
+
+impl Token {
+    pub fn key(user_id: &str, token_id: &str) -> Item {
+        hashmap! {
+            "user_id".to_string() => string_attr_value(user_id),
+            "token_id".to_string() => string_attr_value(token_id),
+        }
+    }
+
+    pub fn to_item(&self) -> Item {
+        let mut item = Self::key(&self.user_id, &self.token_id);
+        item.insert("name".to_string(), string_attr_value(self.name.clone()));
+        item.insert(
+            "token_hash".to_string(),
+            string_attr_value(self.token_hash.clone()),
+        );
+        let scopes: Vec<String> = self
+            .permissions
+            .scopes
+            .iter()
+            .map(|scope| scope.to_string())
+            .collect();
+        item.insert("scopes".to_string(), string_list_attr_value(&scopes));
+        item.insert(
+            "crates".to_string(),
+            string_list_attr_value(&self.permissions.crates),
+        );
+        if let Some(created_at) = self.created_at {
+            item.insert("created_at".to_string(), long_attr_value(created_at));
+        }
+        if let Some(last_used_at) = self.last_used_at {
+            item.insert("last_used_at".to_string(), long_attr_value(last_used_at));
+        }
+        if let Some(expires_at) = self.expires_at {
+            item.insert("expires_at".to_string(), long_attr_value(expires_at));
+        }
+        item
+    }
+
+    pub fn from_item(item: &Item) -> ApiResult<Token> {
+        // tokens from before scopes were added can do anything
+        let mut permissions = TokenPermissions::default();
+        if item.contains_key("scopes") {
+            permissions.scopes = get_string_list(item, "scopes")?
+                .iter()
+                .map(|scope| scope.parse())
+                .collect::<Result<_, _>>()
+                .map_err(ApiError::Database)?;
+        }
+        permissions.crates = get_string_list(item, "crates")?;
+
+        Ok(Token {
+            user_id: get_string(item, "user_id")?,
+            token_id: get_string(item, "token_id")?,
+            name: get_string(item, "name")?,
+            token_hash: get_string(item, "token_hash")?,
+            permissions,
+            created_at: get_maybe_long(item, "created_at")?,
+            last_used_at: get_maybe_long(item, "last_used_at")?,
+            expires_at: get_maybe_long(item, "expires_at")?,
+        })
+    }
+
+    // Everything but the secret.
+    pub fn info(&self) -> TokenInfo {
+        TokenInfo {
+            id: self.token_id.clone(),
+            name: self.name.clone(),
+            scopes: self.permissions.scopes.clone(),
+            crates: self.permissions.crates.clone(),
+            created_at: self.created_at,
+            last_used_at: self.last_used_at,
+            expires_at: self.expires_at,
+        }
+    }
+}
+
+pub fn validate_token_name(name: &str) -> ApiResult<()> {
+    if name.trim().is_empty()
+        || name.len() > MAX_TOKEN_NAME_LENGTH
+        || name.chars().any(char::is_control)
+    {
+        return Err(ApiError::InvalidInput(format!(
+            "invalid token name {:?}",
+            name
+        )));
+    }
+
+    Ok(())
+}
+
+pub fn token_expires_at(created_at: i64, expires_in: Option<i64>) -> ApiResult<Option<i64>> {
+    match expires_in {
+        None => Ok(None),
+        Some(expires_in) if expires_in > 0 && expires_in <= MAX_TOKEN_EXPIRES_IN => {
+            Ok(Some(created_at + expires_in))
+        }
+        Some(expires_in) => Err(ApiError::InvalidInput(format!(
+            "invalid token expires_in {}, expected between 1 and {} seconds",
+            expires_in, MAX_TOKEN_EXPIRES_IN
+        ))),
+    }
+}
+
+// The value of a Warning header telling the caller to rotate their token soon.
+pub fn expiry_warning(expires_at: i64, now: i64, warning_days: i64) -> Option<String> {
+    let remaining = expires_at - now;
+    if remaining > warning_days * SECONDS_PER_DAY {
+        return None;
+    }
+
+    let expires_at = UNIX_EPOCH + Duration::from_secs(expires_at.max(0) as u64);
+    Some(format!(
+        "299 wagon \"api token expires {}\"",
+        httpdate::fmt_http_date(expires_at)
+    ))
+}
+
+pub fn validate_token_permissions(permissions: &TokenPermissions) -> ApiResult<()> {
+    if permissions.scopes.is_empty() {
+        return Err(ApiError::InvalidInput(
+            "a token needs at least one scope".to_string(),
+        ));
+    }
+
+    for glob in permissions.crates.iter() {
+        token_types::validate_crate_glob(glob).map_err(ApiError::InvalidInput)?;
+    }
+
+    Ok(())
+}
+
+pub trait TokenRepository: Send + Sync {
+    // Oldest first.
+    fn get_tokens<'a>(&'a self, user_id: &'a str) -> BoxFuture<'a, ApiResult<Vec<Token>>>;
+
+    fn put_token<'a>(&'a self, token: &'a Token) -> BoxFuture<'a, ApiResult<()>>;
+
+    // NotFound if the user has no such token.
+    fn delete_token<'a>(
+        &'a self,
+        user_id: &'a str,
+        token_id: &'a str,
+    ) -> BoxFuture<'a, ApiResult<()>>;
+
+    // For authenticating api keys when there's no authorizer in front of the api.
+    fn find_token<'a>(&'a self, token_hash: &'a str) -> BoxFuture<'a, ApiResult<Option<Token>>>;
+
+    fn record_token_use<'a>(
+        &'a self,
+        user_id: &'a str,
+        token_id: &'a str,
+        now: i64,
+    ) -> BoxFuture<'a, ApiResult<()>>;
+}
+
+pub struct DynamoDbTokens {
+    client: DynamoDbClient,
+    table: String,
+    // The old table with a single token per user, which tokens are moved out of.
+    legacy_table: Option<String>,
+    // Only needed by find_token, the authorizer has its own lookup.
+    token_hash_index: Option<String>,
+    secrets: Arc<dyn Secrets>,
+}
+
+impl DynamoDbTokens {
+    pub fn new(
+        client: DynamoDbClient,
+        table: &str,
+        legacy_table: Option<&str>,
+        secrets: Arc<dyn Secrets>,
+    ) -> Self {
+        DynamoDbTokens {
+            client,
+            table: table.to_owned(),
+            legacy_table: legacy_table.map(|t| t.to_owned()),
+            token_hash_index: None,
+            secrets,
+        }
+    }
+
+    pub fn with_token_hash_index(mut self, index: &str) -> Self {
+        self.token_hash_index = Some(index.to_owned());
+        self
+    }
+
+    async fn put_token_item(&self, token: &Token) -> ApiResult<()> {
+        self.client
+            .put_item(PutItemInput {
+                item: token.to_item(),
+                table_name: self.table.clone(),
+                ..Default::default()
+            })
+            .await
+            .map_err(|err| {
+                log::error!("put token error for user {}: {:?}", token.user_id, err);
+                ApiError::Database("error saving token".to_string())
+            })?;
+
+        Ok(())
+    }
+
+    // Moves the user's token from the legacy table into the tokens table,
+    // where it shows up under the default name and can be revoked like any other.
+    pub async fn migrate_legacy_token(&self, user_id: &str) -> ApiResult<()> {
+        let legacy_table = match self.legacy_table.as_ref() {
+            Some(table) => table.clone(),
+            None => return Ok(()),
+        };
+        let legacy_key = hashmap! {
+            "user_id".to_string() => string_attr_value(user_id),
+        };
+
+        let output = self
+            .client
+            .get_item(GetItemInput {
+                key: legacy_key.clone(),
+                table_name: legacy_table.clone(),
+                ..Default::default()
+            })
+            .await
+            .map_err(|err| {
+                log::error!("get legacy token error for user {}: {:?}", user_id, err);
+                ApiError::Database("error fetching token".to_string())
+            })?;
+
+        let token = match output
+            .item
+            .as_ref()
+            .map(|item| get_maybe_string(item, "token"))
+            .transpose()?
+            .flatten()
+        {
+            Some(token) => token,
+            None => return Ok(()),
+        };
+
+        log::info!("migrating legacy token for user {}", user_id);
+        self.put_token_item(&Token {
+            user_id: user_id.to_owned(),
+            token_id: generate_token_id(&*self.secrets).await?,
+            name: DEFAULT_TOKEN_NAME.to_string(),
+            token_hash: hash_token(&*self.secrets, &token).await?,
+            permissions: TokenPermissions::default(),
+            created_at: None,
+            last_used_at: None,
+            expires_at: None,
+        })
+        .await?;
+
+        self.client
+            .delete_item(DeleteItemInput {
+                key: legacy_key,
+                table_name: legacy_table,
+                ..Default::default()
+            })
+            .await
+            .map_err(|err| {
+                log::error!("delete legacy token error for user {}: {:?}", user_id, err);
+                ApiError::Database("error migrating token".to_string())
+            })?;
+
+        Ok(())
+    }
+
+    // Replaces the plaintext `token` of an item saved before tokens were hashed.
+    pub async fn migrate_plaintext_token(&self, item: &mut Item) -> ApiResult<()> {
+        if item.contains_key("token_hash") {
+            return Ok(());
+        }
+        let token = match get_maybe_string(item, "token")? {
+            Some(token) => token,
+            None => return Ok(()),
+        };
+        let user_id = get_string(item, "user_id")?;
+        let token_id = get_string(item, "token_id")?;
+        let token_hash = hash_token(&*self.secrets, &token).await?;
+
+        log::info!("hashing token {} for user {}", token_id, user_id);
+        self.client
+            .update_item(UpdateItemInput {
+                key: Token::key(&user_id, &token_id),
+                update_expression: Some("SET token_hash = :token_hash REMOVE #T".to_string()),
+                condition_expression: Some("attribute_exists(token_id)".to_string()),
+                expression_attribute_names: Some(hashmap! {
+                    "#T".to_string() => "token".to_string(),
+                }),
+                expression_attribute_values: Some(hashmap! {
+                    ":token_hash".to_string() => string_attr_value(token_hash.clone()),
+                }),
+                table_name: self.table.clone(),
+                ..Default::default()
+            })
+            .await
+            .map_err(|err| {
+                log::error!("hash token error for user {}: {:?}", user_id, err);
+                ApiError::Database("error migrating token".to_string())
+            })?;
+
+        item.remove("token");
+        item.insert("token_hash".to_string(), string_attr_value(token_hash));
+        Ok(())
+    }
+
+    // Hashes every plaintext token and moves everything out of the legacy table,
+    // rather than waiting for each user to list their tokens.
+    pub async fn migrate_all_tokens(&self) -> ApiResult<usize> {
+        let mut migrated = 0;
+
+        let mut exclusive_start_key = None;
+        loop {
+            let output = self
+                .client
+                .scan(ScanInput {
+                    filter_expression: Some("attribute_exists(#T)".to_string()),
+                    expression_attribute_names: Some(hashmap! {
+                        "#T".to_string() => "token".to_string(),
+                    }),
+                    exclusive_start_key,
+                    table_name: self.table.clone(),
+                    ..Default::default()
+                })
+                .await
+                .map_err(|err| {
+                    log::error!("scan tokens error: {:?}", err);
+                    ApiError::Database("error fetching tokens".to_string())
+                })?;
+
+            for mut item in output.items.unwrap_or_default() {
+                self.migrate_plaintext_token(&mut item).await?;
+                migrated += 1;
+            }
+
+            exclusive_start_key = output.last_evaluated_key;
+            if exclusive_start_key.is_none() {
+                break;
+            }
+        }
+
+        if let Some(legacy_table) = self.legacy_table.as_ref() {
+            let mut exclusive_start_key = None;
+            loop {
+                let output = self
+                    .client
+                    .scan(ScanInput {
+                        exclusive_start_key,
+                        table_name: legacy_table.clone(),
+                        ..Default::default()
+                    })
+                    .await
+                    .map_err(|err| {
+                        log::error!("scan legacy tokens error: {:?}", err);
+                        ApiError::Database("error fetching tokens".to_string())
+                    })?;
+
+                for item in output.items.unwrap_or_default() {
+                    self.migrate_legacy_token(&get_string(&item, "user_id")?)
+                        .await?;
+                    migrated += 1;
+                }
+
+                exclusive_start_key = output.last_evaluated_key;
+                if exclusive_start_key.is_none() {
+                    break;
+                }
+            }
+        }
+
+        Ok(migrated)
+    }
+}
+
+impl TokenRepository for DynamoDbTokens {
+    fn get_tokens<'a>(&'a self, user_id: &'a str) -> BoxFuture<'a, ApiResult<Vec<Token>>> {
+        Box::pin(async move {
+            self.migrate_legacy_token(user_id).await?;
+
+            let mut tokens = vec![];
+            let mut exclusive_start_key = None;
+
+            loop {
+                let output = self
+                    .client
+                    .query(QueryInput {
+                        key_condition_expression: Some("user_id = :user_id".to_string()),
+                        expression_attribute_values: Some(hashmap! {
+                            ":user_id".to_string() => string_attr_value(user_id),
+                        }),
+                        exclusive_start_key,
+                        table_name: self.table.clone(),
+                        ..Default::default()
+                    })
+                    .await
+                    .map_err(|err| {
+                        log::error!("query tokens error for user {}: {:?}", user_id, err);
+                        ApiError::Database("error fetching tokens".to_string())
+                    })?;
+
+                for mut item in output.items.unwrap_or_default() {
+                    self.migrate_plaintext_token(&mut item).await?;
+                    tokens.push(Token::from_item(&item)?);
+                }
+
+                exclusive_start_key = output.last_evaluated_key;
+                if exclusive_start_key.is_none() {
+                    break;
+                }
+            }
+
+            sort_tokens(&mut tokens);
+            Ok(tokens)
+        })
+    }
+
+    fn put_token<'a>(&'a self, token: &'a Token) -> BoxFuture<'a, ApiResult<()>> {
+        Box::pin(self.put_token_item(token))
+    }
+
+    fn delete_token<'a>(
+        &'a self,
+        user_id: &'a str,
+        token_id: &'a str,
+    ) -> BoxFuture<'a, ApiResult<()>> {
+        Box::pin(async move {
+            self.migrate_legacy_token(user_id).await?;
+
+            self.client
+                .delete_item(DeleteItemInput {
+                    key: Token::key(user_id, token_id),
+                    condition_expression: Some("attribute_exists(token_id)".to_string()),
+                    table_name: self.table.clone(),
+                    ..Default::default()
+                })
+                .await
+                .map_err(|err| match err {
+                    RusotoError::Service(DeleteItemError::ConditionalCheckFailed(_)) => {
+                        ApiError::NotFound(format!("token {} not found", token_id))
+                    }
+                    err => {
+                        log::error!("delete token error for user {}: {:?}", user_id, err);
+                        ApiError::Database("error revoking token".to_string())
+                    }
+                })?;
+
+            Ok(())
+        })
+    }
+
+    fn find_token<'a>(&'a self, token_hash: &'a str) -> BoxFuture<'a, ApiResult<Option<Token>>> {
+        Box::pin(async move {
+            let index = self
+                .token_hash_index
+                .as_ref()
+                .ok_or_else(|| ApiError::Other("no token hash index configured".to_string()))?;
+
+            let output = self
+                .client
+                .query(QueryInput {
+                    key_condition_expression: Some("token_hash = :token_hash".to_string()),
+                    expression_attribute_values: Some(hashmap! {
+                        ":token_hash".to_string() => string_attr_value(token_hash),
+                    }),
+                    index_name: Some(index.clone()),
+                    table_name: self.table.clone(),
+                    ..Default::default()
+                })
+                .await
+                .map_err(|err| {
+                    log::error!("query token hash index error: {:?}", err);
+                    ApiError::Database("error fetching token".to_string())
+                })?;
+
+            let keys = match output.items.and_then(|items| items.into_iter().next()) {
+                Some(keys) => keys,
+                None => return Ok(None),
+            };
+
+            // the index only projects the keys
+            let output = self
+                .client
+                .get_item(GetItemInput {
+                    key: Token::key(
+                        &get_string(&keys, "user_id")?,
+                        &get_string(&keys, "token_id")?,
+                    ),
+                    table_name: self.table.clone(),
+                    ..Default::default()
+                })
+                .await
+                .map_err(|err| {
+                    log::error!("get token error: {:?}", err);
+                    ApiError::Database("error fetching token".to_string())
+                })?;
+
+            output.item.as_ref().map(Token::from_item).transpose()
+        })
+    }
+
+    fn record_token_use<'a>(
+        &'a self,
+        user_id: &'a str,
+        token_id: &'a str,
+        now: i64,
+    ) -> BoxFuture<'a, ApiResult<()>> {
+        Box::pin(async move {
+            self.client
+                .update_item(UpdateItemInput {
+                    key: Token::key(user_id, token_id),
+                    update_expression: Some("SET last_used_at = :now".to_string()),
+                    condition_expression: Some("attribute_exists(token_id)".to_string()),
+                    expression_attribute_values: Some(hashmap! {
+                        ":now".to_string() => long_attr_value(now),
+                    }),
+                    table_name: self.table.clone(),
+                    ..Default::default()
+                })
+                .await
+                .map_err(|err| {
+                    log::error!("record token use error for user {}: {:?}", user_id, err);
+                    ApiError::Database("error updating token".to_string())
+                })?;
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryTokens {
+    tokens: Mutex<BTreeMap<(String, String), Token>>,
+}
+
+impl TokenRepository for MemoryTokens {
+    fn get_tokens<'a>(&'a self, user_id: &'a str) -> BoxFuture<'a, ApiResult<Vec<Token>>> {
+        Box::pin(async move {
+            let mut tokens: Vec<Token> = self
+                .tokens
+                .lock()
+                .unwrap()
+                .values()
+                .filter(|t| t.user_id == user_id)
+                .cloned()
+                .collect();
+            sort_tokens(&mut tokens);
+            Ok(tokens)
+        })
+    }
+
+    fn put_token<'a>(&'a self, token: &'a Token) -> BoxFuture<'a, ApiResult<()>> {
+        Box::pin(async move {
+            let key = (token.user_id.clone(), token.token_id.clone());
+            self.tokens.lock().unwrap().insert(key, token.clone());
+            Ok(())
+        })
+    }
+
+    fn delete_token<'a>(
+        &'a self,
+        user_id: &'a str,
+        token_id: &'a str,
+    ) -> BoxFuture<'a, ApiResult<()>> {
+        Box::pin(async move {
+            self.tokens
+                .lock()
+                .unwrap()
+                .remove(&(user_id.to_owned(), token_id.to_owned()))
+                .map(|_| ())
+                .ok_or_else(|| ApiError::NotFound(format!("token {} not found", token_id)))
+        })
+    }
+
+    fn find_token<'a>(&'a self, token_hash: &'a str) -> BoxFuture<'a, ApiResult<Option<Token>>> {
+        Box::pin(async move {
+            let tokens = self.tokens.lock().unwrap();
+            Ok(tokens
+                .values()
+                .find(|t| t.token_hash == token_hash)
+                .cloned())
+        })
+    }
+
+    fn record_token_use<'a>(
+        &'a self,
+        user_id: &'a str,
+        token_id: &'a str,
+        now: i64,
+    ) -> BoxFuture<'a, ApiResult<()>> {
+        Box::pin(async move {
+            let mut tokens = self.tokens.lock().unwrap();
+            if let Some(token) = tokens.get_mut(&(user_id.to_owned(), token_id.to_owned())) {
+                token.last_used_at = Some(now);
+            }
+            Ok(())
+        })
+    }
+}
+
+fn sort_tokens(tokens: &mut Vec<Token>) {
+    tokens.sort_by(|a, b| (a.created_at, &a.token_id).cmp(&(b.created_at, &b.token_id)));
+}
+
+// Only a newly created token can be returned, an existing default token
+// can't be shown again and has to be revoked to get a new one.
+// The default token only gets the scopes the user has, eg. just read for a reader.
+pub async fn get_or_create_token(
+    state: &AppState,
+    user_id: &str,
+    allowed: &TokenPermissions,
+) -> ApiResult<Option<String>> {
+    let tokens = state.tokens.get_tokens(user_id).await?;
+
+    if tokens.iter().any(|t| t.name == DEFAULT_TOKEN_NAME) {
+        return Ok(None);
+    }
+
+    let mut permissions = TokenPermissions::default();
+    permissions
+        .scopes
+        .retain(|scope| allowed.scopes.contains(scope));
+    if permissions.scopes.is_empty() {
+        return Err(ApiError::Forbidden("no scopes are allowed".to_string()));
+    }
+
+    Ok(Some(
+        create_user_token(state, user_id, DEFAULT_TOKEN_NAME, permissions, None)
+            .await?
+            .token,
+    ))
+}
+
+// What the custom authorizer does with an api key, for when the api runs without api gateway.
+// Only finds hashed tokens, anything in the legacy table has to be migrated first.
+pub async fn authenticate_api_key(state: &AppState, key: &str) -> ApiResult<Option<Identity>> {
+    let token_hash = hash_token(&*state.secrets, key).await?;
+    let token = match state.tokens.find_token(&token_hash).await? {
+        Some(token) => token,
+        None => return Ok(None),
+    };
+
+    let now = unix_timestamp();
+    if token
+        .expires_at
+        .map(|expires_at| expires_at <= now)
+        .unwrap_or(false)
+    {
+        log::info!(
+            "token {} for user {} has expired",
+            token.token_id,
+            token.user_id
+        );
+        return Ok(None);
+    }
+
+    // best effort, like the authorizer
+    if let Err(err) = state
+        .tokens
+        .record_token_use(&token.user_id, &token.token_id, now)
+        .await
+    {
+        log::info!("error recording use of token {}: {:?}", token.token_id, err);
+    }
+
+    Ok(Some(Identity {
+        principal_id: token.user_id,
+        auth_method: AuthMethod::ApiKey,
+        permissions: token.permissions,
+        roles: vec![],
+        token_id: Some(token.token_id),
+        expires_at: token.expires_at,
+    }))
+}
+
+pub async fn hash_token(secrets: &dyn Secrets, token: &str) -> ApiResult<String> {
+    Ok(token_types::hash_token(
+        secrets.token_hash_key().await?,
+        token,
+    ))
+}
+
+pub async fn generate_token(secrets: &dyn Secrets) -> ApiResult<String> {
+    secrets.random_bytes(24).await.map(base64::encode)
+}
+
+pub async fn generate_token_id(secrets: &dyn Secrets) -> ApiResult<String> {
+    secrets.random_bytes(8).await.map(hex::encode)
+}
+
+// Adds a token alongside the user's existing ones.
+pub async fn create_user_token(
+    state: &AppState,
+    user_id: &str,
+    name: &str,
+    permissions: TokenPermissions,
+    expires_in: Option<i64>,
+) -> ApiResult<CreateTokenOutput> {
+    validate_token_name(name)?;
+    validate_token_permissions(&permissions)?;
+    let created_at = unix_timestamp();
+    let expires_at = token_expires_at(created_at, expires_in)?;
+
+    let secrets = &*state.secrets;
+    let secret = generate_token(secrets).await?;
+    let token = Token {
+        user_id: user_id.to_owned(),
+        token_id: generate_token_id(secrets).await?,
+        name: name.to_owned(),
+        token_hash: hash_token(secrets, &secret).await?,
+        permissions,
+        created_at: Some(created_at),
+        last_used_at: None,
+        expires_at,
+    };
+    state.tokens.put_token(&token).await?;
+    log::info!(
+        "created token {} {:?} for user {}",
+        token.token_id,
+        token.name,
+        user_id
+    );
+
+    Ok(CreateTokenOutput {
+        id: token.token_id,
+        name: token.name,
+        token: secret,
+        scopes: token.permissions.scopes,
+        crates: token.permissions.crates,
+        created_at: token.created_at,
+        expires_at: token.expires_at,
+    })
+}
+
+pub async fn list_tokens(state: &AppState, user_id: &str) -> ApiResult<ListTokensOutput> {
+    let tokens = state.tokens.get_tokens(user_id).await?;
+
+    Ok(ListTokensOutput {
+        tokens: tokens.iter().map(Token::info).collect(),
+    })
+}
+
+pub async fn revoke_token(
+    state: &AppState,
+    user_id: &str,
+    token_id: &str,
+) -> ApiResult<RevokeTokenOutput> {
+    state.tokens.delete_token(user_id, token_id).await?;
+    log::info!("revoked token {} for user {}", token_id, user_id);
+
+    Ok(RevokeTokenOutput { ok: true })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::Config;
+    use api_types::tokens::TokenScope;
+
+    #[test]
+    fn test_token_item_round_trip() {
+        let token = Token {
+            user_id: "user".to_string(),
+            token_id: "0123456789abcdef".to_string(),
+            name: "ci".to_string(),
+            token_hash: "0123".to_string(),
+            permissions: TokenPermissions {
+                scopes: vec![TokenScope::Publish],
+                crates: vec!["foo-*".to_string()],
+            },
+            created_at: Some(1000),
+            last_used_at: None,
+            expires_at: Some(2000),
+        };
+        assert_eq!(
+            Token::from_item(&token.to_item()).expect("from_item"),
+            token
+        );
+
+        let info = token.info();
+        assert_eq!(info.id, "0123456789abcdef");
+        assert_eq!(info.name, "ci");
+        assert_eq!(info.scopes, vec![TokenScope::Publish]);
+
+        let mut item = token.to_item();
+        item.remove("scopes");
+        item.remove("crates");
+        assert_eq!(
+            Token::from_item(&item).expect("from_item").permissions,
+            TokenPermissions::default()
+        );
+    }
+
+    #[test]
+    fn test_validate_token_permissions() {
+        assert!(validate_token_permissions(&TokenPermissions::default()).is_ok());
+        assert!(validate_token_permissions(&TokenPermissions {
+            scopes: vec![],
+            crates: vec![],
+        })
+        .is_err());
+        assert!(validate_token_permissions(&TokenPermissions {
+            scopes: vec![TokenScope::Read],
+            crates: vec!["foo/bar".to_string()],
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn test_token_expiry() {
+        assert_eq!(token_expires_at(1000, None).expect("no expiry"), None);
+        assert_eq!(
+            token_expires_at(1000, Some(60)).expect("expiry"),
+            Some(1060)
+        );
+        assert!(token_expires_at(1000, Some(0)).is_err());
+        assert!(token_expires_at(1000, Some(-60)).is_err());
+        assert!(token_expires_at(1000, Some(MAX_TOKEN_EXPIRES_IN + 1)).is_err());
+
+        let now = 1_600_000_000;
+        assert_eq!(expiry_warning(now + 30 * SECONDS_PER_DAY, now, 7), None);
+        assert_eq!(
+            expiry_warning(now + SECONDS_PER_DAY, now, 7).as_deref(),
+            Some("299 wagon \"api token expires Mon, 14 Sep 2020 12:26:40 GMT\"")
+        );
+    }
+
+    #[test]
+    fn test_validate_token_name() {
+        assert!(validate_token_name("laptop").is_ok());
+        assert!(validate_token_name("github actions").is_ok());
+        assert!(validate_token_name("").is_err());
+        assert!(validate_token_name("  ").is_err());
+        assert!(validate_token_name("bad\nname").is_err());
+        assert!(validate_token_name(&"x".repeat(MAX_TOKEN_NAME_LENGTH + 1)).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_user_tokens_in_memory() {
+        let state = AppState::in_memory(Config::default());
+
+        let all = TokenPermissions::default();
+        let default = get_or_create_token(&state, "user", &all)
+            .await
+            .expect("create");
+        assert!(default.is_some());
+        assert_eq!(
+            get_or_create_token(&state, "user", &all)
+                .await
+                .expect("get"),
+            None
+        );
+
+        let created =
+            create_user_token(&state, "user", "ci", TokenPermissions::default(), Some(60))
+                .await
+                .expect("create");
+        let stored = state.tokens.get_tokens("user").await.expect("get");
+        let stored = stored
+            .iter()
+            .find(|t| t.token_id == created.id)
+            .expect("stored");
+        assert_eq!(
+            stored.token_hash,
+            hash_token(&*state.secrets, &created.token)
+                .await
+                .expect("hash")
+        );
+        assert_eq!(stored.expires_at, created.created_at.map(|t| t + 60));
+
+        let listed = list_tokens(&state, "user").await.expect("list");
+        assert_eq!(listed.tokens.len(), 2);
+        assert!(list_tokens(&state, "other")
+            .await
+            .expect("list")
+            .tokens
+            .is_empty());
+
+        revoke_token(&state, "user", &created.id)
+            .await
+            .expect("revoke");
+        assert!(matches!(
+            revoke_token(&state, "user", &created.id).await,
+            Err(ApiError::NotFound(_))
+        ));
+        assert_eq!(
+            list_tokens(&state, "user")
+                .await
+                .expect("list")
+                .tokens
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_api_key_in_memory() {
+        let state = AppState::in_memory(Config::default());
+        let permissions = TokenPermissions {
+            scopes: vec![TokenScope::Read],
+            crates: vec![],
+        };
+        let created = create_user_token(&state, "user", "ci", permissions.clone(), None)
+            .await
+            .expect("create");
+
+        let identity = authenticate_api_key(&state, &created.token)
+            .await
+            .expect("authenticate")
+            .expect("identity");
+        assert_eq!(identity.principal_id, "user");
+        assert_eq!(identity.auth_method, AuthMethod::ApiKey);
+        assert_eq!(identity.permissions, permissions);
+        assert_eq!(identity.token_id.as_deref(), Some(&created.id[..]));
+        let tokens = state.tokens.get_tokens("user").await.expect("get");
+        assert!(tokens[0].last_used_at.is_some());
+
+        assert_eq!(
+            authenticate_api_key(&state, "wrong")
+                .await
+                .expect("authenticate"),
+            None
+        );
+
+        let mut expired = tokens[0].clone();
+        expired.expires_at = Some(unix_timestamp() - 1);
+        state.tokens.put_token(&expired).await.expect("put");
+        assert_eq!(
+            authenticate_api_key(&state, &created.token)
+                .await
+                .expect("authenticate"),
+            None
+        );
+    }
+}
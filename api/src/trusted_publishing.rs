@@ -0,0 +1,451 @@
+use api_types::auth::Identity;
+use api_types::tokens::{CreateTokenOutput, TokenPermissions, TokenScope};
+use api_types::trusted_publishing::{
+    AddTrustPolicyOutput, ExchangeTokenInput, GitHubClaims, GitHubTrustPolicy,
+    ListTrustPoliciesOutput, RemoveTrustPolicyOutput,
+};
+use authorizers::jwt::{decode_and_validate_jwt, KeySet, TokenDecoder};
+
+use crate::config::Config;
+use crate::error::ApiError;
+use crate::owners::{self, TrustPolicy};
+use crate::result::ApiResult;
+use crate::state::AppState;
+use crate::tokens;
+use crate::BoxFuture;
+
+pub const GITHUB_ACTIONS_ISSUER: &str = "https://token.actions.githubusercontent.com";
+// Long enough for cargo publish, short enough that a leaked token isn't worth much.
+pub const TRUSTED_PUBLISHING_TOKEN_EXPIRES_IN: i64 = 30 * 60;
+
+// Checks the signature, issuer, audience and expiry of a github actions oidc token.
+pub trait OidcVerifier: Send + Sync {
+    fn verify<'a>(&'a self, jwt: &'a str) -> BoxFuture<'a, ApiResult<GitHubClaims>>;
+}
+
+// Fetches github's signing keys.
+pub struct GitHubOidc {
+    decoder: TokenDecoder,
+}
+
+impl GitHubOidc {
+    pub fn new(audience: &str) -> Self {
+        GitHubOidc {
+            decoder: TokenDecoder::new(
+                &format!("{}/.well-known/openid-configuration", GITHUB_ACTIONS_ISSUER),
+                &[audience.to_owned()],
+            ),
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Option<Box<dyn OidcVerifier>> {
+        config
+            .trusted_publishing_audience
+            .as_ref()
+            .map(|audience| Box::new(GitHubOidc::new(audience)) as Box<dyn OidcVerifier>)
+    }
+}
+
+impl OidcVerifier for GitHubOidc {
+    fn verify<'a>(&'a self, jwt: &'a str) -> BoxFuture<'a, ApiResult<GitHubClaims>> {
+        Box::pin(async move {
+            self.decoder.decode_claims(jwt).await.map_err(|err| {
+                log::info!("github oidc token rejected: {:?}", err);
+                ApiError::NotAuthorized("invalid github oidc token".to_string())
+            })
+        })
+    }
+}
+
+// Keys known up front rather than fetched, eg. for tests.
+pub struct KeySetOidc {
+    pub key_set: KeySet,
+    pub issuer: String,
+    pub audiences: Vec<String>,
+}
+
+impl OidcVerifier for KeySetOidc {
+    fn verify<'a>(&'a self, jwt: &'a str) -> BoxFuture<'a, ApiResult<GitHubClaims>> {
+        Box::pin(async move {
+            decode_and_validate_jwt(jwt, &self.key_set, &self.audiences, &self.issuer).map_err(
+                |err| {
+                    log::info!("oidc token rejected: {:?}", err);
+                    ApiError::NotAuthorized("invalid github oidc token".to_string())
+                },
+            )
+        })
+    }
+}
+
+pub async fn list_trust_policies(
+    state: &AppState,
+    name: &str,
+) -> ApiResult<ListTrustPoliciesOutput> {
+    Ok(ListTrustPoliciesOutput {
+        policies: state
+            .owners
+            .get_trust_policies(name)
+            .await?
+            .iter()
+            .map(TrustPolicy::info)
+            .collect(),
+    })
+}
+
+pub async fn add_trust_policy(
+    state: &AppState,
+    identity: &Identity,
+    name: &str,
+    policy: GitHubTrustPolicy,
+) -> ApiResult<AddTrustPolicyOutput> {
+    owners::check_owner_or_admin(state, identity, name).await?;
+    let principal_id = identity.principal_id.as_str();
+
+    let is_id = |id: &str| !id.is_empty() && id.chars().all(|c| c.is_ascii_digit());
+    if policy.repository.split('/').count() != 2
+        || !is_id(&policy.repository_id)
+        || !is_id(&policy.repository_owner_id)
+        || policy.workflow.is_empty()
+        || policy.workflow.contains('/')
+    {
+        return Err(ApiError::InvalidInput(
+            "a trust policy needs an owner/repo repository, the repository and owner ids, and a workflow file name"
+                .to_string(),
+        ));
+    }
+
+    let policy = TrustPolicy::new(name, policy, principal_id);
+    if state.owners.put_trust_policy(&policy).await? {
+        log::info!(
+            "{} added trust policy {} to {}: {:?}",
+            principal_id,
+            policy.id,
+            name,
+            policy.policy
+        );
+    }
+
+    Ok(AddTrustPolicyOutput {
+        ok: true,
+        id: policy.id,
+    })
+}
+
+pub async fn remove_trust_policy(
+    state: &AppState,
+    identity: &Identity,
+    name: &str,
+    id: &str,
+) -> ApiResult<RemoveTrustPolicyOutput> {
+    owners::check_owner_or_admin(state, identity, name).await?;
+    let principal_id = identity.principal_id.as_str();
+
+    state.owners.delete_trust_policy(name, id).await?;
+    log::info!("{} removed trust policy {} from {}", principal_id, id, name);
+    Ok(RemoveTrustPolicyOutput { ok: true })
+}
+
+// Swaps a github actions oidc token for a short lived token that can only publish the crate.
+// The token belongs to the matching policy rather than to anyone, and only works while
+// whoever added the policy is still an owner.
+pub async fn exchange_token(
+    state: &AppState,
+    input: &ExchangeTokenInput,
+) -> ApiResult<CreateTokenOutput> {
+    let verifier = state
+        .oidc_verifier
+        .as_ref()
+        .ok_or_else(|| ApiError::NotFound("trusted publishing is not enabled".to_string()))?;
+    let claims = verifier.verify(&input.jwt).await?;
+    let name = &input.crate_name;
+
+    let policy = state
+        .owners
+        .get_trust_policies(name)
+        .await?
+        .into_iter()
+        .find(|policy| policy.policy.matches(&claims))
+        .ok_or_else(|| {
+            ApiError::Forbidden(format!(
+                "{} is not a trusted publisher of crate {}",
+                claims.sub, name
+            ))
+        })?;
+    owners::check_policy_added_by_owner(state, &policy).await?;
+
+    log::info!(
+        "trust policy {} of {} matched {} {:?}",
+        policy.id,
+        name,
+        claims.workflow_ref,
+        claims.jti
+    );
+    tokens::create_user_token(
+        state,
+        &format!("{}{}", owners::TRUSTED_PUBLISHER_PREFIX, policy.id),
+        &format!("trusted publishing {}", policy.id),
+        TokenPermissions {
+            scopes: vec![TokenScope::Publish],
+            crates: vec![name.to_owned()],
+        },
+        Some(TRUSTED_PUBLISHING_TOKEN_EXPIRES_IN),
+    )
+    .await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::owners::Owner;
+    use api_types::auth::{AuthMethod, Role};
+    use jsonwebtoken::{Algorithm, EncodingKey, Header};
+    use lambda_http::http;
+    use serde_json::json;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    const AUD: &str = "https://wagon.example.com";
+
+    fn state() -> AppState {
+        AppState::in_memory(Config::default()).with_oidc_verifier(Box::new(KeySetOidc {
+            key_set: serde_json::from_str(include_str!(
+                "../../authorizers/resources/test/jwks.json"
+            ))
+            .expect("jwks"),
+            issuer: GITHUB_ACTIONS_ISSUER.to_string(),
+            audiences: vec![AUD.to_string()],
+        }))
+    }
+
+    fn github_jwt(
+        aud: &str,
+        repository: &str,
+        workflow_ref: &str,
+        environment: Option<&str>,
+    ) -> String {
+        let key =
+            EncodingKey::from_rsa_pem(include_bytes!("../../authorizers/resources/test/rsa.pem"))
+                .expect("encoding key");
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some("test-rs256".to_string());
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("time")
+            .as_secs();
+
+        jsonwebtoken::encode(
+            &header,
+            &json!({
+                "sub": format!("repo:{}:ref:refs/tags/v1.0.0", repository),
+                "iss": GITHUB_ACTIONS_ISSUER,
+                "aud": aud,
+                "exp": now + 300,
+                "iat": now,
+                "jti": "jti",
+                "repository": repository,
+                "repository_id": "123",
+                "repository_owner_id": "456",
+                "workflow_ref": workflow_ref,
+                "environment": environment,
+                "ref": "refs/tags/v1.0.0",
+            }),
+            &key,
+        )
+        .expect("encode")
+    }
+
+    fn policy() -> GitHubTrustPolicy {
+        GitHubTrustPolicy {
+            repository: "octo-org/octo-repo".to_string(),
+            repository_id: "123".to_string(),
+            repository_owner_id: "456".to_string(),
+            workflow: "release.yml".to_string(),
+            environment: Some("release".to_string()),
+            git_ref: Some("refs/tags/v*".to_string()),
+        }
+    }
+
+    fn exchange(jwt: String) -> ExchangeTokenInput {
+        ExchangeTokenInput {
+            jwt,
+            crate_name: "foo".to_string(),
+        }
+    }
+
+    fn identity(principal_id: &str) -> Identity {
+        Identity::new(principal_id, AuthMethod::BearerToken)
+    }
+
+    const WORKFLOW_REF: &str = "octo-org/octo-repo/.github/workflows/release.yml@refs/tags/v1.0.0";
+
+    #[tokio::test]
+    async fn test_trust_policies() {
+        let state = state();
+        state
+            .owners
+            .put_owner(&Owner::new("foo", "alice", "alice"))
+            .await
+            .expect("owner");
+
+        let err = add_trust_policy(&state, &identity("bob"), "foo", policy())
+            .await
+            .expect_err("not an owner");
+        assert_eq!(err.status(), http::StatusCode::FORBIDDEN);
+
+        let added = add_trust_policy(&state, &identity("alice"), "foo", policy())
+            .await
+            .expect("add");
+        let again = add_trust_policy(&state, &identity("alice"), "foo", policy())
+            .await
+            .expect("add again");
+        assert_eq!(added.id, again.id);
+
+        let listed = list_trust_policies(&state, "foo").await.expect("list");
+        assert_eq!(listed.policies.len(), 1);
+        assert_eq!(listed.policies[0].policy, policy());
+        assert_eq!(listed.policies[0].added_by.as_deref(), Some("alice"));
+        // policies aren't owners
+        assert_eq!(
+            owners::get_owner_ids(&state, "foo").await.expect("owners"),
+            vec!["alice"]
+        );
+
+        let mut admin = identity("carol");
+        admin.roles = vec![Role::Admin];
+        remove_trust_policy(&state, &admin, "foo", &added.id)
+            .await
+            .expect("remove");
+        let err = remove_trust_policy(&state, &identity("alice"), "foo", &added.id)
+            .await
+            .expect_err("already removed");
+        assert_eq!(err.status(), http::StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_exchange_token() {
+        let state = state();
+        state
+            .owners
+            .put_owner(&Owner::new("foo", "alice", "alice"))
+            .await
+            .expect("owner");
+        add_trust_policy(&state, &identity("alice"), "foo", policy())
+            .await
+            .expect("add");
+
+        let created = exchange_token(
+            &state,
+            &exchange(github_jwt(
+                AUD,
+                "octo-org/octo-repo",
+                WORKFLOW_REF,
+                Some("release"),
+            )),
+        )
+        .await
+        .expect("exchange");
+        assert_eq!(created.scopes, vec![TokenScope::Publish]);
+        assert_eq!(created.crates, vec!["foo".to_string()]);
+        let expires_in =
+            created.expires_at.expect("expires_at") - created.created_at.expect("created_at");
+        assert_eq!(expires_in, TRUSTED_PUBLISHING_TOKEN_EXPIRES_IN);
+
+        let identity = tokens::authenticate_api_key(&state, &created.token)
+            .await
+            .expect("authenticate")
+            .expect("identity");
+        let policy_id = list_trust_policies(&state, "foo")
+            .await
+            .expect("list")
+            .policies[0]
+            .id
+            .clone();
+        assert_eq!(
+            identity.principal_id,
+            format!("{}{}", owners::TRUSTED_PUBLISHER_PREFIX, policy_id)
+        );
+        assert!(state
+            .tokens
+            .get_tokens("alice")
+            .await
+            .expect("get")
+            .is_empty());
+        let new_crate = owners::check_publisher(&state, &identity.principal_id, "foo")
+            .await
+            .expect("publisher");
+        assert!(!new_crate);
+        let err = owners::check_publisher(&state, &identity.principal_id, "bar")
+            .await
+            .expect_err("another crate");
+        assert_eq!(err.status(), http::StatusCode::FORBIDDEN);
+        assert!(identity
+            .permissions
+            .allows(TokenScope::Publish, Some("foo")));
+        assert!(!identity
+            .permissions
+            .allows(TokenScope::Publish, Some("bar")));
+        assert!(!identity.permissions.allows(TokenScope::Yank, Some("foo")));
+
+        for jwt in vec![
+            github_jwt(
+                "another registry",
+                "octo-org/octo-repo",
+                WORKFLOW_REF,
+                Some("release"),
+            ),
+            github_jwt(AUD, "octo-org/octo-repo", WORKFLOW_REF, None),
+            github_jwt(
+                AUD,
+                "octo-org/octo-repo",
+                "octo-org/octo-repo/.github/workflows/ci.yml@refs/tags/v1.0.0",
+                Some("release"),
+            ),
+            github_jwt(
+                AUD,
+                "evil-org/octo-repo",
+                "evil-org/octo-repo/.github/workflows/release.yml@refs/tags/v1.0.0",
+                Some("release"),
+            ),
+            "not a jwt".to_string(),
+        ] {
+            assert!(exchange_token(&state, &exchange(jwt)).await.is_err());
+        }
+
+        // tokens are only handed out for policies added by current owners
+        state
+            .owners
+            .put_owner(&Owner::new("foo", "bob", "alice"))
+            .await
+            .expect("owner");
+        state
+            .owners
+            .delete_owner("foo", "alice")
+            .await
+            .expect("delete owner");
+        let err = exchange_token(
+            &state,
+            &exchange(github_jwt(
+                AUD,
+                "octo-org/octo-repo",
+                WORKFLOW_REF,
+                Some("release"),
+            )),
+        )
+        .await
+        .expect_err("former owner");
+        assert_eq!(err.status(), http::StatusCode::FORBIDDEN);
+        // and tokens already handed out stop working
+        let err = owners::check_publisher(&state, &identity.principal_id, "foo")
+            .await
+            .expect_err("former owner's policy");
+        assert_eq!(err.status(), http::StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_exchange_token_disabled() {
+        let state = AppState::in_memory(Config::default());
+        let err = exchange_token(&state, &exchange("jwt".to_string()))
+            .await
+            .expect_err("disabled");
+        assert_eq!(err.status(), http::StatusCode::NOT_FOUND);
+    }
+}
@@ -1,22 +1,19 @@
 use authorizers::error::AuthError;
-use authorizers::iam::{policy_builder_for_method, Method};
-use authorizers::jwt::TokenDecoder;
+use authorizers::iam::policy_builder_for_method;
+use authorizers::jwt::TrustedIssuers;
 use authorizers::result::AuthResult;
 use authorizers::token::{lookup_token, AuthorizationHeader};
 use authorizers::{authorizer_handler, Authenticator, BoxFuture, Claims};
+use api_types::auth::{AuthMethod, Identity};
 use aws_lambda_events::apigw;
 use env_logger;
 use lambda_runtime::{run, service_fn, Error, LambdaEvent};
 use lazy_static::lazy_static;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
-use std::env;
 
 lazy_static! {
-    static ref TOKEN_DECODER: TokenDecoder = TokenDecoder::new(
-        &env::var("OPENID_CONFIGURATION_URI").unwrap(),
-        &env::var("OPENID_AUD").unwrap()
-    );
+    static ref TRUSTED_ISSUERS: TrustedIssuers = TrustedIssuers::from_env().unwrap();
 }
 
 #[tokio::main]
@@ -43,22 +40,29 @@ impl Authenticator for TokenAuthenticator {
         Box::pin(async move {
             match AuthorizationHeader::from_request(event) {
                 AuthorizationHeader::BearerToken(token) => {
-                    let id_token = TOKEN_DECODER.decode(&token).await?;
+                    let (identity, _id_token) = TRUSTED_ISSUERS.authenticate(&token).await?;
                     Ok(Claims {
-                        principal_id: id_token.sub,
-                        scopes: vec!["user", "api"],
+                        roles: identity.roles.clone(),
+                        identity,
                     })
                 }
                 AuthorizationHeader::ApiKey(key) => {
-                    let principal_id = lookup_token(&key)
+                    let token = lookup_token(&key)
                         .await?
                         .ok_or_else(|| AuthError::ApiKeyError("api key not found".to_string()))?;
                     Ok(Claims {
-                        principal_id: principal_id,
-                        scopes: vec!["api"],
+                        roles: vec![],
+                        identity: Identity {
+                            principal_id: token.user_id,
+                            auth_method: AuthMethod::ApiKey,
+                            permissions: token.permissions,
+                            roles: vec![],
+                            token_id: token.token_id,
+                            expires_at: token.expires_at,
+                        },
                     })
                 }
-                _other => Err(AuthError::InputError(format!("bearer token expected"))),
+                _other => Err(AuthError::InputError("bearer token expected".to_string())),
             }
         })
     }
@@ -70,13 +74,8 @@ impl Authenticator for TokenAuthenticator {
         Box::pin(async move {
             let mut builder = policy_builder_for_method(&event);
 
-            if claims.scopes.contains(&"user") {
-                builder = builder.allow_method(Method::All, "/api/token")?;
-            }
-
-            if claims.scopes.contains(&"api") {
-                builder = builder.allow_method(Method::All, "/api/v1/*")?;
-            }
+            builder = builder.allow_roles(&claims.roles)?;
+            builder = builder.allow_permissions(&claims.identity.permissions)?;
 
             Ok(builder.build())
         })
@@ -9,8 +9,8 @@ async fn main() -> Result<()> {
     env_logger::init();
     let args: Vec<String> = env::args().collect();
     if let Some(token) = args.get(1) {
-        let user_id = lookup_token(&token).await?;
-        println!("user_id: {:?}", user_id);
+        let token = lookup_token(&token).await?;
+        println!("token: {:?}", token);
     } else {
         println!("usage: lookup_token <token>");
     }
@@ -2,6 +2,8 @@ use serde::{Serialize, Deserialize};
 use serde_json;
 use aws_lambda_events::event::apigw;
 use crate::result::AuthResult;
+use api_types::auth::Role;
+use api_types::tokens::{TokenPermissions, TokenScope};
 
 pub static POLICY_VERSION: &str = "2012-10-17"; // override if necessary
 
@@ -20,7 +22,7 @@ pub enum Method {
     Get,
     #[serde(rename = "POST")]
     Post,
-    #[serde(rename = "*PUT")]
+    #[serde(rename = "PUT")]
     Put,
     #[serde(rename = "DELETE")]
     Delete,
@@ -101,6 +103,65 @@ impl ApiGatewayCustomAuthorizerPolicyBuilder {
         self.add_method(Effect::Deny, method, resource)
     }
 
+    // Crate globs become wildcards in the resource arns. Publishing can't be limited
+    // to crates here because the name is in the body, the api checks it again.
+    pub fn allow_permissions(mut self, permissions: &TokenPermissions) -> AuthResult<Self> {
+        let crates = if permissions.crates.is_empty() {
+            vec!["*".to_string()]
+        } else {
+            permissions.crates.clone()
+        };
+
+        for scope in permissions.scopes.iter() {
+            match scope {
+                TokenScope::Read => {
+                    self = self.allow_method(Method::Get, "/api/v1/crates")?;
+                    self = self.allow_method(Method::Get, "/index/*")?;
+                    for name in crates.iter() {
+                        self = self.allow_method(Method::Get, format!("/api/v1/crates/{}/*/download", name))?;
+                        self = self.allow_method(Method::Get, format!("/api/v1/crates/{}/owners", name))?;
+                        self = self.allow_method(Method::Get, format!("/api/v1/crates/{}/trusted_publishers", name))?;
+                    }
+                }
+                TokenScope::Publish => {
+                    self = self.allow_method(Method::Put, "/api/v1/crates/new")?;
+                }
+                TokenScope::Yank => {
+                    for name in crates.iter() {
+                        self = self.allow_method(Method::Delete, format!("/api/v1/crates/{}/*/yank", name))?;
+                        self = self.allow_method(Method::Put, format!("/api/v1/crates/{}/*/unyank", name))?;
+                    }
+                }
+                TokenScope::Owners => {
+                    for name in crates.iter() {
+                        self = self.allow_method(Method::Put, format!("/api/v1/crates/{}/owners", name))?;
+                        self = self.allow_method(Method::Delete, format!("/api/v1/crates/{}/owners", name))?;
+                        self = self.allow_method(Method::Put, format!("/api/v1/crates/{}/trusted_publishers", name))?;
+                        self = self.allow_method(Method::Delete, format!("/api/v1/crates/{}/trusted_publishers/*", name))?;
+                    }
+                }
+            }
+        }
+
+        Ok(self)
+    }
+
+    // Any signed in user can manage their tokens. Admins can call anything, the api
+    // still checks what they do with it.
+    pub fn allow_roles(mut self, roles: &[Role]) -> AuthResult<Self> {
+        if roles.contains(&Role::Admin) {
+            return Ok(self.allow_all_methods());
+        }
+
+        if !roles.is_empty() {
+            self = self.allow_method(Method::All, "/api/token")?;
+            self = self.allow_method(Method::All, "/api/tokens")?;
+            self = self.allow_method(Method::All, "/api/tokens/*")?;
+        }
+
+        Ok(self)
+    }
+
     // Creates and executes a new child thread.
     pub fn build(self) -> apigw::ApiGatewayCustomAuthorizerPolicy {
         self.policy
@@ -163,4 +224,66 @@ mod test {
         let policy_str = serde_json::to_string(&policy).expect("to_json");
         assert_eq!(policy_str, r#"{"Version":"2012-10-17","Statement":[{"Action":["execute-api:Invoke"],"Effect":"Allow","Resource":["arn:aws:execute-api:region:account_id:rest_api_id/stage/GET/api/token"]}]}"#);
     }
+
+    #[test]
+    fn test_allow_permissions_policy() {
+        let permissions = TokenPermissions {
+            scopes: vec![TokenScope::Publish, TokenScope::Yank],
+            crates: vec!["foo-*".to_string()],
+        };
+        let policy = ApiGatewayCustomAuthorizerPolicyBuilder::new("region", "account_id", "rest_api_id", "stage")
+            .allow_permissions(&permissions)
+            .expect("allow")
+            .build();
+        let resources: Vec<_> = policy.statement.iter().flat_map(|stmt| stmt.resource.clone()).collect();
+        assert_eq!(resources, vec![
+            "arn:aws:execute-api:region:account_id:rest_api_id/stage/PUT/api/v1/crates/new".to_string(),
+            "arn:aws:execute-api:region:account_id:rest_api_id/stage/DELETE/api/v1/crates/foo-*/*/yank".to_string(),
+            "arn:aws:execute-api:region:account_id:rest_api_id/stage/PUT/api/v1/crates/foo-*/*/unyank".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn test_allow_owners_policy() {
+        let permissions = TokenPermissions {
+            scopes: vec![TokenScope::Owners],
+            crates: vec!["foo".to_string()],
+        };
+        let policy = ApiGatewayCustomAuthorizerPolicyBuilder::new("region", "account_id", "rest_api_id", "stage")
+            .allow_permissions(&permissions)
+            .expect("allow")
+            .build();
+        let resources: Vec<_> = policy.statement.iter().flat_map(|stmt| stmt.resource.clone()).collect();
+        assert_eq!(resources, vec![
+            "arn:aws:execute-api:region:account_id:rest_api_id/stage/PUT/api/v1/crates/foo/owners".to_string(),
+            "arn:aws:execute-api:region:account_id:rest_api_id/stage/DELETE/api/v1/crates/foo/owners".to_string(),
+            "arn:aws:execute-api:region:account_id:rest_api_id/stage/PUT/api/v1/crates/foo/trusted_publishers".to_string(),
+            "arn:aws:execute-api:region:account_id:rest_api_id/stage/DELETE/api/v1/crates/foo/trusted_publishers/*".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn test_allow_roles_policy() {
+        let builder = || ApiGatewayCustomAuthorizerPolicyBuilder::new("region", "account_id", "rest_api_id", "stage");
+        let resources = |roles: &[Role]| -> Vec<String> {
+            builder()
+                .allow_roles(roles)
+                .expect("allow")
+                .build()
+                .statement
+                .iter()
+                .flat_map(|stmt| stmt.resource.clone())
+                .collect()
+        };
+
+        assert!(resources(&[]).is_empty());
+        assert_eq!(resources(&[Role::Reader]), vec![
+            "arn:aws:execute-api:region:account_id:rest_api_id/stage/*/api/token".to_string(),
+            "arn:aws:execute-api:region:account_id:rest_api_id/stage/*/api/tokens".to_string(),
+            "arn:aws:execute-api:region:account_id:rest_api_id/stage/*/api/tokens/*".to_string(),
+        ]);
+        assert_eq!(resources(&[Role::Publisher, Role::Admin]), vec![
+            "arn:aws:execute-api:region:account_id:rest_api_id/stage/*/*".to_string(),
+        ]);
+    }
 }
@@ -0,0 +1,276 @@
+use super::types::KeySet;
+use crate::error::AuthError;
+use crate::result::AuthResult;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// How long keys are kept when the jwks response doesn't say.
+pub const DEFAULT_KEY_SET_MAX_AGE: Duration = Duration::from_secs(60 * 60);
+// Tokens with made up kids shouldn't have us hammering the jwks endpoint.
+pub const DEFAULT_MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+// The identity provider's signing keys, refetched when they expire or when a token
+// turns up signed with a kid we haven't seen, which is what happens after a rotation.
+// If the jwks endpoint is down we carry on with the keys we have.
+pub struct KeySetCache {
+    pub default_max_age: Duration,
+    pub min_refresh_interval: Duration,
+    // only ever locked briefly, never across a fetch
+    state: Mutex<CacheState>,
+    // held while fetching so concurrent requests share one fetch
+    fetching: tokio::sync::Mutex<()>,
+}
+
+#[derive(Default)]
+struct CacheState {
+    key_set: Option<Arc<KeySet>>,
+    expires_at: Option<Instant>,
+    // successful or not
+    last_fetch: Option<Instant>,
+}
+
+impl CacheState {
+    fn knows(&self, kid: &str) -> bool {
+        self.key_set
+            .as_ref()
+            .map(|key_set| key_set.find_key(kid).is_some())
+            .unwrap_or(false)
+    }
+
+    fn fresh(&self, now: Instant) -> bool {
+        self.expires_at.map(|t| now < t).unwrap_or(false)
+    }
+}
+
+impl Default for KeySetCache {
+    fn default() -> Self {
+        KeySetCache::new(DEFAULT_KEY_SET_MAX_AGE, DEFAULT_MIN_REFRESH_INTERVAL)
+    }
+}
+
+impl KeySetCache {
+    pub fn new(default_max_age: Duration, min_refresh_interval: Duration) -> Self {
+        KeySetCache {
+            default_max_age,
+            min_refresh_interval,
+            state: Mutex::new(CacheState::default()),
+            fetching: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    // Returns keys which should include the kid, fetching them if need be.
+    // While another request is fetching, stale keys that include the kid are
+    // served rather than waiting for it.
+    pub async fn get<F, Fut>(&self, kid: &str, now: Instant, fetch: F) -> AuthResult<Arc<KeySet>>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = AuthResult<(KeySet, Option<Duration>)>>,
+    {
+        if let Some(key_set) = self.cached(kid, now) {
+            return Ok(key_set);
+        }
+
+        let _fetching = match self.fetching.try_lock() {
+            Ok(guard) => guard,
+            Err(_) => {
+                if let Some(key_set) = self.known(kid) {
+                    return Ok(key_set);
+                }
+                let guard = self.fetching.lock().await;
+                // whoever was fetching may have got what we need
+                if let Some(key_set) = self.cached(kid, now) {
+                    return Ok(key_set);
+                }
+                guard
+            }
+        };
+
+        let may_fetch = {
+            let mut state = self.state.lock().unwrap();
+            let may_fetch = state
+                .last_fetch
+                .map(|t| now.saturating_duration_since(t) >= self.min_refresh_interval)
+                .unwrap_or(true);
+            if may_fetch {
+                state.last_fetch = Some(now);
+            }
+            may_fetch
+        };
+
+        if may_fetch {
+            match fetch().await {
+                Ok((key_set, max_age)) => {
+                    log::info!("fetched {} signing keys", key_set.keys.len());
+                    let mut state = self.state.lock().unwrap();
+                    state.key_set = Some(Arc::new(key_set));
+                    state.expires_at = Some(now + max_age.unwrap_or(self.default_max_age));
+                }
+                Err(err) if self.state.lock().unwrap().key_set.is_some() => {
+                    log::warn!("error fetching signing keys, using stale keys: {:?}", err);
+                }
+                Err(err) => return Err(err),
+            }
+        } else if self.known(kid).is_none() {
+            log::info!(
+                "unknown jwt kid {}, keys were fetched too recently to refetch",
+                kid
+            );
+        }
+
+        self.state
+            .lock()
+            .unwrap()
+            .key_set
+            .clone()
+            .ok_or_else(|| AuthError::JwtError("no signing keys available".to_string()))
+    }
+
+    // Unexpired keys including the kid.
+    fn cached(&self, kid: &str, now: Instant) -> Option<Arc<KeySet>> {
+        let state = self.state.lock().unwrap();
+        if state.fresh(now) && state.knows(kid) {
+            state.key_set.clone()
+        } else {
+            None
+        }
+    }
+
+    // Keys including the kid, however old.
+    fn known(&self, kid: &str) -> Option<Arc<KeySet>> {
+        let state = self.state.lock().unwrap();
+        if state.knows(kid) {
+            state.key_set.clone()
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::jwt::types::{Key, RSAKey};
+    use std::cell::Cell;
+
+    fn key_set(kids: &[&str]) -> KeySet {
+        KeySet {
+            keys: kids
+                .iter()
+                .map(|kid| {
+                    Key::RSA(RSAKey {
+                        alg: Some("RS256".to_string()),
+                        e: "AQAB".to_string(),
+                        kid: kid.to_string(),
+                        n: "n".to_string(),
+                        use_: Some("sig".to_string()),
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    fn kids(key_set: &KeySet) -> Vec<&str> {
+        key_set.keys.iter().map(|k| k.kid()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_key_set_cache() {
+        let cache = KeySetCache::new(Duration::from_secs(600), Duration::from_secs(60));
+        let start = Instant::now();
+        let fetches = Cell::new(0);
+        let fetch = |kids: &'static [&'static str], max_age: Option<u64>| {
+            fetches.set(fetches.get() + 1);
+            async move { Ok((key_set(kids), max_age.map(Duration::from_secs))) }
+        };
+
+        let keys = cache
+            .get("a", start, || fetch(&["a"], None))
+            .await
+            .expect("first fetch");
+        assert_eq!(kids(&keys), vec!["a"]);
+
+        // cached
+        cache
+            .get("a", start + Duration::from_secs(10), || {
+                fetch(&["a", "b"], None)
+            })
+            .await
+            .expect("cached");
+        assert_eq!(fetches.get(), 1);
+
+        // unknown kids are only refetched once the refresh interval has passed
+        let keys = cache
+            .get("b", start + Duration::from_secs(30), || {
+                fetch(&["a", "b"], None)
+            })
+            .await
+            .expect("rate limited");
+        assert_eq!(kids(&keys), vec!["a"]);
+        assert_eq!(fetches.get(), 1);
+
+        let keys = cache
+            .get("b", start + Duration::from_secs(61), || {
+                fetch(&["a", "b"], Some(120))
+            })
+            .await
+            .expect("rotated");
+        assert_eq!(kids(&keys), vec!["a", "b"]);
+        assert_eq!(fetches.get(), 2);
+
+        // cache-control max-age replaces the default
+        let keys = cache
+            .get("b", start + Duration::from_secs(200), || {
+                fetch(&["b"], None)
+            })
+            .await
+            .expect("expired");
+        assert_eq!(kids(&keys), vec!["b"]);
+        assert_eq!(fetches.get(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_key_set_cache_single_flight() {
+        let cache = KeySetCache::default();
+        let start = Instant::now();
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+
+        let first = cache.get("a", start, || async move {
+            rx.await.expect("released");
+            Ok((key_set(&["a"]), None))
+        });
+        // waits for the first fetch instead of making its own
+        let second = cache.get("a", start, || async {
+            Err(AuthError::JwtError("second fetch".to_string()))
+        });
+        let release = async move {
+            tx.send(()).expect("send");
+        };
+
+        let (first, second, _) = futures::join!(first, second, release);
+        assert_eq!(kids(&first.expect("first")), vec!["a"]);
+        assert_eq!(kids(&second.expect("second")), vec!["a"]);
+    }
+
+    #[tokio::test]
+    async fn test_key_set_cache_serves_stale_keys() {
+        let cache = KeySetCache::new(Duration::from_secs(600), Duration::from_secs(60));
+        let start = Instant::now();
+        let unavailable = || async { Err(AuthError::JwtError("unavailable".to_string())) };
+
+        assert!(cache.get("a", start, unavailable).await.is_err());
+
+        cache
+            .get("a", start + Duration::from_secs(60), || async {
+                Ok((key_set(&["a"]), None))
+            })
+            .await
+            .expect("fetch");
+
+        let keys = cache
+            .get("a", start + Duration::from_secs(3600), unavailable)
+            .await
+            .expect("stale");
+        assert_eq!(kids(&keys), vec!["a"]);
+    }
+}
@@ -1,8 +1,10 @@
 use crate::result::AuthResult;
+use super::keys::HTTP_CLIENT;
 use super::types::OpenIdConfiguration;
 
 pub async fn fetch_config(uri: &str) -> AuthResult<OpenIdConfiguration> {
-    let resp = reqwest::get(uri)
+    let resp = HTTP_CLIENT.get(uri)
+        .send()
         .await?
         .json::<OpenIdConfiguration>()
         .await?;
@@ -0,0 +1,404 @@
+use super::{roles_for, IdToken, RoleRule, TokenDecoder, ValidationProfile};
+use crate::error::AuthError;
+use crate::result::AuthResult;
+use api_types::auth::{AuthMethod, Identity, Role};
+use api_types::tokens::TokenScope;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+
+// An identity provider whose tokens we accept, eg. cognito for people
+// and github actions for ci.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct IssuerConfig {
+    pub openid_configuration_uri: String,
+    // The iss claim this issuer's tokens have. Taken from the openid configuration if not set.
+    #[serde(default)]
+    pub issuer: Option<String>,
+    pub audiences: Vec<String>,
+    // The claim that becomes the principal id, and what it's prefixed with
+    // so different issuers' subjects can't collide.
+    #[serde(default = "default_principal_claim")]
+    pub principal_claim: String,
+    #[serde(default)]
+    pub principal_prefix: String,
+    // What the issuer's tokens can be used for, narrowed down by their roles.
+    #[serde(default = "default_scopes")]
+    pub scopes: Vec<TokenScope>,
+    #[serde(default)]
+    pub role_rules: Vec<RoleRule>,
+    // For tokens no rule matches.
+    #[serde(default)]
+    pub default_roles: Vec<Role>,
+    // Claims every token must have one of the values of, eg. github actions tokens'
+    // repository_owner, as anyone can get a token from a public issuer.
+    #[serde(default)]
+    pub required_claim_values: BTreeMap<String, Vec<String>>,
+    // Whether the tokens are from people signing in. Only they can create api tokens.
+    #[serde(default)]
+    pub interactive: bool,
+    #[serde(default)]
+    pub validation: ValidationProfile,
+}
+
+fn default_principal_claim() -> String {
+    "sub".to_string()
+}
+
+fn default_scopes() -> Vec<TokenScope> {
+    TokenScope::ALL.to_vec()
+}
+
+impl IssuerConfig {
+    pub fn new(openid_configuration_uri: &str, aud: &str) -> Self {
+        IssuerConfig {
+            openid_configuration_uri: openid_configuration_uri.to_owned(),
+            issuer: None,
+            audiences: vec![aud.to_owned()],
+            principal_claim: default_principal_claim(),
+            principal_prefix: String::new(),
+            scopes: default_scopes(),
+            role_rules: vec![],
+            default_roles: vec![],
+            required_claim_values: BTreeMap::new(),
+            interactive: false,
+            validation: ValidationProfile::default(),
+        }
+    }
+
+    // The registry's own user pool, configured without a list of issuers.
+    // Everyone who can sign in to it can publish, as before there were roles.
+    pub fn user_pool(openid_configuration_uri: &str, aud: &str) -> Self {
+        IssuerConfig {
+            default_roles: vec![Role::Publisher],
+            interactive: true,
+            ..IssuerConfig::new(openid_configuration_uri, aud)
+        }
+    }
+
+    // Configured issuers have to say whose tokens they accept.
+    pub fn check(&self) -> AuthResult<()> {
+        if self.required_claim_values.is_empty()
+            || self.required_claim_values.values().any(|values| values.is_empty())
+        {
+            return Err(AuthError::InputError(format!(
+                "issuer {} has no required_claim_values",
+                self.openid_configuration_uri
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn allows(&self, id_token: &IdToken) -> bool {
+        self.required_claim_values.iter().all(|(claim, values)| {
+            id_token
+                .claim_strs(claim)
+                .iter()
+                .any(|value| values.iter().any(|allowed| allowed == value))
+        })
+    }
+}
+
+pub struct TrustedIssuer {
+    pub config: IssuerConfig,
+    pub decoder: TokenDecoder,
+}
+
+impl TrustedIssuer {
+    pub fn new(config: IssuerConfig) -> Self {
+        let decoder = TokenDecoder::new(&config.openid_configuration_uri, &config.audiences)
+            .with_validation_profile(config.validation.clone());
+        TrustedIssuer { config, decoder }
+    }
+
+    pub async fn issuer(&self) -> AuthResult<String> {
+        match self.config.issuer {
+            Some(ref issuer) => Ok(issuer.clone()),
+            None => Ok(self.decoder.config().await?.issuer.clone()),
+        }
+    }
+
+    pub fn identity(&self, id_token: &IdToken) -> AuthResult<Identity> {
+        if !self.config.allows(id_token) {
+            return Err(AuthError::JwtError(format!(
+                "token for {} doesn't have the required claims",
+                id_token.sub
+            )));
+        }
+
+        let claim = &self.config.principal_claim;
+        let principal = id_token
+            .claim_str(claim)
+            .ok_or_else(|| AuthError::JwtError(format!("missing {} claim", claim)))?;
+
+        let auth_method = if self.config.interactive {
+            AuthMethod::BearerToken
+        } else {
+            AuthMethod::CiToken
+        };
+        let mut identity = Identity::new(
+            &format!("{}{}", self.config.principal_prefix, principal),
+            auth_method,
+        );
+        identity.roles = roles_for(&self.config.role_rules, &self.config.default_roles, id_token);
+        identity.permissions.scopes = self.config.scopes
+            .iter()
+            .filter(|scope| identity.roles.iter().any(|role| role.scopes().contains(scope)))
+            .copied()
+            .collect();
+        Ok(identity)
+    }
+}
+
+pub struct TrustedIssuers {
+    pub issuers: Vec<TrustedIssuer>,
+}
+
+impl TrustedIssuers {
+    pub fn new(configs: Vec<IssuerConfig>) -> Self {
+        TrustedIssuers {
+            issuers: configs.into_iter().map(TrustedIssuer::new).collect(),
+        }
+    }
+
+    // TRUSTED_ISSUERS is a json list of issuer configs.
+    // OPENID_CONFIGURATION_URI and OPENID_AUD still work for the user pool on its own.
+    pub fn from_env() -> AuthResult<Self> {
+        if let Ok(issuers) = env::var("TRUSTED_ISSUERS") {
+            let configs = serde_json::from_str::<Vec<IssuerConfig>>(&issuers)
+                .map_err(|e| AuthError::InputError(format!("invalid TRUSTED_ISSUERS: {}", e)))?;
+            for config in configs.iter() {
+                config.check()?;
+            }
+            return Ok(Self::new(configs));
+        }
+
+        match (env::var("OPENID_CONFIGURATION_URI"), env::var("OPENID_AUD")) {
+            (Ok(uri), Ok(aud)) => Ok(Self::new(vec![IssuerConfig::user_pool(&uri, &aud)])),
+            _ => Err(AuthError::InputError(
+                "TRUSTED_ISSUERS or OPENID_CONFIGURATION_URI and OPENID_AUD must be set".to_string(),
+            )),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.issuers.is_empty()
+    }
+
+    pub async fn find(&self, iss: &str) -> AuthResult<&TrustedIssuer> {
+        for issuer in self.issuers.iter() {
+            match issuer.issuer().await {
+                Ok(ref trusted) if trusted == iss => return Ok(issuer),
+                Ok(_) => {}
+                Err(e) => log::warn!(
+                    "error loading openid configuration {}: {:?}",
+                    issuer.config.openid_configuration_uri,
+                    e
+                ),
+            }
+        }
+
+        Err(AuthError::JwtError(format!("untrusted issuer {}", iss)))
+    }
+
+    // Picks the issuer by the token's iss claim before checking the token with that issuer's keys.
+    pub async fn authenticate(&self, token: &str) -> AuthResult<(Identity, IdToken)> {
+        let iss = unverified_issuer(token)?;
+        let issuer = self.find(&iss).await?;
+        let id_token = issuer.decoder.decode(token).await?;
+        let identity = issuer.identity(&id_token)?;
+        Ok((identity, id_token))
+    }
+}
+
+// The iss claim, read without checking the signature. Only good for deciding which keys to check it with.
+pub fn unverified_issuer(token: &str) -> AuthResult<String> {
+    #[derive(Deserialize)]
+    struct Issuer {
+        iss: String,
+    }
+
+    let payload = token
+        .split('.')
+        .nth(1)
+        .ok_or_else(|| AuthError::JwtError("malformed jwt".to_string()))?;
+    let payload = base64::decode_config(payload, base64::URL_SAFE_NO_PAD)
+        .map_err(|e| AuthError::JwtError(format!("malformed jwt payload: {}", e)))?;
+
+    Ok(serde_json::from_slice::<Issuer>(&payload)?.iss)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use maplit::hashmap;
+    use serde_json::json;
+
+    fn id_token() -> IdToken {
+        IdToken {
+            sub: "a1234567".to_string(),
+            email_verified: None,
+            iss: "https://token.actions.githubusercontent.com".to_string(),
+            aud: Some("wagon".to_string()),
+            token_use: None,
+            exp: 1606986655,
+            iat: 1606983055,
+            email: None,
+            claims: hashmap! {
+                "repository".to_string() => json!("octo-org/octo-repo"),
+            },
+        }
+    }
+
+    #[test]
+    fn test_parse_issuer_configs() {
+        let configs: Vec<IssuerConfig> = serde_json::from_str(
+            r#"[
+                {
+                    "openid_configuration_uri": "https://cognito-idp.eu-west-1.amazonaws.com/eu-west-1_foo/.well-known/openid-configuration",
+                    "audiences": ["app id"]
+                },
+                {
+                    "openid_configuration_uri": "https://cognito-idp.eu-west-1.amazonaws.com/eu-west-1_foo/.well-known/openid-configuration",
+                    "audiences": ["app id"],
+                    "interactive": true,
+                    "required_claim_values": {"cognito:groups": ["wagon-users"]},
+                    "validation": {"token_uses": ["id", "access"], "leeway": 30, "client_ids": ["cli app id"]}
+                },
+                {
+                    "openid_configuration_uri": "https://token.actions.githubusercontent.com/.well-known/openid-configuration",
+                    "issuer": "https://token.actions.githubusercontent.com",
+                    "audiences": ["wagon"],
+                    "principal_claim": "repository",
+                    "principal_prefix": "github:",
+                    "scopes": ["publish", "yank"],
+                    "required_claim_values": {"repository_owner": ["octo-org"]}
+                }
+            ]"#,
+        )
+        .expect("from_str");
+
+        assert_eq!(
+            configs[0],
+            IssuerConfig::new(
+                "https://cognito-idp.eu-west-1.amazonaws.com/eu-west-1_foo/.well-known/openid-configuration",
+                "app id"
+            )
+        );
+        assert!(configs[0].default_roles.is_empty());
+        assert!(configs[0].check().is_err());
+        assert!(configs[1].interactive);
+        assert_eq!(configs[1].validation.leeway, 30);
+        assert_eq!(configs[1].validation.client_ids, vec!["cli app id".to_string()]);
+        assert_eq!(configs[2].principal_claim, "repository");
+        assert_eq!(configs[2].scopes, vec![TokenScope::Publish, TokenScope::Yank]);
+        assert!(!configs[2].interactive);
+        assert!(configs[2].check().is_ok());
+    }
+
+    #[test]
+    fn test_issuer_required_claim_values() {
+        let mut config = IssuerConfig::new("uri", "wagon");
+        config.default_roles = vec![Role::Publisher];
+        config.required_claim_values = serde_json::from_value(json!({
+            "repository_owner": ["octo-org"],
+        }))
+        .expect("claims");
+        assert!(config.check().is_ok());
+
+        // tokens without the claim, or with another value, are refused
+        let mut id_token = id_token();
+        assert!(TrustedIssuer::new(config.clone()).identity(&id_token).is_err());
+        id_token.claims.insert("repository_owner".to_string(), json!("evil-org"));
+        assert!(TrustedIssuer::new(config.clone()).identity(&id_token).is_err());
+
+        id_token.claims.insert("repository_owner".to_string(), json!("octo-org"));
+        let identity = TrustedIssuer::new(config.clone()).identity(&id_token).expect("identity");
+        assert_eq!(identity.auth_method, AuthMethod::CiToken);
+
+        config.required_claim_values.insert("environment".to_string(), vec![]);
+        assert!(config.check().is_err());
+    }
+
+    #[test]
+    fn test_issuer_identity() {
+        let mut config = IssuerConfig::user_pool("uri", "wagon");
+        let issuer = TrustedIssuer::new(config.clone());
+        let identity = issuer.identity(&id_token()).expect("identity");
+        assert_eq!(identity.principal_id, "a1234567");
+        assert_eq!(identity.permissions.scopes, TokenScope::ALL.to_vec());
+        assert_eq!(identity.roles, vec![Role::Publisher]);
+        assert_eq!(identity.auth_method, AuthMethod::BearerToken);
+
+        // no roles unless a rule gives them
+        let identity = TrustedIssuer::new(IssuerConfig::new("uri", "wagon"))
+            .identity(&id_token())
+            .expect("identity");
+        assert!(identity.roles.is_empty());
+        assert!(identity.permissions.scopes.is_empty());
+
+        config.principal_claim = "repository".to_string();
+        config.principal_prefix = "github:".to_string();
+        config.scopes = vec![TokenScope::Publish];
+        let issuer = TrustedIssuer::new(config.clone());
+        let identity = issuer.identity(&id_token()).expect("identity");
+        assert_eq!(identity.principal_id, "github:octo-org/octo-repo");
+        assert_eq!(identity.permissions.scopes, vec![TokenScope::Publish]);
+        assert_eq!(identity.auth_method, AuthMethod::BearerToken);
+
+        config.principal_claim = "environment".to_string();
+        assert!(TrustedIssuer::new(config).identity(&id_token()).is_err());
+    }
+
+    #[test]
+    fn test_issuer_identity_roles() {
+        let mut config = IssuerConfig::new("uri", "wagon");
+        config.role_rules = serde_json::from_value(json!([
+            {"claim": "repository", "value": "octo-org/octo-repo", "role": "admin"},
+        ]))
+        .expect("rules");
+        config.default_roles = vec![Role::Reader];
+        config.scopes = vec![TokenScope::Read, TokenScope::Publish];
+
+        let identity = TrustedIssuer::new(config.clone()).identity(&id_token()).expect("identity");
+        assert_eq!(identity.roles, vec![Role::Admin]);
+        assert_eq!(identity.permissions.scopes, vec![TokenScope::Read, TokenScope::Publish]);
+
+        // readers only get read, whatever the issuer allows
+        config.role_rules[0].value = "octo-org/other-repo".to_string();
+        let identity = TrustedIssuer::new(config).identity(&id_token()).expect("identity");
+        assert_eq!(identity.roles, vec![Role::Reader]);
+        assert_eq!(identity.permissions.scopes, vec![TokenScope::Read]);
+    }
+
+    #[tokio::test]
+    async fn test_find_issuer() {
+        let mut cognito = IssuerConfig::new("cognito uri", "app id");
+        cognito.issuer = Some("https://cognito-idp.eu-west-1.amazonaws.com/eu-west-1_foo".to_string());
+        let mut github = IssuerConfig::new("github uri", "wagon");
+        github.issuer = Some("https://token.actions.githubusercontent.com".to_string());
+        let issuers = TrustedIssuers::new(vec![cognito, github]);
+
+        let issuer = issuers
+            .find("https://token.actions.githubusercontent.com")
+            .await
+            .expect("github");
+        assert_eq!(issuer.config.openid_configuration_uri, "github uri");
+        assert!(issuers.find("https://evil.example.com").await.is_err());
+    }
+
+    #[test]
+    fn test_unverified_issuer() {
+        let payload = base64::encode_config(
+            r#"{"iss":"https://token.actions.githubusercontent.com","sub":"repo:octo-org/octo-repo"}"#,
+            base64::URL_SAFE_NO_PAD,
+        );
+        let token = format!("header.{}.signature", payload);
+        assert_eq!(
+            unverified_issuer(&token).expect("iss"),
+            "https://token.actions.githubusercontent.com"
+        );
+        assert!(unverified_issuer("not a jwt").is_err());
+        assert!(unverified_issuer("header.!!!.signature").is_err());
+    }
+}
@@ -1,10 +1,67 @@
+use lazy_static::lazy_static;
 use crate::result::AuthResult;
 use super::types::KeySet;
+use std::time::Duration;
+
+// Requests wait on a key fetch, so a slow identity provider mustn't hold them
+// up for longer than the authorizer is allowed to run.
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+pub const FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+
+lazy_static! {
+    pub static ref HTTP_CLIENT: reqwest::Client = reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(FETCH_TIMEOUT)
+        .build()
+        .expect("http client");
+}
 
 pub async fn fetch_key_set(uri: &str) -> AuthResult<KeySet> {
-    let resp = reqwest::get(uri)
+    let (key_set, _max_age) = fetch_key_set_with_max_age(uri).await?;
+    Ok(key_set)
+}
+
+// Also returns how long the keys may be cached for, if the response says.
+pub async fn fetch_key_set_with_max_age(uri: &str) -> AuthResult<(KeySet, Option<Duration>)> {
+    let resp = HTTP_CLIENT.get(uri)
+        .send()
         .await?
-        .json::<KeySet>()
-        .await?;
-    Ok(resp)
+        .error_for_status()?;
+    let max_age = resp.headers()
+        .get(reqwest::header::CACHE_CONTROL)
+        .and_then(|value| value.to_str().ok())
+        .and_then(cache_control_max_age);
+    let key_set = resp.json::<KeySet>().await?;
+    Ok((key_set, max_age))
+}
+
+// no-cache and no-store mean refetch whenever we're allowed to.
+pub fn cache_control_max_age(value: &str) -> Option<Duration> {
+    let mut max_age = None;
+
+    for directive in value.split(',').map(|d| d.trim().to_lowercase()) {
+        if directive == "no-cache" || directive == "no-store" {
+            return Some(Duration::from_secs(0));
+        }
+
+        if let Some(seconds) = directive.strip_prefix("max-age=") {
+            max_age = seconds.trim_matches('"').parse::<u64>().ok().map(Duration::from_secs);
+        }
+    }
+
+    max_age
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cache_control_max_age() {
+        assert_eq!(cache_control_max_age("max-age=3600"), Some(Duration::from_secs(3600)));
+        assert_eq!(cache_control_max_age("public, Max-Age=\"60\", must-revalidate"), Some(Duration::from_secs(60)));
+        assert_eq!(cache_control_max_age("max-age=60, no-cache"), Some(Duration::from_secs(0)));
+        assert_eq!(cache_control_max_age("public"), None);
+        assert_eq!(cache_control_max_age("max-age=soon"), None);
+    }
 }
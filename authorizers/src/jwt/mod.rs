@@ -2,68 +2,101 @@ use crate::result::AuthResult;
 use crate::error::AuthError;
 use serde::de::DeserializeOwned;
 use double_checked_cell_async::DoubleCheckedCell;
+use std::sync::Arc;
+use std::time::Instant;
 
 pub mod types;
 pub mod config;
 pub mod keys;
+pub mod cache;
+pub mod issuers;
+pub mod validation;
+pub mod roles;
 
 pub use types::*;
 pub use config::*;
 pub use keys::*;
+pub use cache::*;
+pub use issuers::*;
+pub use validation::*;
+pub use roles::*;
 
 pub struct TokenDecoder {
     pub config_uri: String,
-    pub aud: String,
+    pub audiences: Vec<String>,
+    pub profile: ValidationProfile,
     config_cell: DoubleCheckedCell<OpenIdConfiguration>,
-    key_set_cell: DoubleCheckedCell<KeySet>,
+    key_set_cache: KeySetCache,
 }
 
 impl TokenDecoder {
-    pub fn new(uri: &str, aud: &str) -> Self {
+    pub fn new(uri: &str, audiences: &[String]) -> Self {
         TokenDecoder {
             config_uri: uri.to_owned(),
-            aud: aud.to_owned(),
+            audiences: audiences.to_vec(),
+            profile: ValidationProfile::default(),
             config_cell: DoubleCheckedCell::new(),
-            key_set_cell: DoubleCheckedCell::new(),
+            key_set_cache: KeySetCache::default(),
         }
     }
 
+    pub fn with_validation_profile(mut self, profile: ValidationProfile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    pub fn with_key_set_cache(mut self, key_set_cache: KeySetCache) -> Self {
+        self.key_set_cache = key_set_cache;
+        self
+    }
+
     pub async fn config<'a>(&'a self) -> AuthResult<&'a OpenIdConfiguration> {
         self.config_cell.get_or_try_init(async {
             fetch_config(&self.config_uri).await
         }).await
     }
 
-    pub async fn key_set<'a>(&'a self) -> AuthResult<&'a KeySet> {
-        self.key_set_cell.get_or_try_init(async {
-            let config = self.config().await?;
+    // The current keys, refetched if they've expired or don't include the kid.
+    pub async fn key_set(&self, kid: &str) -> AuthResult<Arc<KeySet>> {
+        let config = self.config().await?;
 
-            if let Some(ref jwks_uri) = config.jwks_uri {
-                fetch_key_set(jwks_uri).await
-            } else {
-                Err(AuthError::JwtError("no jwks uri configured".to_string()))
-            }
-        }).await
+        let jwks_uri = config.jwks_uri.as_ref()
+            .ok_or_else(|| AuthError::JwtError("no jwks uri configured".to_string()))?;
+
+        self.key_set_cache.get(kid, Instant::now(), || fetch_key_set_with_max_age(jwks_uri)).await
     }
 
     pub async fn decode(&self, token: &str) -> AuthResult<IdToken> {
+        self.decode_claims(token).await
+    }
+
+    // For tokens with claims other than the usual id token ones.
+    pub async fn decode_claims<T: DeserializeOwned>(&self, token: &str) -> AuthResult<T> {
         let config = self.config().await?;
-        let key_set = self.key_set().await?;
+        let header = jsonwebtoken::decode_header(token)?;
+        let kid = header.kid
+            .ok_or_else(|| AuthError::JwtError("missing jwt kid".to_string()))?;
+        let key_set = self.key_set(&kid).await?;
 
-        decode_and_validate_jwt(token, &key_set, &self.aud, &config.issuer)
+        decode_and_validate_jwt_with_profile(token, &key_set, &self.audiences, &config.issuer, &self.profile)
     }
 }
 
-pub fn decode_and_validate_jwt<T: DeserializeOwned>(token: &str, key_set: &KeySet, aud: &str, iss: &str) -> AuthResult<T> {
+pub fn decode_and_validate_jwt<T: DeserializeOwned>(token: &str, key_set: &KeySet, audiences: &[String], iss: &str) -> AuthResult<T> {
+    decode_and_validate_jwt_with_profile(token, key_set, audiences, iss, &ValidationProfile::default())
+}
+
+pub fn decode_and_validate_jwt_with_profile<T: DeserializeOwned>(token: &str, key_set: &KeySet, audiences: &[String], iss: &str, profile: &ValidationProfile) -> AuthResult<T> {
     let header = jsonwebtoken::decode_header(token)?;
     if let Some(ref kid) = header.kid {
         if let Some(key) = key_set.find_key(kid) {
-            let algorithm = algorithm_from_key(key)?;
-            let mut validation = jsonwebtoken::Validation::new(algorithm);
-            validation.set_audience(&[aud]);
-            validation.iss = Some(iss.to_owned());
+            let algorithm = algorithm_from_key(key, header.alg)?;
+            let validation = profile.validation(algorithm, iss);
+
+            let claims: serde_json::Map<String, serde_json::Value> = decode_and_validate_jwt_with_key(token, key, &validation)?;
+            profile.check_claims(&claims, audiences)?;
 
-            decode_and_validate_jwt_with_key(token, key, &validation)
+            Ok(serde_json::from_value(serde_json::Value::Object(claims))?)
         } else {
             return Err(AuthError::JwtError("unknown jwt kid".to_string()))
         }
@@ -85,20 +118,240 @@ fn decoding_key_from_key(key: &Key) -> AuthResult<jsonwebtoken::DecodingKey> {
         Key::RSA(k) => {
             Ok(jsonwebtoken::DecodingKey::from_rsa_components(&k.n, &k.e))
         }
+        Key::EC(k) => {
+            // ring wants the uncompressed point, 0x04 followed by the coordinates
+            let size = curve_coordinate_size(&k.crv)?;
+            let mut point = vec![0x04];
+            for coordinate in &[&k.x, &k.y] {
+                let bytes = base64::decode_config(coordinate, base64::URL_SAFE_NO_PAD)
+                    .map_err(|e| AuthError::JwtError(format!("invalid ec key {}: {}", k.kid, e)))?;
+                if bytes.len() != size {
+                    return Err(AuthError::JwtError(format!("invalid ec key {}: coordinate is {} bytes", k.kid, bytes.len())));
+                }
+                point.extend_from_slice(&bytes);
+            }
+
+            Ok(jsonwebtoken::DecodingKey::from_ec_der(&point).into_static())
+        }
     }
 }
 
-fn algorithm_from_key(key: &Key) -> AuthResult<jsonwebtoken::Algorithm> {
+fn curve_coordinate_size(crv: &str) -> AuthResult<usize> {
+    match crv {
+        "P-256" => Ok(32),
+        "P-384" => Ok(48),
+        other => Err(AuthError::JwtError(format!("unsupported curve {}", other))),
+    }
+}
+
+// The token's own alg is only trusted for RSA keys that don't name one,
+// and then only if it's an RSA algorithm.
+fn algorithm_from_key(key: &Key, header_alg: jsonwebtoken::Algorithm) -> AuthResult<jsonwebtoken::Algorithm> {
+    use jsonwebtoken::Algorithm::*;
+
     match key {
         Key::RSA(k) => {
-            match &k.alg[..] {
-                "RS256" => {
-                    Ok(jsonwebtoken::Algorithm::RS256)
-                },
-                other => {
+            match k.alg.as_deref() {
+                Some("RS256") => Ok(RS256),
+                Some("RS384") => Ok(RS384),
+                Some("RS512") => Ok(RS512),
+                Some("PS256") => Ok(PS256),
+                Some("PS384") => Ok(PS384),
+                Some("PS512") => Ok(PS512),
+                Some(other) => {
                     Err(AuthError::JwtError(format!("unsupported algorithm {}", other)))
                 }
+                None => match header_alg {
+                    RS256 | RS384 | RS512 | PS256 | PS384 | PS512 => Ok(header_alg),
+                    other => {
+                        Err(AuthError::JwtError(format!("algorithm {:?} can't be used with rsa key {}", other, k.kid)))
+                    }
+                },
+            }
+        }
+        Key::EC(k) => {
+            match (k.alg.as_deref(), &k.crv[..]) {
+                (Some("ES256"), "P-256") | (None, "P-256") => Ok(ES256),
+                (Some("ES384"), "P-384") | (None, "P-384") => Ok(ES384),
+                (alg, crv) => {
+                    Err(AuthError::JwtError(format!("unsupported algorithm {:?} for curve {}", alg, crv)))
+                }
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use jsonwebtoken::{Algorithm, EncodingKey, Header};
+    use serde_json::json;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    const AUD: &str = "app id";
+    const ISS: &str = "https://issuer.example.com";
+
+    fn audiences() -> Vec<String> {
+        vec!["other app id".to_string(), AUD.to_string()]
+    }
+
+    fn key_set() -> KeySet {
+        serde_json::from_str(include_str!("../../resources/test/jwks.json")).expect("jwks")
+    }
+
+    fn now() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).expect("time").as_secs()
+    }
+
+    fn sign(alg: Algorithm, kid: &str) -> String {
+        let now = now();
+        sign_claims(alg, kid, json!({
+            "sub": "user",
+            "iss": ISS,
+            "aud": AUD,
+            "exp": now + 3600,
+            "iat": now,
+        }))
+    }
+
+    fn sign_claims(alg: Algorithm, kid: &str, claims: serde_json::Value) -> String {
+        let key = match alg {
+            Algorithm::ES256 => EncodingKey::from_ec_pem(include_bytes!("../../resources/test/ec-p256.pem")),
+            _ => EncodingKey::from_rsa_pem(include_bytes!("../../resources/test/rsa.pem")),
+        }.expect("encoding key");
+
+        let mut header = Header::new(alg);
+        header.kid = Some(kid.to_string());
+
+        jsonwebtoken::encode(&header, &claims, &key).expect("encode")
+    }
+
+    #[test]
+    fn test_decode_supported_algorithms() {
+        let key_set = key_set();
+
+        for (alg, kid) in &[
+            (Algorithm::RS256, "test-rs256"),
+            (Algorithm::RS384, "test-rs384"),
+            (Algorithm::PS256, "test-ps256"),
+            (Algorithm::ES256, "test-es256"),
+        ] {
+            let token = sign(*alg, kid);
+            let id_token: IdToken = decode_and_validate_jwt(&token, &key_set, &audiences(), ISS)
+                .unwrap_or_else(|e| panic!("{:?}: {:?}", alg, e));
+            assert_eq!(id_token.sub, "user");
+        }
+    }
+
+    #[test]
+    fn test_decode_rejects_algorithm_mismatch() {
+        let key_set = key_set();
+
+        let token = sign(Algorithm::RS256, "test-rs384");
+        assert!(decode_and_validate_jwt::<IdToken>(&token, &key_set, &audiences(), ISS).is_err());
+
+        let token = sign(Algorithm::RS256, "test-oct");
+        assert!(decode_and_validate_jwt::<IdToken>(&token, &key_set, &audiences(), ISS).is_err());
+    }
+
+    #[test]
+    fn test_decode_access_token() {
+        let key_set = key_set();
+        let now = now();
+        let token = sign_claims(Algorithm::RS256, "test-rs256", json!({
+            "sub": "user",
+            "iss": ISS,
+            "client_id": "cli app id",
+            "token_use": "access",
+            "username": "alice",
+            "exp": now + 3600,
+            "iat": now,
+        }));
+
+        // no aud, so only accepted with the client id
+        assert!(decode_and_validate_jwt::<IdToken>(&token, &key_set, &audiences(), ISS).is_err());
+
+        let profile = ValidationProfile {
+            token_uses: vec!["access".to_string()],
+            client_ids: vec!["cli app id".to_string()],
+            ..Default::default()
+        };
+        let access_token: IdToken = decode_and_validate_jwt_with_profile(&token, &key_set, &audiences(), ISS, &profile)
+            .expect("access token");
+        assert_eq!(access_token.claim_str("username"), Some("alice"));
+        assert_eq!(access_token.aud, None);
+
+        // and the id token is now the wrong token_use
+        let id_token = sign(Algorithm::RS256, "test-rs256");
+        assert!(decode_and_validate_jwt_with_profile::<IdToken>(&id_token, &key_set, &audiences(), ISS, &profile).is_err());
+    }
+
+    #[test]
+    fn test_decode_leeway() {
+        let key_set = key_set();
+        let now = now();
+        let claims = |exp: u64, nbf: u64| json!({
+            "sub": "user",
+            "iss": ISS,
+            "aud": AUD,
+            "exp": exp,
+            "nbf": nbf,
+            "iat": now,
+        });
+        let decode = |token: &str, leeway: u64| {
+            let profile = ValidationProfile { leeway, ..Default::default() };
+            decode_and_validate_jwt_with_profile::<IdToken>(token, &key_set, &audiences(), ISS, &profile)
+        };
+
+        let expired = sign_claims(Algorithm::RS256, "test-rs256", claims(now - 10, now - 3600));
+        assert!(decode(&expired, 0).is_err());
+        assert!(decode(&expired, 60).is_ok());
+
+        let not_yet_valid = sign_claims(Algorithm::RS256, "test-rs256", claims(now + 3600, now + 10));
+        assert!(decode(&not_yet_valid, 0).is_err());
+        assert!(decode(&not_yet_valid, 60).is_ok());
+
+        let wrong_issuer = sign_claims(Algorithm::RS256, "test-rs256", json!({
+            "sub": "user",
+            "iss": "https://evil.example.com",
+            "aud": AUD,
+            "exp": now + 3600,
+            "iat": now,
+        }));
+        assert!(decode(&wrong_issuer, 60).is_err());
+    }
+
+    #[test]
+    fn test_algorithm_from_ec_key() {
+        let key = |alg: Option<&str>, crv: &str| Key::EC(ECKey {
+            alg: alg.map(|s| s.to_string()),
+            crv: crv.to_string(),
+            kid: "kid".to_string(),
+            x: "x".to_string(),
+            y: "y".to_string(),
+            use_: None,
+        });
+
+        assert_eq!(algorithm_from_key(&key(None, "P-256"), Algorithm::HS256).expect("P-256"), Algorithm::ES256);
+        assert_eq!(algorithm_from_key(&key(Some("ES384"), "P-384"), Algorithm::ES256).expect("P-384"), Algorithm::ES384);
+        assert!(algorithm_from_key(&key(Some("ES256"), "P-384"), Algorithm::ES256).is_err());
+        assert!(algorithm_from_key(&key(None, "P-521"), Algorithm::ES256).is_err());
+        assert!(decoding_key_from_key(&key(None, "P-256")).is_err());
+    }
+
+    #[test]
+    fn test_algorithm_from_rsa_key_without_alg() {
+        let key = |alg: Option<&str>| Key::RSA(RSAKey {
+            alg: alg.map(|s| s.to_string()),
+            e: "AQAB".to_string(),
+            kid: "kid".to_string(),
+            n: "n".to_string(),
+            use_: None,
+        });
+
+        assert_eq!(algorithm_from_key(&key(None), Algorithm::PS384).expect("PS384"), Algorithm::PS384);
+        assert!(algorithm_from_key(&key(None), Algorithm::HS256).is_err());
+        assert!(algorithm_from_key(&key(None), Algorithm::ES256).is_err());
+        assert_eq!(algorithm_from_key(&key(Some("RS256")), Algorithm::RS512).expect("RS256"), Algorithm::RS256);
+    }
+}
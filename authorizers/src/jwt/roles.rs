@@ -0,0 +1,89 @@
+use super::IdToken;
+use api_types::auth::Role;
+use serde::Deserialize;
+
+// Gives the role to tokens whose claim is the value, or is a list containing it,
+// eg. cognito:groups containing wagon-admins.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct RoleRule {
+    pub claim: String,
+    pub value: String,
+    pub role: Role,
+}
+
+impl RoleRule {
+    pub fn matches(&self, id_token: &IdToken) -> bool {
+        id_token
+            .claim_strs(&self.claim)
+            .iter()
+            .any(|value| *value == self.value)
+    }
+}
+
+// The roles of every matching rule, or the defaults if none match.
+pub fn roles_for(rules: &[RoleRule], default_roles: &[Role], id_token: &IdToken) -> Vec<Role> {
+    let mut roles: Vec<Role> = rules
+        .iter()
+        .filter(|rule| rule.matches(id_token))
+        .map(|rule| rule.role)
+        .collect();
+
+    if roles.is_empty() {
+        roles = default_roles.to_vec();
+    }
+
+    roles.sort();
+    roles.dedup();
+    roles
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn id_token(groups: &[&str]) -> IdToken {
+        serde_json::from_value(json!({
+            "sub": "a1234567",
+            "iss": "https://cognito-idp.eu-west-1.amazonaws.com/eu-west-1_foo",
+            "aud": "app id",
+            "exp": 1606986655,
+            "iat": 1606983055,
+            "email": "me@example.com",
+            "cognito:groups": groups,
+        }))
+        .expect("id token")
+    }
+
+    fn rules() -> Vec<RoleRule> {
+        serde_json::from_value(json!([
+            {"claim": "cognito:groups", "value": "wagon-admins", "role": "admin"},
+            {"claim": "cognito:groups", "value": "wagon-publishers", "role": "publisher"},
+            {"claim": "email", "value": "me@example.com", "role": "publisher"},
+        ]))
+        .expect("rules")
+    }
+
+    #[test]
+    fn test_roles_for() {
+        let defaults = [Role::Reader];
+
+        assert_eq!(
+            roles_for(
+                &rules(),
+                &defaults,
+                &id_token(&["wagon-admins", "wagon-publishers"])
+            ),
+            vec![Role::Admin, Role::Publisher]
+        );
+        assert_eq!(
+            roles_for(&rules(), &defaults, &id_token(&["other"])),
+            vec![Role::Publisher]
+        );
+        assert_eq!(
+            roles_for(&rules()[..2], &defaults, &id_token(&[])),
+            vec![Role::Reader]
+        );
+        assert!(roles_for(&rules()[..2], &[], &id_token(&[])).is_empty());
+    }
+}
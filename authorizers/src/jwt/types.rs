@@ -32,12 +32,45 @@ pub struct OpenIdConfiguration {
     pub userinfo_endpoint: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(untagged)]
 pub enum ResponseType {
     #[serde(rename="code")]
     Code,
     #[serde(rename="token")]
     Token,
+
+    Other(String)
+}
+
+impl<'de> Deserialize<'de> for ResponseType {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_str(ResponseTypeVisitor)
+    }
+}
+
+pub struct ResponseTypeVisitor;
+
+impl<'de> Visitor<'de> for ResponseTypeVisitor {
+    type Value = ResponseType;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a ResponseType string")
+    }
+
+    fn visit_str<E>(self, s: &str) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(match s {
+            "code" => ResponseType::Code,
+            "token" => ResponseType::Token,
+            other => ResponseType::Other(other.to_owned())
+        })
+    }
 }
 
 #[derive(Serialize, Debug, PartialEq)]
@@ -189,11 +222,44 @@ impl<'de> Visitor<'de> for TokenEndpointAuthMethodVisitor {
 }
 */
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Debug, PartialEq)]
 pub struct KeySet {
     pub keys: Vec<Key>,
 }
 
+// Keys we can't use, like symmetric oct keys, curves we don't support or
+// encryption keys, are skipped so they don't stop us using the rest of the set.
+impl<'de> Deserialize<'de> for KeySet {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct RawKeySet {
+            keys: Vec<serde_json::Value>,
+        }
+
+        let raw = RawKeySet::deserialize(deserializer)?;
+        let keys = raw.keys.into_iter()
+            .filter_map(|value| {
+                match serde_json::from_value::<Key>(value.clone()) {
+                    Ok(key) if key.use_().map(|u| u != "sig").unwrap_or(false) => {
+                        log::warn!("skipping jwk {} with use {:?}", key.kid(), key.use_());
+                        None
+                    }
+                    Ok(key) => Some(key),
+                    Err(e) => {
+                        log::warn!("skipping unsupported jwk {:?} {:?}: {}", value.get("kty"), value.get("kid"), e);
+                        None
+                    }
+                }
+            })
+            .collect();
+
+        Ok(KeySet { keys })
+    }
+}
+
 impl KeySet {
     pub fn find_key<'a>(&'a self, kid: &str) -> Option<&'a Key> {
         self.keys.iter().find(|k| k.kid() == kid)
@@ -204,16 +270,30 @@ impl KeySet {
 #[serde(tag="kty")]
 pub enum Key {
     RSA(RSAKey),
+    EC(ECKey),
 }
 
+// Without an alg, the key is good for whichever RSA algorithm the token says.
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct RSAKey {
-    pub alg: String,
+    pub alg: Option<String>,
     pub e: String,
     pub kid: String,
     pub n: String,
     #[serde(rename="use")]
-    pub use_: String,
+    pub use_: Option<String>,
+}
+
+// alg is optional for EC keys, the curve is enough to tell which algorithm they're for.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ECKey {
+    pub alg: Option<String>,
+    pub crv: String,
+    pub kid: String,
+    pub x: String,
+    pub y: String,
+    #[serde(rename="use")]
+    pub use_: Option<String>,
 }
 
 impl Key {
@@ -222,6 +302,16 @@ impl Key {
             Key::RSA(k) => {
                 &k.kid
             }
+            Key::EC(k) => {
+                &k.kid
+            }
+        }
+    }
+
+    pub fn use_(&self) -> Option<&str> {
+        match self {
+            Key::RSA(k) => k.use_.as_deref(),
+            Key::EC(k) => k.use_.as_deref(),
         }
     }
 }
@@ -248,7 +338,8 @@ pub struct IdToken {
     pub sub: String,
     pub email_verified: Option<bool>,
     pub iss: String,
-    pub aud: String,
+    // access tokens have a client_id instead
+    pub aud: Option<String>,
     pub token_use: Option<String>,
     pub exp: i64,
     pub iat: i64,
@@ -258,6 +349,28 @@ pub struct IdToken {
     pub claims: HashMap<String, serde_json::Value>,
 }
 
+impl IdToken {
+    // A string claim by name, whether or not it has a field of its own.
+    pub fn claim_str<'a>(&'a self, name: &str) -> Option<&'a str> {
+        match name {
+            "sub" => Some(&self.sub),
+            "iss" => Some(&self.iss),
+            "aud" => self.aud.as_deref(),
+            "token_use" => self.token_use.as_deref(),
+            "email" => self.email.as_deref(),
+            other => self.claims.get(other).and_then(|value| value.as_str()),
+        }
+    }
+
+    // A claim's strings, whether it's a single string or a list like cognito:groups.
+    pub fn claim_strs<'a>(&'a self, name: &str) -> Vec<&'a str> {
+        match self.claims.get(name) {
+            Some(serde_json::Value::Array(values)) => values.iter().filter_map(|value| value.as_str()).collect(),
+            _ => self.claim_str(name).into_iter().collect(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -286,7 +399,7 @@ mod test {
             sub: "a1234567-b123-c123-d123-e1234567890a".to_string(),
             email_verified: Some(true),
             iss: "https://cognito-idp.eu-west-1.amazonaws.com/eu-west-1_foo".to_string(),
-            aud: "app id".to_string(),
+            aud: Some("app id".to_string()),
             token_use: Some("id".to_string()),
             exp: 1606986655,
             iat: 1606983055,
@@ -302,6 +415,69 @@ mod test {
         assert_eq!(id_token, expected);
     }
 
+    #[test]
+    fn test_deserialize_access_token() {
+        let json_str = r#"{
+            "sub": "a1234567-b123-c123-d123-e1234567890a",
+            "iss": "https://cognito-idp.eu-west-1.amazonaws.com/eu-west-1_foo",
+            "client_id": "app id",
+            "origin_jti": "baz",
+            "event_id": "bar",
+            "token_use": "access",
+            "scope": "openid email",
+            "auth_time": 1605040679,
+            "exp": 1606986655,
+            "iat": 1606983055,
+            "jti": "qux",
+            "username": "a1234567-b123-c123-d123-e1234567890a"
+          }"#;
+        let access_token = serde_json::from_str::<IdToken>(json_str).expect("from_str");
+        assert_eq!(access_token.aud, None);
+        assert_eq!(access_token.claim_str("client_id"), Some("app id"));
+        assert_eq!(access_token.claim_str("token_use"), Some("access"));
+    }
+
+    #[test]
+    fn test_deserialize_github_actions_openid_config() {
+        let json_str = r#"{
+            "issuer": "https://token.actions.githubusercontent.com",
+            "jwks_uri": "https://token.actions.githubusercontent.com/.well-known/jwks",
+            "subject_types_supported": ["public", "pairwise"],
+            "response_types_supported": ["id_token"],
+            "claims_supported": ["sub", "aud", "exp", "iat", "iss", "jti", "nbf", "ref", "repository", "repository_owner", "workflow", "environment"],
+            "id_token_signing_alg_values_supported": ["RS256"],
+            "scopes_supported": ["openid"]
+        }"#;
+        let config = serde_json::from_str::<OpenIdConfiguration>(json_str).expect("from_str");
+        assert_eq!(config.issuer, "https://token.actions.githubusercontent.com");
+        assert_eq!(config.response_types_supported, vec![ResponseType::Other("id_token".to_string())]);
+        assert_eq!(config.subject_types_supported, vec![SubjectType::Public, SubjectType::Other("pairwise".to_string())]);
+    }
+
+    #[test]
+    fn test_id_token_claim_str() {
+        let mut id_token = serde_json::from_str::<IdToken>(r#"{
+            "sub": "repo:octo-org/octo-repo:environment:prod",
+            "iss": "https://token.actions.githubusercontent.com",
+            "aud": "wagon",
+            "exp": 1606986655,
+            "iat": 1606983055,
+            "repository": "octo-org/octo-repo",
+            "run_number": 3
+          }"#).expect("from_str");
+        assert_eq!(id_token.claim_str("sub"), Some("repo:octo-org/octo-repo:environment:prod"));
+        assert_eq!(id_token.claim_str("repository"), Some("octo-org/octo-repo"));
+        assert_eq!(id_token.claim_str("run_number"), None);
+        assert_eq!(id_token.claim_str("email"), None);
+        id_token.email = Some("me@example.com".to_string());
+        assert_eq!(id_token.claim_str("email"), Some("me@example.com"));
+
+        id_token.claims.insert("cognito:groups".to_string(), json!(["admins", "publishers"]));
+        assert_eq!(id_token.claim_strs("cognito:groups"), vec!["admins", "publishers"]);
+        assert_eq!(id_token.claim_strs("repository"), vec!["octo-org/octo-repo"]);
+        assert!(id_token.claim_strs("run_number").is_empty());
+    }
+
     #[test]
     fn test_serialize_custom_scope() {
         let expected = r#""foo""#;
@@ -361,14 +537,61 @@ mod test {
             "use": "sig"
           }"#;
         let expected = Key::RSA(RSAKey {
-            alg: "RS256".to_string(),
+            alg: Some("RS256".to_string()),
             e: "AQAB".to_string(),
             kid: "test kid".to_string(),
             n: "test n param".to_string(),
-            use_: "sig".to_string()
+            use_: Some("sig".to_string())
         });
         let key = serde_json::from_str::<Key>(json_str).expect("from_str");
         assert_eq!(key, expected);
+
+        let json_str = r#"{"e": "AQAB", "kid": "test kid", "kty": "RSA", "n": "test n param"}"#;
+        let key = serde_json::from_str::<Key>(json_str).expect("from_str");
+        assert_eq!(key.use_(), None);
+        assert!(matches!(key, Key::RSA(RSAKey { alg: None, .. })));
+    }
+
+    #[test]
+    fn test_deserialize_ec_key() {
+        let json_str = r#"{
+            "crv": "P-256",
+            "kid": "test kid",
+            "kty": "EC",
+            "x": "test x param",
+            "y": "test y param"
+          }"#;
+        let expected = Key::EC(ECKey {
+            alg: None,
+            crv: "P-256".to_string(),
+            kid: "test kid".to_string(),
+            x: "test x param".to_string(),
+            y: "test y param".to_string(),
+            use_: None,
+        });
+        let key = serde_json::from_str::<Key>(json_str).expect("from_str");
+        assert_eq!(key, expected);
+    }
+
+    #[test]
+    fn test_deserialize_key_set_skips_unsupported_keys() {
+        let json_str = include_str!("../../resources/test/jwks.json");
+        let key_set = serde_json::from_str::<KeySet>(json_str).expect("from_str");
+        let kids: Vec<&str> = key_set.keys.iter().map(|k| k.kid()).collect();
+        assert_eq!(kids, vec!["test-rs256", "test-rs384", "test-ps256", "test-es256"]);
+
+        let json_str = r#"{"keys": [{"kty": "OKP", "crv": "Ed25519", "kid": "ed", "x": "x"}]}"#;
+        let key_set = serde_json::from_str::<KeySet>(json_str).expect("from_str");
+        assert!(key_set.keys.is_empty());
+
+        let json_str = r#"{"keys": [
+            {"kty": "RSA", "kid": "enc", "e": "AQAB", "n": "n", "use": "enc"},
+            {"kty": "RSA", "kid": "any", "e": "AQAB", "n": "n"}
+        ]}"#;
+        let key_set = serde_json::from_str::<KeySet>(json_str).expect("from_str");
+        let kids: Vec<&str> = key_set.keys.iter().map(|k| k.kid()).collect();
+        assert_eq!(kids, vec!["any"]);
+        assert!(serde_json::from_str::<KeySet>(r#"{"keys": {}}"#).is_err());
     }
 
     #[test]
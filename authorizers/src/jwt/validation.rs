@@ -0,0 +1,167 @@
+use crate::error::AuthError;
+use crate::result::AuthResult;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+// What a token needs on top of a good signature and issuer.
+// The defaults accept any token_use but only tokens with one of the audiences,
+// which rules out cognito access tokens since they have a client_id instead of an aud.
+#[derive(Deserialize, Clone, Debug, PartialEq, Default)]
+#[serde(default)]
+pub struct ValidationProfile {
+    // Accepted token_use claims, eg. "id" and "access" for cognito. Not checked if empty.
+    pub token_uses: Vec<String>,
+    // Seconds of clock skew allowed when checking exp and nbf.
+    pub leeway: u64,
+    // Claims tokens must have besides the ones checked anyway.
+    pub required_claims: Vec<String>,
+    // Accepted client_id claims of access tokens.
+    pub client_ids: Vec<String>,
+}
+
+impl ValidationProfile {
+    // The checks jsonwebtoken does itself. The audience is left to check_claims.
+    pub fn validation(
+        &self,
+        algorithm: jsonwebtoken::Algorithm,
+        iss: &str,
+    ) -> jsonwebtoken::Validation {
+        let mut validation = jsonwebtoken::Validation::new(algorithm);
+        validation.leeway = self.leeway;
+        validation.validate_exp = true;
+        validation.validate_nbf = true;
+        validation.iss = Some(iss.to_owned());
+        validation
+    }
+
+    pub fn check_claims(
+        &self,
+        claims: &Map<String, Value>,
+        audiences: &[String],
+    ) -> AuthResult<()> {
+        let token_use = claims.get("token_use").and_then(Value::as_str);
+        if !self.token_uses.is_empty()
+            && !token_use
+                .map(|t| self.token_uses.iter().any(|u| u == t))
+                .unwrap_or(false)
+        {
+            return Err(AuthError::JwtError(format!(
+                "token_use {:?} not accepted",
+                token_use
+            )));
+        }
+
+        for claim in self.required_claims.iter() {
+            if claims.get(claim).map(Value::is_null).unwrap_or(true) {
+                return Err(AuthError::JwtError(format!("missing {} claim", claim)));
+            }
+        }
+
+        if token_use == Some("access") {
+            let client_id = claims.get("client_id").and_then(Value::as_str);
+            if !client_id
+                .map(|id| self.client_ids.iter().any(|c| c == id))
+                .unwrap_or(false)
+            {
+                return Err(AuthError::JwtError(format!(
+                    "client_id {:?} not accepted",
+                    client_id
+                )));
+            }
+        } else {
+            // aud is either a string or a list of them
+            let accepted = match claims.get("aud") {
+                Some(Value::String(aud)) => audiences.contains(aud),
+                Some(Value::Array(auds)) => auds
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .any(|aud| audiences.iter().any(|a| a == aud)),
+                _ => false,
+            };
+            if !accepted {
+                return Err(AuthError::JwtError("invalid audience".to_string()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn claims(value: Value) -> Map<String, Value> {
+        match value {
+            Value::Object(claims) => claims,
+            _ => panic!("not an object"),
+        }
+    }
+
+    fn audiences() -> Vec<String> {
+        vec!["app id".to_string()]
+    }
+
+    #[test]
+    fn test_check_audience() {
+        let profile = ValidationProfile::default();
+
+        assert!(profile
+            .check_claims(&claims(json!({"aud": "app id"})), &audiences())
+            .is_ok());
+        assert!(profile
+            .check_claims(&claims(json!({"aud": ["other", "app id"]})), &audiences())
+            .is_ok());
+        assert!(profile
+            .check_claims(&claims(json!({"aud": "other"})), &audiences())
+            .is_err());
+        assert!(profile
+            .check_claims(&claims(json!({})), &audiences())
+            .is_err());
+    }
+
+    #[test]
+    fn test_check_access_token() {
+        let access_token =
+            claims(json!({"token_use": "access", "client_id": "app id", "username": "alice"}));
+
+        // the default profile has no client ids
+        assert!(ValidationProfile::default()
+            .check_claims(&access_token, &audiences())
+            .is_err());
+
+        let profile = ValidationProfile {
+            token_uses: vec!["access".to_string()],
+            client_ids: vec!["app id".to_string()],
+            required_claims: vec!["username".to_string()],
+            ..Default::default()
+        };
+        assert!(profile.check_claims(&access_token, &audiences()).is_ok());
+
+        let id_token = claims(json!({"token_use": "id", "aud": "app id", "username": "alice"}));
+        assert!(profile.check_claims(&id_token, &audiences()).is_err());
+
+        let other_client =
+            claims(json!({"token_use": "access", "client_id": "other", "username": "alice"}));
+        assert!(profile.check_claims(&other_client, &audiences()).is_err());
+
+        let no_username =
+            claims(json!({"token_use": "access", "client_id": "app id", "username": null}));
+        assert!(profile.check_claims(&no_username, &audiences()).is_err());
+    }
+
+    #[test]
+    fn test_parse_profile() {
+        let profile: ValidationProfile = serde_json::from_str(
+            r#"{"token_uses": ["id", "access"], "leeway": 30, "client_ids": ["app id"]}"#,
+        )
+        .expect("profile");
+        assert_eq!(profile.leeway, 30);
+        assert_eq!(
+            profile.token_uses,
+            vec!["id".to_string(), "access".to_string()]
+        );
+        assert!(profile.required_claims.is_empty());
+    }
+}
@@ -1,3 +1,4 @@
+use api_types::auth::{Identity, Role};
 use aws_lambda_events::apigw;
 use futures::Future;
 use lambda_runtime::Context;
@@ -14,8 +15,11 @@ use iam::policy_builder_for_method;
 use result::AuthResult;
 
 pub struct Claims {
-    pub principal_id: String,
-    pub scopes: Vec<&'static str>,
+    // Empty for api keys, which only have the scopes in their identity's permissions.
+    pub roles: Vec<Role>,
+    // Passed on to the api in the authorizer context, so it knows who the
+    // request is from and can check crate names the policy can't see.
+    pub identity: Identity,
 }
 
 pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
@@ -32,11 +36,11 @@ pub trait Authenticator {
 
     fn auth(
         event: &apigw::ApiGatewayCustomAuthorizerRequest,
-    ) -> BoxFuture<AuthResult<(Option<String>, apigw::ApiGatewayCustomAuthorizerPolicy)>> {
+    ) -> BoxFuture<AuthResult<(Claims, apigw::ApiGatewayCustomAuthorizerPolicy)>> {
         Box::pin(async move {
             let claims = Self::authenticate(&event).await?;
             let policy = Self::authorize(&event, &claims).await?;
-            Ok((Some(claims.principal_id), policy))
+            Ok((claims, policy))
         })
     }
 }
@@ -45,7 +49,6 @@ pub async fn authorizer_handler<T: Authenticator>(
     event: apigw::ApiGatewayCustomAuthorizerRequest,
     _ctx: Context,
 ) -> apigw::ApiGatewayCustomAuthorizerResponse<Value> {
-    log::info!("Client token: {:?}", event.authorization_token);
     log::info!("Method ARN: {:?}", event.method_arn);
 
     // validate the incoming token
@@ -56,14 +59,22 @@ pub async fn authorizer_handler<T: Authenticator>(
     // 2. Decode a JWT token inline
     // 3. Lookup in a self-managed DB
 
-    let (principal_id, policy) = T::auth(&event).await.unwrap_or_else(|err| {
-        log::info!("authentication failure: {:?}", err);
-
-        (
-            None,
-            policy_builder_for_method(&event).deny_all_methods().build(),
-        )
-    });
+    let (principal_id, policy, context) = match T::auth(&event).await {
+        Ok((claims, policy)) => (
+            Some(claims.identity.principal_id.clone()),
+            policy,
+            claims.identity.to_context(),
+        ),
+        Err(err) => {
+            log::info!("authentication failure: {:?}", err);
+
+            (
+                None,
+                policy_builder_for_method(&event).deny_all_methods().build(),
+                json!({}),
+            )
+        }
+    };
 
     // you can send a 401 Unauthorized response to the client by failing like so:
     // Err(HandlerError{ msg: "Unauthorized".to_string(), backtrace: None });
@@ -88,7 +99,7 @@ pub async fn authorizer_handler<T: Authenticator>(
     apigw::ApiGatewayCustomAuthorizerResponse {
         principal_id: principal_id,
         policy_document: policy,
-        context: json!({}),
+        context: context,
         usage_identifier_key: None,
     }
 }
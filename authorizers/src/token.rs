@@ -5,12 +5,24 @@ use aws_lambda_events::event::apigw;
 use crate::result::AuthResult;
 use crate::error::AuthError;
 use rusoto_core::Region;
-use rusoto_dynamodb::{AttributeValue, DynamoDb, DynamoDbClient, QueryInput};
+use rusoto_dynamodb::{AttributeValue, DynamoDb, DynamoDbClient, GetItemInput, QueryInput, UpdateItemInput};
+use rusoto_kms::{DecryptRequest, Kms, KmsClient};
+use double_checked_cell_async::DoubleCheckedCell;
+use api_types::tokens::{hash_token, TokenPermissions};
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 lazy_static! {
     static ref DYNAMODB_CLIENT: DynamoDbClient = DynamoDbClient::new(Region::default());
     static ref TOKENS_TABLE: String = env::var("TOKENS_TABLE").unwrap();
-    static ref TOKENS_TABLE_TOKENS_INDEX: String = env::var("TOKENS_TABLE_TOKENS_INDEX").unwrap();
+    static ref TOKENS_TABLE_TOKEN_HASH_INDEX: String = env::var("TOKENS_TABLE_TOKEN_HASH_INDEX").unwrap();
+    // Index on plaintext tokens, only needed until they have all been hashed.
+    static ref TOKENS_TABLE_TOKENS_INDEX: Option<String> = env::var("TOKENS_TABLE_TOKENS_INDEX").ok();
+    static ref LEGACY_TOKENS_TABLE: Option<String> = env::var("LEGACY_TOKENS_TABLE").ok();
+    static ref LEGACY_TOKENS_TABLE_TOKENS_INDEX: Option<String> = env::var("LEGACY_TOKENS_TABLE_TOKENS_INDEX").ok();
+    static ref KMS_CLIENT: KmsClient = KmsClient::new(Region::default());
+    static ref TOKEN_HASH_KEY: String = env::var("TOKEN_HASH_KEY").unwrap();
+    static ref TOKEN_HASH_KEY_CELL: DoubleCheckedCell<Vec<u8>> = DoubleCheckedCell::new();
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -45,37 +57,227 @@ impl AuthorizationHeader {
     }
 }
 
-pub async fn lookup_token(token: &str) -> AuthResult<Option<String>> {
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiToken {
+    pub user_id: String,
+    // None for tokens still in the legacy table
+    pub token_id: Option<String>,
+    pub permissions: TokenPermissions,
+    pub expires_at: Option<i64>,
+}
+
+pub async fn lookup_token(token: &str) -> AuthResult<Option<ApiToken>> {
+    let token_hash = hash_token(token_hash_key().await?, token);
+
+    if let Some(item) = query_tokens_index(&TOKENS_TABLE, &TOKENS_TABLE_TOKEN_HASH_INDEX, "token_hash", &token_hash).await? {
+        return found_token(&item).await;
+    }
+
+    // tokens saved in plaintext before they were hashed
+    if let Some(index) = TOKENS_TABLE_TOKENS_INDEX.as_ref() {
+        if let Some(item) = query_tokens_index(&TOKENS_TABLE, index, "token", token).await? {
+            if let (Some(user_id), Some(token_id)) = (get_s(&item, "user_id"), get_s(&item, "token_id")) {
+                hash_plaintext_token(&user_id, &token_id, token, &token_hash).await;
+            }
+            return found_token(&item).await;
+        }
+    }
+
+    // tokens the api hasn't moved out of the old single token table yet
+    match (LEGACY_TOKENS_TABLE.as_ref(), LEGACY_TOKENS_TABLE_TOKENS_INDEX.as_ref()) {
+        (Some(table), Some(index)) => Ok(query_tokens_index(table, index, "token", token)
+            .await?
+            .and_then(|item| get_s(&item, "user_id"))
+            .map(|user_id| ApiToken {
+                user_id,
+                token_id: None,
+                permissions: TokenPermissions::default(),
+                expires_at: None,
+            })),
+        _ => Ok(None),
+    }
+}
+
+// The indexes only project the keys, the scopes are on the token itself.
+async fn found_token(keys: &HashMap<String, AttributeValue>) -> AuthResult<Option<ApiToken>> {
+    let (user_id, token_id) = match (get_s(keys, "user_id"), get_s(keys, "token_id")) {
+        (Some(user_id), Some(token_id)) => (user_id, token_id),
+        _ => return Ok(None),
+    };
+
+    let output = DYNAMODB_CLIENT.get_item(GetItemInput {
+        key: token_key(&user_id, &token_id),
+        table_name: TOKENS_TABLE.clone(),
+        ..Default::default()
+    }).await
+        .map_err(|err| {
+            log::info!("error getting token {}: {:?}", token_id, err);
+
+            AuthError::DatabaseError("error querying database for api key".to_string())
+        })?;
+
+    let item = match output.item {
+        Some(item) => item,
+        // revoked since the index was read
+        None => return Ok(None),
+    };
+
+    // dynamodb can take a couple of days to delete items after their ttl
+    let expires_at = get_n(&item, "expires_at");
+    if is_expired(expires_at, unix_timestamp()) {
+        log::info!("token {} for user {} has expired", token_id, user_id);
+        return Ok(None);
+    }
+
+    record_token_use(&user_id, &token_id).await;
+
+    Ok(Some(ApiToken {
+        user_id,
+        token_id: Some(token_id),
+        permissions: token_permissions(&item),
+        expires_at,
+    }))
+}
+
+fn is_expired(expires_at: Option<i64>, now: i64) -> bool {
+    expires_at.map(|expires_at| expires_at <= now).unwrap_or(false)
+}
+
+fn unix_timestamp() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+// Tokens from before scopes were added can do anything, scopes this version
+// doesn't know about are dropped rather than failing the lookup.
+fn token_permissions(item: &HashMap<String, AttributeValue>) -> TokenPermissions {
+    let mut permissions = TokenPermissions::default();
+    if let Some(scopes) = get_l(item, "scopes") {
+        permissions.scopes = scopes.iter().filter_map(|scope| scope.parse().ok()).collect();
+    }
+    if let Some(crates) = get_l(item, "crates") {
+        permissions.crates = crates;
+    }
+    permissions
+}
+
+fn token_key(user_id: &str, token_id: &str) -> HashMap<String, AttributeValue> {
+    hashmap! {
+        "user_id".to_string() => AttributeValue { s: Some(user_id.to_owned()), ..Default::default() },
+        "token_id".to_string() => AttributeValue { s: Some(token_id.to_owned()), ..Default::default() },
+    }
+}
+
+async fn token_hash_key() -> AuthResult<&'static [u8]> {
+    let key = TOKEN_HASH_KEY_CELL.get_or_try_init(async {
+        let ciphertext = base64::decode(&*TOKEN_HASH_KEY)
+            .map_err(|err| AuthError::ApiKeyError(format!("invalid token hash key: {}", err)))?;
+
+        let output = KMS_CLIENT.decrypt(DecryptRequest {
+            ciphertext_blob: ciphertext.into(),
+            ..Default::default()
+        }).await
+            .map_err(|err| AuthError::ApiKeyError(format!("error decrypting token hash key: {}", err)))?;
+
+        output
+            .plaintext
+            .map(|b| b.to_vec())
+            .ok_or_else(|| AuthError::ApiKeyError("error decrypting token hash key".to_string()))
+    }).await?;
+
+    Ok(&key[..])
+}
+
+async fn query_tokens_index(table: &str, index: &str, attribute: &str, value: &str) -> AuthResult<Option<HashMap<String, AttributeValue>>> {
     let results = DYNAMODB_CLIENT.query(QueryInput {
         key_condition_expression: Some("#T = :token".to_string()),
         expression_attribute_values: Some(hashmap! {
-            ":token".to_string() => AttributeValue { s: Some(token.to_owned()), ..Default::default() }
+            ":token".to_string() => AttributeValue { s: Some(value.to_owned()), ..Default::default() }
         }),
         expression_attribute_names: Some(hashmap! {
-            "#T".to_string() => "token".to_string()
+            "#T".to_string() => attribute.to_owned()
         }),
-        table_name: TOKENS_TABLE.clone(),
-        index_name: Some(TOKENS_TABLE_TOKENS_INDEX.clone()),
+        table_name: table.to_owned(),
+        index_name: Some(index.to_owned()),
         ..Default::default()
     }).await
         .map_err(|err| {
             log::info!("error querying tokens index: {:?}", err);
 
-            AuthError::DatabaseError(format!("error querying database for api key"))
+            AuthError::DatabaseError("error querying database for api key".to_string())
         })?;
 
     log::debug!("{:?}", results);
 
     Ok(results
         .items
-        .and_then(|items| items.into_iter().next())
-        .and_then(|attrs| attrs.get("user_id").map(|v| v.to_owned()))
-        .and_then(|attr_value| attr_value.s))
+        .and_then(|items| items.into_iter().next()))
+}
+
+// Best effort like recording use, the api hashes anything left over when tokens are listed.
+async fn hash_plaintext_token(user_id: &str, token_id: &str, token: &str, token_hash: &str) {
+    let result = DYNAMODB_CLIENT.update_item(UpdateItemInput {
+        key: token_key(user_id, token_id),
+        update_expression: Some("SET token_hash = :token_hash REMOVE #T".to_string()),
+        condition_expression: Some("#T = :token".to_string()),
+        expression_attribute_names: Some(hashmap! {
+            "#T".to_string() => "token".to_string()
+        }),
+        expression_attribute_values: Some(hashmap! {
+            ":token".to_string() => AttributeValue { s: Some(token.to_owned()), ..Default::default() },
+            ":token_hash".to_string() => AttributeValue { s: Some(token_hash.to_owned()), ..Default::default() },
+        }),
+        table_name: TOKENS_TABLE.clone(),
+        ..Default::default()
+    }).await;
+
+    if let Err(err) = result {
+        log::info!("error hashing token {}: {:?}", token_id, err);
+    }
+}
+
+// Best effort, a failure here shouldn't lock the user out.
+async fn record_token_use(user_id: &str, token_id: &str) {
+    let now = unix_timestamp();
+
+    let result = DYNAMODB_CLIENT.update_item(UpdateItemInput {
+        key: token_key(user_id, token_id),
+        update_expression: Some("SET last_used_at = :now".to_string()),
+        condition_expression: Some("attribute_exists(token_id)".to_string()),
+        expression_attribute_values: Some(hashmap! {
+            ":now".to_string() => AttributeValue { n: Some(now.to_string()), ..Default::default() }
+        }),
+        table_name: TOKENS_TABLE.clone(),
+        ..Default::default()
+    }).await;
+
+    if let Err(err) = result {
+        log::info!("error recording use of token {}: {:?}", token_id, err);
+    }
+}
+
+fn get_s(item: &HashMap<String, AttributeValue>, key: &str) -> Option<String> {
+    item.get(key).and_then(|attr| attr.s.clone())
+}
+
+fn get_n(item: &HashMap<String, AttributeValue>, key: &str) -> Option<i64> {
+    item.get(key)
+        .and_then(|attr| attr.n.as_ref())
+        .and_then(|n| n.parse().ok())
+}
+
+fn get_l(item: &HashMap<String, AttributeValue>, key: &str) -> Option<Vec<String>> {
+    item.get(key)
+        .and_then(|attr| attr.l.as_ref())
+        .map(|l| l.iter().filter_map(|attr| attr.s.clone()).collect())
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use api_types::tokens::TokenScope;
 
     #[test]
     fn test_auth_bearer_token_from_value() {
@@ -91,6 +293,30 @@ mod test {
         assert_eq!(AuthorizationHeader::from_value(value), expected);
     }
 
+    #[test]
+    fn test_token_permissions() {
+        let string = |s: &str| AttributeValue { s: Some(s.to_owned()), ..Default::default() };
+        let list = |l: &[&str]| AttributeValue { l: Some(l.iter().map(|s| string(*s)).collect()), ..Default::default() };
+
+        let mut item = token_key("user", "token");
+        assert_eq!(token_permissions(&item), TokenPermissions::default());
+
+        item.insert("scopes".to_string(), list(&["publish", "teleport"]));
+        item.insert("crates".to_string(), list(&["foo-*"]));
+        assert_eq!(token_permissions(&item), TokenPermissions {
+            scopes: vec![TokenScope::Publish],
+            crates: vec!["foo-*".to_string()],
+        });
+    }
+
+    #[test]
+    fn test_is_expired() {
+        assert!(!is_expired(None, 1000));
+        assert!(!is_expired(Some(1001), 1000));
+        assert!(is_expired(Some(1000), 1000));
+        assert!(is_expired(Some(999), 1000));
+    }
+
     #[test]
     fn test_auth_bearer_token_from_req() {
         let req = apigw::ApiGatewayCustomAuthorizerRequest {
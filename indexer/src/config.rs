@@ -1,9 +1,12 @@
 use crate::error::IndexerError;
 use crate::result::IndexerResult;
+use api_types::index::IndexConfig;
 use rusoto_core::Region;
 use std::path::PathBuf;
 
 pub const DEFAULT_WORK_DIR: &str = "/tmp";
+pub const DEFAULT_AUTHOR_NAME: &str = "wagon";
+pub const DEFAULT_AUTHOR_EMAIL: &str = "wagon@localhost";
 
 pub struct ConfigBuilder {
     pub region: Region,
@@ -14,6 +17,11 @@ pub struct ConfigBuilder {
     pub username: Option<String>,
     pub password: Option<String>,
     pub persist_checkout: bool,
+    pub author_name: String,
+    pub author_email: String,
+    pub api_url: Option<String>,
+    pub dl_url: Option<String>,
+    pub auth_required: bool,
 }
 
 impl Default for ConfigBuilder {
@@ -27,6 +35,11 @@ impl Default for ConfigBuilder {
             username: None,
             password: None,
             persist_checkout: true,
+            author_name: DEFAULT_AUTHOR_NAME.to_string(),
+            author_email: DEFAULT_AUTHOR_EMAIL.to_string(),
+            api_url: None,
+            dl_url: None,
+            auth_required: true,
         }
     }
 }
@@ -40,6 +53,11 @@ pub struct Config {
     pub username: String,
     pub password: String,
     pub persist_checkout: bool,
+    pub author_name: String,
+    pub author_email: String,
+    pub api_url: String,
+    pub dl_url: Option<String>,
+    pub auth_required: bool,
 }
 
 impl Config {
@@ -48,6 +66,15 @@ impl Config {
         config.load()?;
         config.build()
     }
+
+    // The config.json that belongs at the root of the index.
+    pub fn index_config(&self) -> IndexConfig {
+        let mut index_config = IndexConfig::new(&self.api_url, self.auth_required);
+        if let Some(ref dl_url) = self.dl_url {
+            index_config.dl = dl_url.clone();
+        }
+        index_config
+    }
 }
 
 impl ConfigBuilder {
@@ -72,26 +99,52 @@ impl ConfigBuilder {
             self.remote_branch = branch;
         }
 
+        if let Some(name) = maybe_get_env_var("INDEXER_GIT_AUTHOR_NAME")? {
+            self.author_name = name;
+        }
+
+        if let Some(email) = maybe_get_env_var("INDEXER_GIT_AUTHOR_EMAIL")? {
+            self.author_email = email;
+        }
+
+        if let Some(api_url) = maybe_get_env_var("INDEXER_API_URL")? {
+            self.api_url = Some(api_url);
+        }
+
+        if let Some(dl_url) = maybe_get_env_var("INDEXER_DL_URL")? {
+            self.dl_url = Some(dl_url);
+        }
+
+        if let Some(auth_required) = maybe_get_env_var("INDEXER_AUTH_REQUIRED")? {
+            self.auth_required = auth_required != "false";
+        }
+
         Ok(())
     }
 
     pub fn validate(&self) -> IndexerResult<()> {
         if self.index_git_url.is_none() {
-            return Err(IndexerError::ConfigError(format!(
-                "missing git index url config setting"
-            )));
+            return Err(IndexerError::ConfigError(
+                "missing git index url config setting".to_string(),
+            ));
         }
 
         if self.username.is_none() {
-            return Err(IndexerError::ConfigError(format!(
-                "missing git username config setting"
-            )));
+            return Err(IndexerError::ConfigError(
+                "missing git username config setting".to_string(),
+            ));
         }
 
         if self.password.is_none() {
-            return Err(IndexerError::ConfigError(format!(
-                "missing git password config setting"
-            )));
+            return Err(IndexerError::ConfigError(
+                "missing git password config setting".to_string(),
+            ));
+        }
+
+        if self.api_url.is_none() {
+            return Err(IndexerError::ConfigError(
+                "missing api url config setting".to_string(),
+            ));
         }
 
         Ok(())
@@ -109,6 +162,11 @@ impl ConfigBuilder {
             remote_name: self.remote_name,
             remote_branch: self.remote_branch,
             persist_checkout: self.persist_checkout,
+            author_name: self.author_name,
+            author_email: self.author_email,
+            api_url: self.api_url.unwrap(),
+            dl_url: self.dl_url,
+            auth_required: self.auth_required,
         })
     }
 }
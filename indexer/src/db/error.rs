@@ -1,5 +1,7 @@
 use rusoto_core::RusotoError;
-use rusoto_dynamodb::{GetItemError, PutItemError, UpdateItemError};
+use rusoto_dynamodb::{
+    DeleteItemError, GetItemError, PutItemError, QueryError, ScanError, UpdateItemError,
+};
 use std::num::ParseIntError;
 
 #[derive(Debug)]
@@ -7,6 +9,10 @@ pub enum DbError {
     GetItemError(RusotoError<GetItemError>),
     PutItemError(RusotoError<PutItemError>),
     UpdateItemError(RusotoError<UpdateItemError>),
+    DeleteItemError(RusotoError<DeleteItemError>),
+    QueryError(RusotoError<QueryError>),
+    ScanError(RusotoError<ScanError>),
+    SerializationError(serde_json::Error),
     RegistryNotFound(String),
     InvalidRegistry(String),
     NoUpdateAttributes,
@@ -15,6 +21,12 @@ pub enum DbError {
     ConfigError(String),
 }
 
+impl From<serde_json::Error> for DbError {
+    fn from(e: serde_json::Error) -> DbError {
+        DbError::SerializationError(e)
+    }
+}
+
 impl From<ParseIntError> for DbError {
     fn from(e: ParseIntError) -> DbError {
         DbError::ParseIntError(e)
@@ -1,22 +1,38 @@
 pub mod error;
+pub mod packages;
 pub mod registries;
 pub mod result;
+pub mod summaries;
 
 use error::DbError;
 use result::DbResult;
+use rusoto_dynamodb::AttributeValue;
+use std::collections::HashMap;
 
 pub struct DbConfig {
     pub registries_table: String,
+    pub packages_table: String,
+    pub packages_name_index: String,
+    pub packages_pending_index: String,
+    pub summaries_table: String,
 }
 
 pub struct DbConfigBuilder {
     pub registries_table: Option<String>,
+    pub packages_table: Option<String>,
+    pub packages_name_index: Option<String>,
+    pub packages_pending_index: Option<String>,
+    pub summaries_table: Option<String>,
 }
 
 impl Default for DbConfigBuilder {
     fn default() -> Self {
         DbConfigBuilder {
             registries_table: None,
+            packages_table: None,
+            packages_name_index: None,
+            packages_pending_index: None,
+            summaries_table: None,
         }
     }
 }
@@ -35,6 +51,22 @@ impl DbConfigBuilder {
             self.registries_table = Some(table);
         }
 
+        if let Some(table) = maybe_get_env_var("INDEXER_PACKAGES_TABLE")? {
+            self.packages_table = Some(table);
+        }
+
+        if let Some(index) = maybe_get_env_var("INDEXER_PACKAGES_NAME_INDEX")? {
+            self.packages_name_index = Some(index);
+        }
+
+        if let Some(index) = maybe_get_env_var("INDEXER_PACKAGES_PENDING_INDEX")? {
+            self.packages_pending_index = Some(index);
+        }
+
+        if let Some(table) = maybe_get_env_var("INDEXER_SUMMARIES_TABLE")? {
+            self.summaries_table = Some(table);
+        }
+
         Ok(())
     }
 
@@ -45,6 +77,30 @@ impl DbConfigBuilder {
             ));
         }
 
+        if self.packages_table.is_none() {
+            return Err(DbError::ConfigError(
+                "missing packages table setting".to_string(),
+            ));
+        }
+
+        if self.packages_name_index.is_none() {
+            return Err(DbError::ConfigError(
+                "missing packages name index setting".to_string(),
+            ));
+        }
+
+        if self.packages_pending_index.is_none() {
+            return Err(DbError::ConfigError(
+                "missing packages pending index setting".to_string(),
+            ));
+        }
+
+        if self.summaries_table.is_none() {
+            return Err(DbError::ConfigError(
+                "missing summaries table setting".to_string(),
+            ));
+        }
+
         Ok(())
     }
 
@@ -53,6 +109,10 @@ impl DbConfigBuilder {
 
         Ok(DbConfig {
             registries_table: self.registries_table.unwrap(),
+            packages_table: self.packages_table.unwrap(),
+            packages_name_index: self.packages_name_index.unwrap(),
+            packages_pending_index: self.packages_pending_index.unwrap(),
+            summaries_table: self.summaries_table.unwrap(),
         })
     }
 }
@@ -68,3 +128,43 @@ pub fn maybe_get_env_var(name: &str) -> DbResult<Option<String>> {
         Err(e) => Err(DbError::ConfigError(format!("config key {}: {}", name, e))),
     }
 }
+
+pub fn add_item_value<K: Into<String>>(
+    m: &mut HashMap<String, AttributeValue>,
+    key: K,
+    v: AttributeValue,
+) {
+    m.insert(key.into(), v);
+}
+
+pub fn long_attr_value(v: i64) -> AttributeValue {
+    AttributeValue {
+        n: Some(v.to_string()),
+        ..Default::default()
+    }
+}
+
+pub fn maybe_string_attr_value<S: Into<String>>(s: Option<S>) -> AttributeValue {
+    AttributeValue {
+        s: s.map(|s| s.into()),
+        ..Default::default()
+    }
+}
+
+pub fn string_attr_value<S: Into<String>>(s: S) -> AttributeValue {
+    maybe_string_attr_value(Some(s))
+}
+
+pub fn null_attr_value() -> AttributeValue {
+    AttributeValue {
+        null: Some(true),
+        ..Default::default()
+    }
+}
+
+pub fn bool_attr_value(v: bool) -> AttributeValue {
+    AttributeValue {
+        bool: Some(v),
+        ..Default::default()
+    }
+}
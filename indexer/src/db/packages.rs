@@ -0,0 +1,302 @@
+use super::error::DbError;
+use super::result::DbResult;
+use super::{bool_attr_value, null_attr_value, string_attr_value, DbConfig};
+use api_types::index::{canonical_crate_name, IndexEntry};
+use rusoto_core::RusotoError;
+use rusoto_dynamodb::{
+    AttributeValue, DynamoDb, DynamoDbClient, QueryInput, UpdateItemError, UpdateItemInput,
+};
+use std::collections::HashMap;
+
+// The index_status the api gives versions it has queued for the indexer,
+// removed once they're in git.
+pub const PENDING_INDEX_STATUS: &str = "pending";
+
+// The index-related parts of an item in the packages table.
+// The api writes new and yanked versions with indexed = false
+// and it's up to the indexer to get them into git.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Package {
+    pub entry: IndexEntry,
+    // Only set for versions published through the api, for the crate's search summary.
+    pub description: Option<String>,
+    pub keywords: Vec<String>,
+    pub published_at: Option<i64>,
+    pub indexed: bool,
+    pub deleted: bool,
+}
+
+impl Package {
+    pub fn key(name: &str, version: &str) -> HashMap<String, AttributeValue> {
+        hashmap! {
+            "name".to_string() => string_attr_value(name),
+            "version".to_string() => string_attr_value(version),
+        }
+    }
+
+    pub fn from_attributes(attrs: &HashMap<String, AttributeValue>) -> DbResult<Package> {
+        let get_s = |key: &str| {
+            attrs
+                .get(key)
+                .and_then(|attr| attr.s.clone())
+                .ok_or_else(|| DbError::InvalidValue(format!("missing {}", key)))
+        };
+        let get_bool = |key: &str| {
+            attrs
+                .get(key)
+                .and_then(|attr| attr.bool)
+                .unwrap_or_default()
+        };
+
+        Ok(Package {
+            entry: IndexEntry {
+                name: get_s("name")?,
+                vers: get_s("version")?,
+                deps: serde_json::from_str(&get_s("deps")?)?,
+                cksum: get_s("cksum")?,
+                features: serde_json::from_str(&get_s("features")?)?,
+                yanked: get_bool("yanked"),
+                links: attrs.get("links").and_then(|attr| attr.s.clone()),
+            },
+            description: attrs.get("description").and_then(|attr| attr.s.clone()),
+            keywords: attrs
+                .get("keywords")
+                .and_then(|attr| attr.l.as_ref())
+                .map(|l| l.iter().filter_map(|attr| attr.s.clone()).collect())
+                .unwrap_or_default(),
+            published_at: attrs
+                .get("published_at")
+                .and_then(|attr| attr.n.as_ref())
+                .map(|n| n.parse::<i64>())
+                .transpose()?,
+            indexed: get_bool("indexed"),
+            deleted: get_bool("deleted"),
+        })
+    }
+}
+
+// Versions the api has queued for the indexer. Only those have an index_status,
+// so this reads the sparse index on it rather than every version in the table.
+pub async fn get_unindexed_packages(
+    client: &DynamoDbClient,
+    config: &DbConfig,
+) -> DbResult<Vec<Package>> {
+    let mut packages = vec![];
+    let mut exclusive_start_key = None;
+
+    loop {
+        let output = client
+            .query(QueryInput {
+                index_name: Some(config.packages_pending_index.clone()),
+                key_condition_expression: Some("index_status = :index_status".to_string()),
+                expression_attribute_values: Some(hashmap! {
+                    ":index_status".to_string() => string_attr_value(PENDING_INDEX_STATUS),
+                }),
+                exclusive_start_key,
+                table_name: config.packages_table.clone(),
+                ..Default::default()
+            })
+            .await
+            .map_err(|e| DbError::QueryError(e))?;
+
+        for item in output.items.unwrap_or_default().iter() {
+            packages.push(Package::from_attributes(item)?);
+        }
+
+        exclusive_start_key = output.last_evaluated_key;
+        if exclusive_start_key.is_none() {
+            break;
+        }
+    }
+
+    log::info!("found {} unindexed packages", packages.len());
+    Ok(packages)
+}
+
+pub async fn get_packages(
+    name: &str,
+    client: &DynamoDbClient,
+    config: &DbConfig,
+) -> DbResult<Vec<Package>> {
+    let mut packages = vec![];
+    let mut exclusive_start_key = None;
+
+    loop {
+        let output = client
+            .query(QueryInput {
+                key_condition_expression: Some("#N = :name".to_string()),
+                expression_attribute_names: Some(hashmap! {"#N".to_string() => "name".to_string()}),
+                expression_attribute_values: Some(hashmap! {
+                    ":name".to_string() => string_attr_value(name),
+                }),
+                exclusive_start_key,
+                table_name: config.packages_table.clone(),
+                ..Default::default()
+            })
+            .await
+            .map_err(|e| DbError::QueryError(e))?;
+
+        for item in output.items.unwrap_or_default().iter() {
+            packages.push(Package::from_attributes(item)?);
+        }
+
+        exclusive_start_key = output.last_evaluated_key;
+        if exclusive_start_key.is_none() {
+            break;
+        }
+    }
+
+    Ok(packages)
+}
+
+// Every version whose name is the same once canonicalized, through the name index.
+// For finding versions from an index file, whose name is lowercased and which has
+// no entries left to give the name as published once it's been removed.
+pub async fn get_packages_by_canonical_name(
+    canonical_name: &str,
+    client: &DynamoDbClient,
+    config: &DbConfig,
+) -> DbResult<Vec<Package>> {
+    let mut packages = vec![];
+    let mut exclusive_start_key = None;
+
+    loop {
+        let output = client
+            .query(QueryInput {
+                index_name: Some(config.packages_name_index.clone()),
+                key_condition_expression: Some("canonical_name = :canonical_name".to_string()),
+                expression_attribute_values: Some(hashmap! {
+                    ":canonical_name".to_string() => string_attr_value(canonical_name),
+                }),
+                exclusive_start_key,
+                table_name: config.packages_table.clone(),
+                ..Default::default()
+            })
+            .await
+            .map_err(|e| DbError::QueryError(e))?;
+
+        for item in output.items.unwrap_or_default().iter() {
+            packages.push(Package::from_attributes(item)?);
+        }
+
+        exclusive_start_key = output.last_evaluated_key;
+        if exclusive_start_key.is_none() {
+            break;
+        }
+    }
+
+    Ok(packages)
+}
+
+// Only marks the package as indexed if it hasn't been yanked or unyanked
+// since we read it, otherwise it stays queued for the next run.
+pub async fn mark_indexed(
+    package: &Package,
+    client: &DynamoDbClient,
+    config: &DbConfig,
+) -> DbResult<bool> {
+    let input = UpdateItemInput {
+        key: Package::key(&package.entry.name, &package.entry.vers),
+        update_expression: Some("SET indexed = :indexed REMOVE index_status".to_string()),
+        condition_expression: Some("yanked = :yanked".to_string()),
+        expression_attribute_values: Some(hashmap! {
+            ":indexed".to_string() => bool_attr_value(true),
+            ":yanked".to_string() => bool_attr_value(package.entry.yanked),
+        }),
+        table_name: config.packages_table.clone(),
+        ..Default::default()
+    };
+
+    match client.update_item(input).await {
+        Ok(_) => Ok(true),
+        Err(RusotoError::Service(UpdateItemError::ConditionalCheckFailed(msg))) => {
+            log::info!(
+                "{} {} changed while indexing: {}",
+                package.entry.name,
+                package.entry.vers,
+                msg
+            );
+            Ok(false)
+        }
+        Err(e) => Err(DbError::UpdateItemError(e)),
+    }
+}
+
+// Mirrors an entry from the git index into the table, leaving the
+// api's own attributes (description, keywords, publisher) alone.
+// Versions the api has queued for the indexer are skipped so a pending
+// yank isn't overwritten by the older state in git.
+pub async fn upsert_index_entry(
+    entry: &IndexEntry,
+    client: &DynamoDbClient,
+    config: &DbConfig,
+) -> DbResult<bool> {
+    let input = UpdateItemInput {
+        key: Package::key(&entry.name, &entry.vers),
+        update_expression: Some(
+            "SET canonical_name = :canonical_name, deps = :deps, cksum = :cksum, \
+             features = :features, yanked = :yanked, links = :links, indexed = :indexed, \
+             deleted = :deleted"
+                .to_string(),
+        ),
+        condition_expression: Some("attribute_not_exists(#V) OR indexed = :indexed".to_string()),
+        expression_attribute_names: Some(hashmap! {"#V".to_string() => "version".to_string()}),
+        expression_attribute_values: Some(hashmap! {
+            ":canonical_name".to_string() => string_attr_value(canonical_crate_name(&entry.name)),
+            ":deps".to_string() => string_attr_value(serde_json::to_string(&entry.deps)?),
+            ":cksum".to_string() => string_attr_value(entry.cksum.clone()),
+            ":features".to_string() => string_attr_value(serde_json::to_string(&entry.features)?),
+            ":yanked".to_string() => bool_attr_value(entry.yanked),
+            ":links".to_string() => match entry.links {
+                Some(ref links) => string_attr_value(links.clone()),
+                None => null_attr_value(),
+            },
+            ":indexed".to_string() => bool_attr_value(true),
+            ":deleted".to_string() => bool_attr_value(false),
+        }),
+        table_name: config.packages_table.clone(),
+        ..Default::default()
+    };
+
+    match client.update_item(input).await {
+        Ok(_) => Ok(true),
+        Err(RusotoError::Service(UpdateItemError::ConditionalCheckFailed(msg))) => {
+            log::info!("{} {} is pending: {}", entry.name, entry.vers, msg);
+            Ok(false)
+        }
+        Err(e) => Err(DbError::UpdateItemError(e)),
+    }
+}
+
+// For versions that have been removed from the git index.
+pub async fn mark_deleted(
+    package: &Package,
+    client: &DynamoDbClient,
+    config: &DbConfig,
+) -> DbResult<bool> {
+    let input = UpdateItemInput {
+        key: Package::key(&package.entry.name, &package.entry.vers),
+        update_expression: Some("SET deleted = :deleted".to_string()),
+        condition_expression: Some("indexed = :indexed".to_string()),
+        expression_attribute_values: Some(hashmap! {
+            ":deleted".to_string() => bool_attr_value(true),
+            ":indexed".to_string() => bool_attr_value(true),
+        }),
+        table_name: config.packages_table.clone(),
+        ..Default::default()
+    };
+
+    match client.update_item(input).await {
+        Ok(_) => Ok(true),
+        Err(RusotoError::Service(UpdateItemError::ConditionalCheckFailed(msg))) => {
+            log::info!(
+                "{} {} is pending: {}",
+                package.entry.name,
+                package.entry.vers,
+                msg
+            );
+            Ok(false)
+        }
+        Err(e) => Err(DbError::UpdateItemError(e)),
+    }
+}
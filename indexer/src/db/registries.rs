@@ -1,6 +1,6 @@
 use super::error::DbError;
 use super::result::DbResult;
-use super::DbConfig;
+use super::{add_item_value, long_attr_value, maybe_string_attr_value, string_attr_value, DbConfig};
 use rusoto_dynamodb::{
     AttributeValue, DynamoDb, DynamoDbClient, GetItemInput, PutItemInput,
     UpdateItemInput,
@@ -247,29 +247,3 @@ pub async fn update_registry_with_retry(
         "update registry: tries is 0".to_string(),
     )))
 }*/
-
-fn add_item_value<K: Into<String>>(
-    m: &mut HashMap<String, AttributeValue>,
-    key: K,
-    v: AttributeValue,
-) {
-    m.insert(key.into(), v);
-}
-
-fn long_attr_value(v: i64) -> AttributeValue {
-    AttributeValue {
-        n: Some(v.to_string()),
-        ..Default::default()
-    }
-}
-
-fn maybe_string_attr_value<S: Into<String>>(s: Option<S>) -> AttributeValue {
-    AttributeValue {
-        s: s.map(|s| s.into()),
-        ..Default::default()
-    }
-}
-
-fn string_attr_value<S: Into<String>>(s: S) -> AttributeValue {
-    maybe_string_attr_value(Some(s))
-}
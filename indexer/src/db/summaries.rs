@@ -0,0 +1,116 @@
+use super::error::DbError;
+use super::packages::{self, Package};
+use super::result::DbResult;
+use super::{long_attr_value, null_attr_value, string_attr_value, DbConfig};
+use rusoto_dynamodb::{AttributeValue, DeleteItemInput, DynamoDb, DynamoDbClient, PutItemInput};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// The api's search reads one summary item per crate, which it refreshes when
+// versions are published or yanked through it. Versions mirrored from git or
+// removed from it don't go through the api, so the indexer refreshes those the
+// same way: the newest version that hasn't been yanked or deleted, or no summary.
+pub async fn refresh_summary(
+    name: &str,
+    client: &DynamoDbClient,
+    config: &DbConfig,
+) -> DbResult<()> {
+    let packages = packages::get_packages(name, client, config).await?;
+
+    match newest_version(&packages) {
+        Some((max_version, package)) => {
+            let item = hashmap! {
+                "name".to_string() => string_attr_value(name),
+                "max_version".to_string() => string_attr_value(max_version.to_string()),
+                "description".to_string() => match package.description {
+                    Some(ref description) => string_attr_value(description.clone()),
+                    None => null_attr_value(),
+                },
+                "keywords".to_string() => AttributeValue {
+                    l: Some(package.keywords.iter().map(|k| string_attr_value(k.clone())).collect()),
+                    ..Default::default()
+                },
+                "updated_at".to_string() => long_attr_value(unix_timestamp()),
+            };
+            client
+                .put_item(PutItemInput {
+                    item,
+                    table_name: config.summaries_table.clone(),
+                    ..Default::default()
+                })
+                .await
+                .map_err(|e| DbError::PutItemError(e))?;
+        }
+        None => {
+            client
+                .delete_item(DeleteItemInput {
+                    key: hashmap! {"name".to_string() => string_attr_value(name)},
+                    table_name: config.summaries_table.clone(),
+                    ..Default::default()
+                })
+                .await
+                .map_err(|e| DbError::DeleteItemError(e))?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn newest_version(packages: &[Package]) -> Option<(semver::Version, &Package)> {
+    packages
+        .iter()
+        .filter(|p| !p.entry.yanked && !p.deleted)
+        .filter_map(|p| match semver::Version::parse(&p.entry.vers) {
+            Ok(version) => Some((version, p)),
+            Err(e) => {
+                log::warn!("skipping {} {}: {}", p.entry.name, p.entry.vers, e);
+                None
+            }
+        })
+        .max_by(|a, b| a.0.cmp(&b.0))
+}
+
+fn unix_timestamp() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use api_types::index::IndexEntry;
+
+    fn package(vers: &str, yanked: bool, deleted: bool) -> Package {
+        Package {
+            entry: IndexEntry {
+                name: "rand".to_string(),
+                vers: vers.to_string(),
+                deps: vec![],
+                cksum: "cksum".to_string(),
+                features: Default::default(),
+                yanked,
+                links: None,
+            },
+            description: None,
+            keywords: vec![],
+            published_at: None,
+            indexed: true,
+            deleted,
+        }
+    }
+
+    #[test]
+    fn test_newest_version_excludes_yanked_and_deleted() {
+        let packages = vec![
+            package("0.6.1", false, false),
+            package("0.6.10", true, false),
+            package("0.6.9", false, true),
+            package("0.6.2", false, false),
+        ];
+        let (version, _) = newest_version(&packages).expect("newest");
+        assert_eq!(version.to_string(), "0.6.2");
+
+        assert!(newest_version(&[package("0.6.9", false, true)]).is_none());
+    }
+}
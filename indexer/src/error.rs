@@ -11,6 +11,9 @@ pub enum IndexerError {
     GitError(git2::Error),
     CloneDirectoryAlreadyExists,
     DbError(DbError),
+    SerializationError(serde_json::Error),
+    // An index file that would end up with entries for more than one crate name.
+    NameConflict(String),
 }
 
 impl Error for IndexerError {}
@@ -44,3 +47,9 @@ impl From<DbError> for IndexerError {
         IndexerError::DbError(e)
     }
 }
+
+impl From<serde_json::Error> for IndexerError {
+    fn from(e: serde_json::Error) -> Self {
+        IndexerError::SerializationError(e)
+    }
+}
@@ -0,0 +1,111 @@
+use crate::config::Config;
+use crate::repo::Repo;
+use crate::result::IndexerResult;
+use api_types::index::IndexConfig;
+
+pub const CONFIG_FILE: &str = "config.json";
+
+// Makes sure config.json at the root of the index matches our config,
+// which also gives an empty index its first commit.
+// Returns true if the file was written.
+pub fn ensure_index_config(repo: &Repo, config: &Config) -> IndexerResult<bool> {
+    let expected = config.index_config();
+    let existing = repo.read_file(CONFIG_FILE)?;
+
+    if !needs_update(existing.as_deref(), &expected) {
+        return Ok(false);
+    }
+
+    let message = if repo.is_empty()? {
+        "Initialise index"
+    } else {
+        "Update config.json"
+    };
+
+    repo.write_file(CONFIG_FILE, &config_file_contents(&expected)?)?;
+    repo.commit(&[CONFIG_FILE], message)?;
+    repo.push()?;
+
+    Ok(true)
+}
+
+pub fn needs_update(existing: Option<&str>, expected: &IndexConfig) -> bool {
+    match existing.map(serde_json::from_str::<IndexConfig>) {
+        Some(Ok(existing)) if existing == *expected => false,
+        Some(Ok(existing)) => {
+            log::warn!("{} is {:?}, expected {:?}", CONFIG_FILE, existing, expected);
+            true
+        }
+        Some(Err(e)) => {
+            log::warn!("{} is invalid: {}", CONFIG_FILE, e);
+            true
+        }
+        None => {
+            log::info!("{} is missing", CONFIG_FILE);
+            true
+        }
+    }
+}
+
+pub fn config_file_contents(index_config: &IndexConfig) -> IndexerResult<String> {
+    let mut contents = serde_json::to_string_pretty(index_config)?;
+    contents.push('\n');
+    Ok(contents)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::ConfigBuilder;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_needs_update() {
+        let expected = IndexConfig::new("https://example.com", true);
+        let contents = config_file_contents(&expected).expect("contents");
+
+        assert!(!needs_update(Some(&contents), &expected));
+        assert!(needs_update(None, &expected));
+        assert!(needs_update(Some("not json"), &expected));
+        assert!(needs_update(
+            Some(&contents.replace("example.com", "example.org")),
+            &expected
+        ));
+    }
+
+    #[test]
+    fn test_bootstrap_empty_index() {
+        let tmp = TempDir::new("index_config").expect("tempdir");
+        let remote_path = tmp.path().join("remote.git");
+        git2::Repository::init_bare(&remote_path).expect("init");
+
+        let config = ConfigBuilder {
+            index_git_url: Some(remote_path.to_string_lossy().to_string()),
+            work_dir: tmp.path().to_path_buf(),
+            username: Some("user".to_string()),
+            password: Some("password".to_string()),
+            api_url: Some("https://example.com".to_string()),
+            ..Default::default()
+        }
+        .build()
+        .expect("config");
+
+        let repo = Repo::new(&config).expect("repo");
+        repo.checkout().expect("checkout");
+        assert!(ensure_index_config(&repo, &config).expect("bootstrap"));
+        assert!(!ensure_index_config(&repo, &config).expect("no drift"));
+
+        let remote = git2::Repository::open_bare(&remote_path).expect("open");
+        let head = remote.refname_to_id("refs/heads/master").expect("pushed");
+        assert_eq!(head.to_string(), repo.head_commit_id().expect("head"));
+
+        // the next run fetches into the same checkout and corrects the drifted url
+        let mut config = config;
+        config.api_url = "https://example.org".to_string();
+        let repo = Repo::new(&config).expect("repo");
+        repo.checkout().expect("checkout");
+        assert!(ensure_index_config(&repo, &config).expect("fix drift"));
+        let contents = repo.read_file(CONFIG_FILE).expect("read").expect("exists");
+        assert!(contents.contains("https://example.org/api/v1/crates"));
+    }
+}
@@ -0,0 +1,201 @@
+use crate::db::packages::{self, Package};
+use crate::db::summaries;
+use crate::db::DbConfig;
+use crate::repo::Repo;
+use crate::result::IndexerResult;
+use api_types::index::{canonical_crate_name, index_path, parse_index_file, IndexEntry};
+use rusoto_dynamodb::DynamoDbClient;
+use std::collections::{BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
+
+// Copies the versions in each changed index file into the packages table,
+// so the api has a queryable copy of the git index.
+// Returns the number of versions updated.
+pub async fn mirror_changed_files(
+    repo: &Repo,
+    changed_files: &HashSet<PathBuf>,
+    client: &DynamoDbClient,
+    db_config: &DbConfig,
+) -> IndexerResult<usize> {
+    let mut count = 0;
+
+    for path in changed_files.iter() {
+        let name = match crate_name_for_path(path) {
+            Some(name) => name,
+            None => {
+                log::debug!("skipping {:?}", path);
+                continue;
+            }
+        };
+
+        // removed files have no versions left
+        let entries = match repo.read_file(path)? {
+            Some(contents) => match parse_index_file(&contents) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    log::error!("skipping invalid index file {:?}: {}", path, e);
+                    continue;
+                }
+            },
+            None => vec![],
+        };
+
+        count += mirror_crate(name, &entries, client, db_config).await?;
+    }
+
+    Ok(count)
+}
+
+pub async fn mirror_crate(
+    name: &str,
+    entries: &[IndexEntry],
+    client: &DynamoDbClient,
+    db_config: &DbConfig,
+) -> IndexerResult<usize> {
+    let mut count = 0;
+
+    for entry in entries.iter() {
+        if packages::upsert_index_entry(entry, client, db_config).await? {
+            count += 1;
+        }
+    }
+
+    let mut names: BTreeSet<String> = entries.iter().map(|e| e.name.clone()).collect();
+    let packages =
+        packages::get_packages_by_canonical_name(&canonical_crate_name(name), client, db_config)
+            .await?;
+
+    for package in removed_versions(name, entries, &packages) {
+        if packages::mark_deleted(package, client, db_config).await? {
+            log::info!(
+                "{} {} removed from index",
+                package.entry.name,
+                package.entry.vers
+            );
+            names.insert(package.entry.name.clone());
+            count += 1;
+        }
+    }
+
+    // search reads summaries, which the api only refreshes for its own publishes and yanks
+    if count > 0 {
+        for name in names.iter() {
+            summaries::refresh_summary(name, client, db_config).await?;
+        }
+    }
+
+    Ok(count)
+}
+
+// The mirrored versions of the crate whose index file is `name` that are no longer in it.
+// The file name is lowercased and the entries, if there are any left, have the name as
+// published, so `packages` are looked up by canonical name. Names that only differ in - and _
+// share a canonical name but have their own files, so those are left alone.
+pub fn removed_versions<'a>(
+    name: &str,
+    entries: &[IndexEntry],
+    packages: &'a [Package],
+) -> Vec<&'a Package> {
+    let versions: HashSet<(&str, &str)> =
+        entries.iter().map(|e| (&e.name[..], &e.vers[..])).collect();
+
+    packages
+        .iter()
+        .filter(|p| p.entry.name.to_lowercase() == name)
+        .filter(|p| p.indexed && !p.deleted)
+        .filter(|p| !versions.contains(&(&p.entry.name[..], &p.entry.vers[..])))
+        .collect()
+}
+
+// The crate an index file belongs to, or None for anything else in the repo like config.json.
+pub fn crate_name_for_path(path: &Path) -> Option<&str> {
+    let name = path.file_name()?.to_str()?;
+
+    if name.is_ascii() && path.to_str()? == index_path(name) {
+        Some(name)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn package(name: &str, vers: &str, indexed: bool, deleted: bool) -> Package {
+        Package {
+            entry: entry(name, vers),
+            description: None,
+            keywords: vec![],
+            published_at: None,
+            indexed,
+            deleted,
+        }
+    }
+
+    fn entry(name: &str, vers: &str) -> IndexEntry {
+        IndexEntry {
+            name: name.to_string(),
+            vers: vers.to_string(),
+            deps: vec![],
+            cksum: "cksum".to_string(),
+            features: Default::default(),
+            yanked: false,
+            links: None,
+        }
+    }
+
+    fn versions(packages: Vec<&Package>) -> Vec<(&str, &str)> {
+        packages
+            .into_iter()
+            .map(|p| (&p.entry.name[..], &p.entry.vers[..]))
+            .collect()
+    }
+
+    #[test]
+    fn test_crate_name_for_path() {
+        assert_eq!(crate_name_for_path(Path::new("1/a")), Some("a"));
+        assert_eq!(crate_name_for_path(Path::new("3/s/syn")), Some("syn"));
+        assert_eq!(crate_name_for_path(Path::new("se/rd/serde")), Some("serde"));
+        assert_eq!(crate_name_for_path(Path::new("config.json")), None);
+        assert_eq!(crate_name_for_path(Path::new("se/rd/other")), None);
+        assert_eq!(
+            crate_name_for_path(Path::new(".github/workflows/ci.yml")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_removed_versions_of_a_removed_mixed_case_file() {
+        let packages = vec![
+            package("MixedCase", "0.1.0", true, false),
+            package("MixedCase", "0.2.0", true, false),
+            package("MixedCase", "0.3.0", false, false),
+            package("MixedCase", "0.0.1", true, true),
+        ];
+        assert_eq!(
+            versions(removed_versions("mixedcase", &[], &packages)),
+            vec![("MixedCase", "0.1.0"), ("MixedCase", "0.2.0")]
+        );
+        assert_eq!(
+            versions(removed_versions(
+                "mixedcase",
+                &[entry("MixedCase", "0.1.0")],
+                &packages
+            )),
+            vec![("MixedCase", "0.2.0")]
+        );
+    }
+
+    #[test]
+    fn test_removed_versions_leave_other_files_alone() {
+        let packages = vec![
+            package("foo-bar", "0.1.0", true, false),
+            package("foo_bar", "0.1.0", true, false),
+        ];
+        assert_eq!(
+            versions(removed_versions("foo-bar", &[], &packages)),
+            vec![("foo-bar", "0.1.0")]
+        );
+    }
+}
@@ -0,0 +1,161 @@
+use crate::db::packages::{self, Package};
+use crate::db::DbConfig;
+use crate::error::IndexerError;
+use crate::repo::Repo;
+use crate::result::IndexerResult;
+use api_types::index::{index_file_contents, index_path, parse_index_file, IndexEntry};
+use rusoto_dynamodb::DynamoDbClient;
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+// Writes every version the api has queued up into the index, then commits and pushes.
+// Returns the number of versions published.
+pub async fn publish_pending(
+    repo: &Repo,
+    client: &DynamoDbClient,
+    db_config: &DbConfig,
+) -> IndexerResult<usize> {
+    let pending = packages::get_unindexed_packages(client, db_config).await?;
+    if pending.is_empty() {
+        return Ok(0);
+    }
+
+    let pending_names: BTreeSet<&str> = pending.iter().map(|p| &p.entry.name[..]).collect();
+    let mut names = BTreeSet::new();
+    let mut paths = vec![];
+    for name in pending_names.into_iter() {
+        let crate_packages = packages::get_packages(name, client, db_config).await?;
+        match write_crate_index(repo, name, &crate_packages) {
+            Ok(path) => {
+                paths.push(path);
+                names.insert(name);
+            }
+            // left pending, without holding up the other crates
+            Err(IndexerError::NameConflict(msg)) => log::error!("not indexing {}: {}", name, msg),
+            Err(e) => return Err(e),
+        }
+    }
+    if names.is_empty() {
+        return Ok(0);
+    }
+
+    let message = format!(
+        "Update {}",
+        names.iter().cloned().collect::<Vec<&str>>().join(", ")
+    );
+    repo.commit(&paths, &message)?;
+    repo.push()?;
+
+    let mut count = 0;
+    for package in pending.iter().filter(|p| names.contains(&p.entry.name[..])) {
+        packages::mark_indexed(package, client, db_config).await?;
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+pub fn write_crate_index(repo: &Repo, name: &str, packages: &[Package]) -> IndexerResult<PathBuf> {
+    let path = PathBuf::from(index_path(name));
+
+    let existing = match repo.read_file(&path)? {
+        Some(contents) => parse_index_file(&contents)?,
+        None => vec![],
+    };
+
+    let mut packages: Vec<&Package> = packages.iter().filter(|p| !p.deleted).collect();
+    packages.sort_by(|a, b| (a.published_at, &a.entry.vers).cmp(&(b.published_at, &b.entry.vers)));
+    let updates = packages.into_iter().map(|p| p.entry.clone()).collect();
+
+    let entries = merge_entries(existing, updates)?;
+    log::info!("writing {} entries for {} to {:?}", entries.len(), name, path);
+    repo.write_file(&path, &index_file_contents(&entries)?)?;
+
+    Ok(path)
+}
+
+// Replaces existing entries in place and appends new versions,
+// so lines already in the index keep their order.
+// Names differing only in case share an index file, so an update for another
+// spelling of the name than the file already has is refused rather than replacing its versions.
+pub fn merge_entries(
+    existing: Vec<IndexEntry>,
+    mut updates: Vec<IndexEntry>,
+) -> IndexerResult<Vec<IndexEntry>> {
+    let name = match existing.first().or_else(|| updates.first()) {
+        Some(entry) => entry.name.clone(),
+        None => return Ok(vec![]),
+    };
+    if let Some(other) = existing
+        .iter()
+        .chain(updates.iter())
+        .find(|entry| entry.name != name)
+    {
+        return Err(IndexerError::NameConflict(format!(
+            "{} {} can't go in the index file of {}",
+            other.name, other.vers, name
+        )));
+    }
+
+    let mut entries: Vec<IndexEntry> = existing
+        .into_iter()
+        .map(|entry| {
+            match updates
+                .iter()
+                .position(|update| update.name == entry.name && update.vers == entry.vers)
+            {
+                Some(i) => updates.remove(i),
+                None => entry,
+            }
+        })
+        .collect();
+    entries.append(&mut updates);
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry(vers: &str, yanked: bool) -> IndexEntry {
+        named_entry("foo", vers, yanked)
+    }
+
+    fn named_entry(name: &str, vers: &str, yanked: bool) -> IndexEntry {
+        IndexEntry {
+            name: name.to_string(),
+            vers: vers.to_string(),
+            deps: vec![],
+            cksum: "cksum".to_string(),
+            features: Default::default(),
+            yanked,
+            links: None,
+        }
+    }
+
+    #[test]
+    fn test_merge_entries() {
+        let existing = vec![entry("0.1.0", false), entry("0.2.0", false)];
+        let updates = vec![entry("0.3.0", false), entry("0.1.0", true)];
+        let merged = merge_entries(existing, updates).expect("merge");
+        assert_eq!(
+            merged,
+            vec![entry("0.1.0", true), entry("0.2.0", false), entry("0.3.0", false)]
+        );
+    }
+
+    #[test]
+    fn test_merge_entries_refuses_other_names() {
+        let existing = vec![entry("0.1.0", false)];
+        let updates = vec![named_entry("Foo", "0.1.0", false)];
+        assert!(matches!(
+            merge_entries(existing.clone(), updates),
+            Err(IndexerError::NameConflict(_))
+        ));
+
+        let updates = vec![entry("0.2.0", false), named_entry("Foo", "0.3.0", false)];
+        assert!(merge_entries(vec![], updates).is_err());
+
+        assert_eq!(merge_entries(vec![], vec![]).expect("merge"), vec![]);
+    }
+}
@@ -3,7 +3,7 @@ use crate::error::IndexerError;
 use crate::result::IndexerResult;
 use crate::work_dir::WorkDir;
 use git2::build::RepoBuilder;
-use git2::{Cred, Direction, FetchOptions, RemoteCallbacks, Repository};
+use git2::{Cred, Direction, FetchOptions, PushOptions, RemoteCallbacks, Repository};
 use std::cell::RefCell;
 use std::collections::HashSet;
 use std::fmt;
@@ -40,6 +40,8 @@ pub struct Repo {
     pub remote_branch: String,
     pub username: String,
     pub password: String,
+    pub author_name: String,
+    pub author_email: String,
     state: RefCell<RepoState>,
 }
 
@@ -58,6 +60,8 @@ impl Repo {
             remote_branch: config.remote_branch.clone(),
             username: config.username.clone(),
             password: config.password.clone(),
+            author_name: config.author_name.clone(),
+            author_email: config.author_email.clone(),
             state: RefCell::new(RepoState::None),
         })
     }
@@ -81,11 +85,11 @@ impl Repo {
         }
     }
 
+    // An empty remote leaves HEAD unborn, ready for the first commit.
     pub fn checkout(&self) -> IndexerResult<()> {
         if !self.dot_git_exists() {
             self.clone()?;
-        } else {
-            let id = self.fetch()?;
+        } else if let Some(id) = self.fetch()? {
             self.reset_head(id)?;
         }
         log::info!("checkout {} in {:?}", self.remote_url, self.dir);
@@ -218,7 +222,7 @@ impl Repo {
         Self::path_exists(&self.dot_git_path())
     }
 
-    pub fn clone(&self) -> IndexerResult<git2::Oid> {
+    pub fn clone(&self) -> IndexerResult<Option<git2::Oid>> {
         if self.dot_git_exists() {
             return Err(IndexerError::CloneDirectoryAlreadyExists);
         }
@@ -229,15 +233,47 @@ impl Repo {
 
         *self.state.borrow_mut() = RepoState::Open(git_repo.clone());
 
-        Ok(git_repo.refname_to_id("HEAD")?)
+        if git_repo.is_empty()? {
+            log::info!("cloned empty repo {}", self.remote_url);
+            git_repo.set_head(&format!("refs/heads/{}", self.remote_branch))?;
+            return Ok(None);
+        }
+
+        Ok(Some(git_repo.refname_to_id("HEAD")?))
+    }
+
+    pub fn is_empty(&self) -> IndexerResult<bool> {
+        let git_repo = self.open()?;
+        Ok(git_repo.is_empty()?)
+    }
+
+    // Asks the remote whether the branch exists yet, which it won't for a new index.
+    pub fn remote_branch_exists(&self) -> IndexerResult<bool> {
+        let git_repo = self.open()?;
+        let mut remote = git_repo.find_remote(&self.remote_name)?;
+
+        let connection =
+            remote.connect_auth(Direction::Fetch, Some(self.remote_callbacks()), None)?;
+        let refname = format!("refs/heads/{}", self.remote_branch);
+        let exists = connection.list()?.iter().any(|head| head.name() == refname);
+        Ok(exists)
     }
 
-    pub fn fetch(&self) -> IndexerResult<git2::Oid> {
+    pub fn fetch(&self) -> IndexerResult<Option<git2::Oid>> {
         //let git_repo = Repository::open(&self.path)?;
         let remote_name = self.remote_name.clone();
 
         let git_repo = self.open()?;
 
+        if !self.remote_branch_exists()? {
+            log::info!(
+                "remote branch {} doesn't exist yet in {}",
+                self.remote_branch,
+                self.remote_url
+            );
+            return Ok(None);
+        }
+
         let mut remote = git_repo.find_remote(&remote_name)?;
 
         let mut fo = self.fetch_options();
@@ -248,7 +284,7 @@ impl Repo {
         );
         remote.fetch(&[&self.remote_branch], Some(&mut fo), None)?;
 
-        Ok(git_repo.refname_to_id("FETCH_HEAD")?)
+        Ok(Some(git_repo.refname_to_id("FETCH_HEAD")?))
     }
 
     pub fn reset_head(&self, oid: git2::Oid) -> IndexerResult<()> {
@@ -273,7 +309,82 @@ impl Repo {
         Ok(())
     }
 
-    pub fn fetch_options<'a>(&'a self) -> FetchOptions<'a> {
+    pub fn read_file<P: AsRef<Path>>(&self, path: P) -> IndexerResult<Option<String>> {
+        match fs::read_to_string(self.path.join(path)) {
+            Ok(contents) => Ok(Some(contents)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn write_file<P: AsRef<Path>>(&self, path: P, contents: &str) -> IndexerResult<()> {
+        let file_path = self.path.join(path);
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&file_path, contents)?;
+        Ok(())
+    }
+
+    // Stages the given paths, relative to the work tree, and commits them on top of HEAD.
+    pub fn commit<P: AsRef<Path>>(&self, paths: &[P], message: &str) -> IndexerResult<git2::Oid> {
+        let git_repo = self.open()?;
+
+        let mut index = git_repo.index()?;
+        for path in paths {
+            if self.path.join(path.as_ref()).exists() {
+                index.add_path(path.as_ref())?;
+            } else {
+                index.remove_path(path.as_ref())?;
+            }
+        }
+        index.write()?;
+
+        let tree = git_repo.find_tree(index.write_tree()?)?;
+        let signature = git2::Signature::now(&self.author_name, &self.author_email)?;
+
+        // a freshly initialised index has no commits yet
+        let parent = match git_repo.head() {
+            Ok(head) => Some(head.peel_to_commit()?),
+            Err(e) if e.code() == git2::ErrorCode::UnbornBranch => None,
+            Err(e) if e.code() == git2::ErrorCode::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        if let Some(ref parent) = parent {
+            if parent.tree_id() == tree.id() {
+                log::info!("nothing to commit for {}", message);
+                return Ok(parent.id());
+            }
+        }
+        let parents: Vec<&git2::Commit> = parent.iter().collect();
+
+        let oid = git_repo.commit(Some("HEAD"), &signature, &signature, message, &tree, &parents)?;
+        log::info!("committed {}: {}", oid, message);
+        Ok(oid)
+    }
+
+    pub fn push(&self) -> IndexerResult<()> {
+        let git_repo = self.open()?;
+        let mut remote = git_repo.find_remote(&self.remote_name)?;
+
+        let mut callbacks = self.remote_callbacks();
+        callbacks.push_update_reference(|refname, status| match status {
+            Some(message) => Err(git2::Error::from_str(&format!(
+                "push to {} rejected: {}",
+                refname, message
+            ))),
+            None => Ok(()),
+        });
+        let mut po = PushOptions::new();
+        po.remote_callbacks(callbacks);
+
+        let refspec = format!("refs/heads/{0}:refs/heads/{0}", self.remote_branch);
+        log::info!("pushing {} to {}", refspec, self.remote_url);
+        remote.push(&[&refspec], Some(&mut po))?;
+        Ok(())
+    }
+
+    pub fn remote_callbacks<'a>(&'a self) -> RemoteCallbacks<'a> {
         let mut callbacks = RemoteCallbacks::new();
 
         callbacks.transfer_progress(|stats| {
@@ -298,8 +409,13 @@ impl Repo {
         callbacks.credentials(move |_url, _username_from_url, _allowed_types| {
             Cred::userpass_plaintext(&self.username, &self.password)
         });
+
+        callbacks
+    }
+
+    pub fn fetch_options<'a>(&'a self) -> FetchOptions<'a> {
         let mut fo = git2::FetchOptions::new();
-        fo.remote_callbacks(callbacks);
+        fo.remote_callbacks(self.remote_callbacks());
 
         fo
     }
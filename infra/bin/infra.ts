@@ -17,6 +17,7 @@ const dashboard = new DashboardStack(app, "WagonDashboard", {name: "wagon"});
 const tokensDb = new TokensDbStack(app, "WagonTokensDb", {
     dashboard: dashboard.dashboard,
 });
+const indexerStack = new IndexerStack(app, "WagonIndexer");
 const authStack = new AuthStack(app, "WagonAuth", {
     user_pool_id: env.USER_POOL_ID!,
 });
@@ -37,6 +38,7 @@ const certStack = new CertStack(app, "WagonCert", {
 const apiStack = new WagonApiStack(app, "WagonApi", {
     dashboard: dashboard.dashboard,
     tokens_db_stack: tokensDb,
+    indexer_stack: indexerStack,
     user_pool_id: env.USER_POOL_ID!,
     apiDomain: "api",
     zoneName: "octomonkey.cloud",
@@ -11,9 +11,12 @@ import * as ddb from "aws-cdk-lib/aws-dynamodb";
 import { DashboardStack } from "./dashboard-stack";
 import { WagonApiStack } from "./wagon-api-stack";
 import { TokensDbStack } from "./tokens-db-stack";
+import { IndexerStack } from "./indexer-stack";
 
 export interface ApiHandlerStackProps extends cdk.StackProps {
     token_db_stack: TokensDbStack;
+    indexer_stack: IndexerStack;
+    api_url: string;
 }
 
 export class ApiHandlerStack extends cdk.Stack {
@@ -39,6 +42,12 @@ export class ApiHandlerStack extends cdk.Stack {
         }));
     
         props.token_db_stack.tokensTable.grantReadWriteData(lambdaRole);
+        props.token_db_stack.tokenHashKey.grantDecrypt(lambdaRole);
+        props.token_db_stack.legacyTokensTable.grantReadWriteData(lambdaRole);
+        props.indexer_stack.packages_table.grantReadWriteData(lambdaRole);
+        props.indexer_stack.owners_table.grantReadWriteData(lambdaRole);
+        props.indexer_stack.summaries_table.grantReadWriteData(lambdaRole);
+        props.indexer_stack.crates_bucket.grantReadWrite(lambdaRole);
     
         this.handler = new lambda.Function(this, "Function", {
             runtime: lambda.Runtime.PROVIDED_AL2,
@@ -50,7 +59,18 @@ export class ApiHandlerStack extends cdk.Stack {
             environment: {
                 RUST_LOG: 'info,api=debug',
                 TOKENS_TABLE: props.token_db_stack.tokensTable.tableName,
-                TOKENS_TABLE_TOKENS_INDEX: props.token_db_stack.tokensIndexName,
+                TOKEN_HASH_KEY: ssm.StringParameter.valueForStringParameter(this, props.token_db_stack.tokenHashKeyParameterName),
+                TOKEN_EXPIRY_WARNING_DAYS: '7',
+                LEGACY_TOKENS_TABLE: props.token_db_stack.legacyTokensTable.tableName,
+                PACKAGES_TABLE: props.indexer_stack.packages_table.tableName,
+                PACKAGES_TABLE_NAME_INDEX: props.indexer_stack.packages_name_index,
+                OWNERS_TABLE: props.indexer_stack.owners_table.tableName,
+                SUMMARIES_TABLE: props.indexer_stack.summaries_table.tableName,
+                STORAGE_BACKEND: 's3',
+                CRATES_BUCKET: props.indexer_stack.crates_bucket.bucketName,
+                API_URL: props.api_url,
+                INDEX_AUTH_REQUIRED: 'true',
+                TRUSTED_PUBLISHING_AUDIENCE: props.api_url,
             },
         });
     }
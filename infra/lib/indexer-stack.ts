@@ -5,11 +5,17 @@ import * as iam from "aws-cdk-lib/aws-iam";
 import * as path from "path";
 import * as apigw from "aws-cdk-lib/aws-apigateway";
 import * as dynamodb from "aws-cdk-lib/aws-dynamodb";
+import * as s3 from "aws-cdk-lib/aws-s3";
 import { CfnOutput } from "aws-cdk-lib";
 
 export class IndexerStack extends cdk.Stack {
     registries_table: dynamodb.ITable;
-    packages_table: dynamodb.ITable;
+    packages_table: dynamodb.Table;
+    packages_name_index: string;
+    packages_pending_index: string;
+    owners_table: dynamodb.ITable;
+    summaries_table: dynamodb.ITable;
+    crates_bucket: s3.IBucket;
 
   constructor(scope: Construct, id: string, props?: cdk.StackProps) {
     super(scope, id, props);
@@ -24,5 +30,35 @@ export class IndexerStack extends cdk.Stack {
         partitionKey: { name: 'name', type: dynamodb.AttributeType.STRING },
         sortKey: { name: 'version', type: dynamodb.AttributeType.STRING },
     });
+    this.packages_name_index = 'NameIndex';
+    this.packages_table.addGlobalSecondaryIndex({
+        indexName: this.packages_name_index,
+        partitionKey: { name: 'canonical_name', type: dynamodb.AttributeType.STRING },
+        projectionType: dynamodb.ProjectionType.ALL,
+    });
+    // sparse, only versions waiting for the indexer have an index_status
+    this.packages_pending_index = 'PendingIndex';
+    this.packages_table.addGlobalSecondaryIndex({
+        indexName: this.packages_pending_index,
+        partitionKey: { name: 'index_status', type: dynamodb.AttributeType.STRING },
+        projectionType: dynamodb.ProjectionType.ALL,
+    });
+
+    this.owners_table = new dynamodb.Table(this, "OwnersTable", {
+        billingMode: dynamodb.BillingMode.PAY_PER_REQUEST,
+        partitionKey: { name: 'name', type: dynamodb.AttributeType.STRING },
+        sortKey: { name: 'user_id', type: dynamodb.AttributeType.STRING },
+    });
+
+    this.summaries_table = new dynamodb.Table(this, "CrateSummariesTable", {
+        billingMode: dynamodb.BillingMode.PAY_PER_REQUEST,
+        partitionKey: { name: 'name', type: dynamodb.AttributeType.STRING },
+    });
+
+    this.crates_bucket = new s3.Bucket(this, "CratesBucket", {
+        blockPublicAccess: s3.BlockPublicAccess.BLOCK_ALL,
+        encryption: s3.BucketEncryption.S3_MANAGED,
+        removalPolicy: cdk.RemovalPolicy.RETAIN,
+    });
   }
 }
@@ -8,6 +8,7 @@ import * as cw from "aws-cdk-lib/aws-cloudwatch";
 import * as ssm from "aws-cdk-lib/aws-ssm";
 import * as logs from "aws-cdk-lib/aws-logs";
 import * as ddb from "aws-cdk-lib/aws-dynamodb";
+import * as kms from "aws-cdk-lib/aws-kms";
 import { DashboardStack } from "./dashboard-stack";
 import { WagonApiStack } from "./wagon-api-stack";
 
@@ -18,11 +19,17 @@ export interface TokensApiStackProps extends cdk.StackProps {
 export class TokensDbStack extends cdk.Stack {
     tokensTable: ddb.Table;
     tokensIndexName: string;
+    tokenHashIndexName: string;
+    tokenHashKey: kms.Key;
+    tokenHashKeyParameterName: string;
+    legacyTokensTable: ddb.Table;
+    legacyTokensIndexName: string;
 
     constructor(scope: Construct, id: string, props: TokensApiStackProps) {
         super(scope, id, props);
 
-        this.tokensTable = new ddb.Table(this, 'Tokens', {
+        // one token per user, tokens are moved out of here into ApiTokens by the api
+        this.legacyTokensTable = new ddb.Table(this, 'Tokens', {
             partitionKey: {
                 name: 'user_id', type: ddb.AttributeType.STRING
             },
@@ -30,6 +37,29 @@ export class TokensDbStack extends cdk.Stack {
             encryption: ddb.TableEncryption.DEFAULT,
         });
 
+        this.legacyTokensIndexName = 'TokensIndex';
+        this.legacyTokensTable.addGlobalSecondaryIndex({
+            indexName: this.legacyTokensIndexName,
+            partitionKey: {
+                name: 'token',
+                type: ddb.AttributeType.STRING,
+            },
+            projectionType: ddb.ProjectionType.KEYS_ONLY,
+        });
+
+        this.tokensTable = new ddb.Table(this, 'ApiTokens', {
+            partitionKey: {
+                name: 'user_id', type: ddb.AttributeType.STRING
+            },
+            sortKey: {
+                name: 'token_id', type: ddb.AttributeType.STRING
+            },
+            billingMode: ddb.BillingMode.PAY_PER_REQUEST,
+            encryption: ddb.TableEncryption.DEFAULT,
+            timeToLiveAttribute: 'expires_at',
+        });
+
+        // plaintext tokens, can be dropped once the api's migrate_tokens has been run
         this.tokensIndexName = 'TokensIndex';
         this.tokensTable.addGlobalSecondaryIndex({
             indexName: this.tokensIndexName,
@@ -39,5 +69,23 @@ export class TokensDbStack extends cdk.Stack {
             },
             projectionType: ddb.ProjectionType.KEYS_ONLY,
         });
+
+        this.tokenHashIndexName = 'TokenHashIndex';
+        this.tokensTable.addGlobalSecondaryIndex({
+            indexName: this.tokenHashIndexName,
+            partitionKey: {
+                name: 'token_hash',
+                type: ddb.AttributeType.STRING,
+            },
+            projectionType: ddb.ProjectionType.KEYS_ONLY,
+        });
+
+        // the key tokens are hashed with is stored encrypted under this one,
+        // see scripts/create-token-hash-key.sh
+        this.tokenHashKey = new kms.Key(this, 'TokenHashKey', {
+            alias: 'wagon/token-hash-key',
+            enableKeyRotation: true,
+        });
+        this.tokenHashKeyParameterName = '/wagon/token-hash-key';
     }
-}
+}
\ No newline at end of file
@@ -15,12 +15,14 @@ import { TokensDbStack } from "./tokens-db-stack";
 import { LogsWidgetStack } from "./logs-widget-stack";
 import { ApiHandlerStack } from "./api-handler-stack";
 import { SSMParameterReader } from './ssm-param-reader';
+import { IndexerStack } from "./indexer-stack";
 
 
 export interface WagonApiStackProps extends cdk.StackProps {
   dashboard: cw.Dashboard;
   user_pool_id: string;
   tokens_db_stack: TokensDbStack;
+  indexer_stack: IndexerStack;
   apiDomain: string;
   zoneName: string;
   zoneId: string;
@@ -56,6 +58,8 @@ export class WagonApiStack extends cdk.Stack {
 
     const handlerStack = new ApiHandlerStack(this, 'ApiHandler', {
       token_db_stack: props.tokens_db_stack,
+      indexer_stack: props.indexer_stack,
+      api_url: `https://${this.domainName}`,
     });
 
     const authorizerRole = new iam.Role(this, "AuthorizerFunctionRole", {
@@ -69,6 +73,8 @@ export class WagonApiStack extends cdk.Stack {
     );
 
     props.tokens_db_stack.tokensTable.grantReadWriteData(authorizerRole);
+    props.tokens_db_stack.legacyTokensTable.grantReadData(authorizerRole);
+    props.tokens_db_stack.tokenHashKey.grantDecrypt(authorizerRole);
 
     // const authorizerHandler = new lambda.Function(this, "AuthorizerFunction", {
     //     runtime: lambda.Runtime.PROVIDED_AL2,
@@ -82,7 +88,11 @@ export class WagonApiStack extends cdk.Stack {
     //         OPENID_CONFIGURATION_URI: props.openid_config_uri,
     //         OPENID_AUD: props.openid_aud,
     //         TOKENS_TABLE: props.tokens_db_stack.tokensTable.tableName,
+    //         TOKENS_TABLE_TOKEN_HASH_INDEX: props.tokens_db_stack.tokenHashIndexName,
     //         TOKENS_TABLE_TOKENS_INDEX: props.tokens_db_stack.tokensIndexName,
+    //         TOKEN_HASH_KEY: ssm.StringParameter.valueForStringParameter(this, props.tokens_db_stack.tokenHashKeyParameterName),
+    //         LEGACY_TOKENS_TABLE: props.tokens_db_stack.legacyTokensTable.tableName,
+    //         LEGACY_TOKENS_TABLE_TOKENS_INDEX: props.tokens_db_stack.legacyTokensIndexName,
     //     },
     // });
 
@@ -117,7 +127,7 @@ export class WagonApiStack extends cdk.Stack {
         passthroughBehavior: apigw.PassthroughBehavior.WHEN_NO_MATCH,
       }),
       binaryMediaTypes: [
-        "application/octet-stream"
+        "*/*"
       ],
     });
 
@@ -137,6 +147,8 @@ export class WagonApiStack extends cdk.Stack {
     GET / => get_root,
         GET /api/token => get_token,
         POST /api/token => create_token,
+        GET /api/tokens => list_tokens,
+        DELETE /api/tokens/{token_id: String} => revoke_token,
         PUT /api/v1/crates/new => new_crate,
         GET /api/v1/crates => search_crates,
         GET /api/v1/crates/{library: String}/{version: String}/download => download_crate,
@@ -145,6 +157,12 @@ export class WagonApiStack extends cdk.Stack {
         GET /api/v1/crates/{library: String}/owners => get_crate_owners,
         PUT /api/v1/crates/{library: String}/owners => add_crate_owner,
         DELETE /api/v1/crates/{library: String}/owners => remove_crate_owner,
+        GET /api/v1/crates/{library: String}/trusted_publishers => get_trust_policies,
+        PUT /api/v1/crates/{library: String}/trusted_publishers => add_trust_policy,
+        DELETE /api/v1/crates/{library: String}/trusted_publishers/{id: String} => remove_trust_policy,
+        POST /api/v1/trusted_publishing/tokens => exchange_trusted_publishing_token,
+        GET /index/config.json => get_index_config,
+        GET /index/{prefix}/{name} => get_index_file,
     */
     const api_resource = api.root.addResource('api');
 
@@ -152,19 +170,28 @@ export class WagonApiStack extends cdk.Stack {
     api_token_resource.addMethod('GET', undefined, {authorizationScopes: ['wagon-api/read', 'wagon-api/write']});
     api_token_resource.addMethod('POST', undefined, {authorizationScopes: ['wagon-api/write']});
 
+    const api_tokens_resource = api_resource.addResource('tokens');
+    api_tokens_resource.addMethod('GET', undefined, {authorizationScopes: ['wagon-api/read', 'wagon-api/write']});
+
+    const api_tokens_token_resource = api_tokens_resource.addResource('{token_id}');
+    api_tokens_token_resource.addMethod('DELETE', undefined, {authorizationScopes: ['wagon-api/write']});
+
     const api_v1_resource = api_resource.addResource('v1');
     
+    // github actions exchange their oidc token here, it's checked by the api rather than the authorizer
+    const api_v1_trusted_publishing_resource = api_v1_resource.addResource('trusted_publishing');
+    const api_v1_trusted_publishing_tokens_resource = api_v1_trusted_publishing_resource.addResource('tokens');
+    api_v1_trusted_publishing_tokens_resource.addMethod('POST', undefined, {
+      authorizationType: apigw.AuthorizationType.NONE,
+    });
+
     const api_v1_crates_resource = api_v1_resource.addResource('crates');
     api_v1_crates_resource.addMethod('GET');
 
+    // cargo doesn't send a content-type with the publish body,
+    // so binaryMediaTypes must match everything for it to arrive intact.
     const api_v1_crates_resource_new = api_v1_crates_resource.addResource('new');
-    api_v1_crates_resource_new.addMethod('PUT', new apigw.LambdaIntegration(handlerStack.handler, {
-      contentHandling: apigw.ContentHandling.CONVERT_TO_TEXT,
-      requestTemplates: {
-        'application/octet-stream': JSON.stringify({ body: '$input.body' }),
-      },
-      passthroughBehavior: apigw.PassthroughBehavior.WHEN_NO_MATCH,
-    }));
+    api_v1_crates_resource_new.addMethod('PUT');
 
     const api_v1_crates_crate_resource = api_v1_crates_resource.addResource('{crate}');
 
@@ -173,16 +200,28 @@ export class WagonApiStack extends cdk.Stack {
     api_v1_crates_crate_owners_resource.addMethod('PUT');
     api_v1_crates_crate_owners_resource.addMethod('DELETE');
 
+    const api_v1_crates_crate_trusted_publishers_resource = api_v1_crates_crate_resource.addResource('trusted_publishers');
+    api_v1_crates_crate_trusted_publishers_resource.addMethod('GET');
+    api_v1_crates_crate_trusted_publishers_resource.addMethod('PUT');
+
+    const api_v1_crates_crate_trusted_publishers_id_resource = api_v1_crates_crate_trusted_publishers_resource.addResource('{id}');
+    api_v1_crates_crate_trusted_publishers_id_resource.addMethod('DELETE');
+
     const api_v1_crates_crate_version_resource = api_v1_crates_crate_resource.addResource('{version}');
     
     const api_v1_crates_crate_version_download_resource = api_v1_crates_crate_version_resource.addResource('download');
     api_v1_crates_crate_version_download_resource.addMethod('GET');
 
     const api_v1_crates_crate_version_yank_resource = api_v1_crates_crate_version_resource.addResource('yank');
-    api_v1_crates_crate_version_download_resource.addMethod('DELETE');
+    api_v1_crates_crate_version_yank_resource.addMethod('DELETE');
     
     const api_v1_crates_crate_version_unyank_resource = api_v1_crates_crate_version_resource.addResource('unyank');
-    api_v1_crates_crate_version_download_resource.addMethod('PUT');
+    api_v1_crates_crate_version_unyank_resource.addMethod('PUT');
+
+    // sparse index for cargo's sparse+https:// protocol
+    const index_resource = api.root.addResource('index');
+    const index_file_resource = index_resource.addResource('{path+}');
+    index_file_resource.addMethod('GET');
 
     new cdk.CfnOutput(this, 'WagonApiDomainNameOutput', {
       value: this.domainName,
@@ -0,0 +1,500 @@
+// End to end tests of the registry: the api behind the standalone server,
+// a bare git repo standing in for the index on github, and the indexer in between.
+//
+// Everything runs against the in-memory repositories. Set WAGON_DYNAMODB_LOCAL to
+// a DynamoDB Local endpoint, eg. http://localhost:8000, to run the same tests
+// against dynamodb too. Rusoto still wants AWS_ACCESS_KEY_ID and
+// AWS_SECRET_ACCESS_KEY to be set, to anything.
+
+use api::config::DEFAULT_TOKEN_EXPIRY_WARNING_DAYS;
+use api::tokens;
+use api_types::index::index_path;
+use api_types::tokens::TokenPermissions;
+use git2::Repository;
+use hyper::header::AUTHORIZATION;
+use hyper::{HeaderMap, StatusCode};
+use indexer::config::{Config as IndexerConfig, ConfigBuilder};
+use indexer::db::packages::Package as IndexerPackage;
+use indexer::db::DbConfig;
+use indexer::index_config;
+use indexer::publish;
+use indexer::repo::Repo;
+use rusoto_core::Region;
+use rusoto_dynamodb::{
+    AttributeDefinition, CreateTableInput, DynamoDb, DynamoDbClient, GlobalSecondaryIndex,
+    KeySchemaElement, Projection,
+};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use server::config::{AuthConfig, DatabaseConfig, ServerConfig, StorageConfig, TablesConfig};
+use server::service::{self, Server};
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tempdir::TempDir;
+
+pub const API_URL: &str = "http://wagon.test";
+pub const DYNAMODB_LOCAL_ENV: &str = "WAGON_DYNAMODB_LOCAL";
+
+const DYNAMODB_LOCAL_REGION: &str = "us-east-1";
+const TOKEN_HASH_KEY: &str = "c2VjcmV0";
+
+static HARNESS_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+pub enum Backend {
+    Memory,
+    DynamoDbLocal { region: Region, db_config: DbConfig },
+}
+
+pub struct Harness {
+    pub server: Arc<Server>,
+    pub backend: Backend,
+    pub remote_path: PathBuf,
+    pub remote_url: String,
+    dir: TempDir,
+}
+
+impl Harness {
+    pub fn memory() -> Harness {
+        Self::new(DatabaseConfig::Memory, Backend::Memory)
+    }
+
+    // None if DynamoDB Local isn't configured, so the tests can skip it.
+    // Every harness gets its own tables, which are left behind for DynamoDB Local to forget.
+    pub async fn dynamodb_local() -> Option<Harness> {
+        let endpoint = std::env::var(DYNAMODB_LOCAL_ENV).ok()?;
+        let region = Region::Custom {
+            name: DYNAMODB_LOCAL_REGION.to_string(),
+            endpoint: endpoint.clone(),
+        };
+
+        let suffix = unique_suffix();
+        let tables = TablesConfig {
+            tokens: format!("ApiTokens-{}", suffix),
+            token_hash_index: "TokenHashIndex".to_string(),
+            packages: format!("Packages-{}", suffix),
+            package_name_index: "NameIndex".to_string(),
+            owners: format!("Owners-{}", suffix),
+            summaries: format!("CrateSummaries-{}", suffix),
+        };
+        let db_config = DbConfig {
+            registries_table: format!("Registries-{}", suffix),
+            packages_table: tables.packages.clone(),
+            packages_name_index: tables.package_name_index.clone(),
+            packages_pending_index: "PendingIndex".to_string(),
+            summaries_table: tables.summaries.clone(),
+        };
+        create_tables(&DynamoDbClient::new(region.clone()), &tables, &db_config).await;
+
+        let database = DatabaseConfig::Dynamodb {
+            endpoint,
+            region: DYNAMODB_LOCAL_REGION.to_string(),
+            tables,
+        };
+        Some(Self::new(
+            database,
+            Backend::DynamoDbLocal { region, db_config },
+        ))
+    }
+
+    fn new(database: DatabaseConfig, backend: Backend) -> Harness {
+        let dir = TempDir::new("wagon").expect("tempdir");
+        let remote_path = dir.path().join("index.git");
+        Repository::init_bare(&remote_path).expect("init remote");
+
+        let config = ServerConfig {
+            listen: ([127, 0, 0, 1], 0).into(),
+            api_url: API_URL.to_string(),
+            index_auth_required: true,
+            token_expiry_warning_days: DEFAULT_TOKEN_EXPIRY_WARNING_DAYS,
+            trusted_publishing_audience: None,
+            storage: StorageConfig {
+                dir: dir.path().join("crates"),
+            },
+            database,
+            auth: AuthConfig {
+                token_hash_key: TOKEN_HASH_KEY.to_string(),
+                openid_configuration_uri: None,
+                openid_aud: None,
+                issuers: vec![],
+            },
+        };
+
+        Harness {
+            server: Arc::new(Server::new(&config).expect("server")),
+            backend,
+            remote_url: format!("file://{}", remote_path.display()),
+            remote_path,
+            dir,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self.backend {
+            Backend::Memory => "memory",
+            Backend::DynamoDbLocal { .. } => "dynamodb local",
+        }
+    }
+
+    // An api key for the user, the one `cargo login` would be given.
+    pub async fn token(&self, user: &str, permissions: TokenPermissions) -> String {
+        tokens::create_user_token(&self.server.state, user, "integration", permissions, None)
+            .await
+            .expect("create token")
+            .token
+    }
+
+    pub async fn request(
+        &self,
+        method: &str,
+        uri: &str,
+        token: Option<&str>,
+        body: Vec<u8>,
+    ) -> TestResponse {
+        let mut request = hyper::Request::builder().method(method).uri(uri);
+        if let Some(token) = token {
+            request = request.header(AUTHORIZATION, token);
+        }
+        let request = request.body(hyper::Body::from(body)).expect("request");
+
+        let response = service::handle(self.server.clone(), request)
+            .await
+            .expect("response");
+        let (parts, body) = response.into_parts();
+        let body = hyper::body::to_bytes(body).await.expect("body");
+
+        TestResponse {
+            status: parts.status,
+            headers: parts.headers,
+            body: body.to_vec(),
+        }
+    }
+
+    pub async fn get(&self, uri: &str, token: Option<&str>) -> TestResponse {
+        self.request("GET", uri, token, vec![]).await
+    }
+
+    pub async fn publish(
+        &self,
+        token: Option<&str>,
+        metadata: &Value,
+        tarball: &[u8],
+    ) -> TestResponse {
+        self.request(
+            "PUT",
+            "/api/v1/crates/new",
+            token,
+            publish_body(metadata, tarball),
+        )
+        .await
+    }
+
+    pub async fn yank(&self, token: Option<&str>, name: &str, version: &str) -> TestResponse {
+        let uri = format!("/api/v1/crates/{}/{}/yank", name, version);
+        self.request("DELETE", &uri, token, vec![]).await
+    }
+
+    pub async fn unyank(&self, token: Option<&str>, name: &str, version: &str) -> TestResponse {
+        let uri = format!("/api/v1/crates/{}/{}/unyank", name, version);
+        self.request("PUT", &uri, token, vec![]).await
+    }
+
+    pub async fn owners(&self, token: Option<&str>, name: &str) -> TestResponse {
+        self.get(&format!("/api/v1/crates/{}/owners", name), token)
+            .await
+    }
+
+    pub async fn add_owners(
+        &self,
+        token: Option<&str>,
+        name: &str,
+        users: &[&str],
+    ) -> TestResponse {
+        let uri = format!("/api/v1/crates/{}/owners", name);
+        self.request("PUT", &uri, token, users_body(users)).await
+    }
+
+    pub async fn remove_owners(
+        &self,
+        token: Option<&str>,
+        name: &str,
+        users: &[&str],
+    ) -> TestResponse {
+        let uri = format!("/api/v1/crates/{}/owners", name);
+        self.request("DELETE", &uri, token, users_body(users)).await
+    }
+
+    // The sparse index, served by the api from the packages table.
+    pub async fn index_file(&self, token: Option<&str>, name: &str) -> TestResponse {
+        self.get(&format!("/index/{}", index_path(name)), token)
+            .await
+    }
+
+    pub fn indexer_config(&self) -> IndexerConfig {
+        let region = match self.backend {
+            Backend::Memory => Region::default(),
+            Backend::DynamoDbLocal { ref region, .. } => region.clone(),
+        };
+
+        ConfigBuilder {
+            region,
+            index_git_url: Some(self.remote_url.clone()),
+            work_dir: self.dir.path().join("indexer"),
+            username: Some(String::new()),
+            password: Some(String::new()),
+            api_url: Some(API_URL.to_string()),
+            ..Default::default()
+        }
+        .build()
+        .expect("indexer config")
+    }
+
+    // One run of the indexer, as the scheduled lambda would do it.
+    pub async fn run_indexer(&self) {
+        let config = self.indexer_config();
+
+        match self.backend {
+            Backend::Memory => self.index_memory_packages(&config).await,
+            Backend::DynamoDbLocal { ref db_config, .. } => {
+                indexer::index(&config, db_config).await.expect("index")
+            }
+        }
+    }
+
+    // The indexer only knows how to read dynamodb, so this rewrites every crate's
+    // index file from the in-memory packages the same way publish_pending does.
+    async fn index_memory_packages(&self, config: &IndexerConfig) {
+        let packages = self
+            .server
+            .state
+            .packages
+            .scan_packages()
+            .await
+            .expect("scan packages");
+
+        let repo = Repo::new(config).expect("repo");
+        repo.checkout().expect("checkout");
+        index_config::ensure_index_config(&repo, config).expect("index config");
+
+        let names: BTreeSet<&str> = packages.iter().map(|p| &p.name[..]).collect();
+        let mut paths = vec![];
+        for name in names.iter() {
+            let crate_packages: Vec<IndexerPackage> = packages
+                .iter()
+                .filter(|p| p.name == *name)
+                .map(|p| IndexerPackage {
+                    entry: p.index_entry(),
+                    description: p.description.clone(),
+                    keywords: p.keywords.clone(),
+                    published_at: p.published_at,
+                    indexed: p.indexed,
+                    deleted: p.deleted,
+                })
+                .collect();
+            paths.push(publish::write_crate_index(&repo, name, &crate_packages).expect("write"));
+        }
+
+        repo.commit(&paths, "Update index").expect("commit");
+        repo.push().expect("push");
+    }
+
+    // A file as it was pushed to the index remote, None if it isn't there yet.
+    pub fn remote_file(&self, path: &str) -> Option<String> {
+        let repo = Repository::open_bare(&self.remote_path).expect("open remote");
+        let branch = self.indexer_config().remote_branch;
+
+        let object = match repo.revparse_single(&format!("refs/heads/{}:{}", branch, path)) {
+            Ok(object) => object,
+            Err(e) if e.code() == git2::ErrorCode::NotFound => return None,
+            Err(e) => panic!("error reading {} from the remote: {}", path, e),
+        };
+        let blob = object.peel_to_blob().expect("blob");
+
+        Some(String::from_utf8(blob.content().to_vec()).expect("utf8"))
+    }
+
+    pub fn remote_index_file(&self, name: &str) -> Option<String> {
+        self.remote_file(&index_path(name))
+    }
+
+    // Pushes a commit removing a file from the index remote, as if it had been deleted
+    // from git by hand, from a clone of its own so the indexer's is left alone.
+    pub fn remove_remote_file(&self, path: &str) {
+        let mut config = self.indexer_config();
+        config.work_dir = self.dir.path().join("editor");
+
+        let repo = Repo::new(&config).expect("repo");
+        repo.checkout().expect("checkout");
+        std::fs::remove_file(repo.path.join(path)).expect("remove file");
+        let message = format!("Remove {}", path);
+        repo.commit(&[path], &message).expect("commit");
+        repo.push().expect("push");
+    }
+}
+
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+}
+
+impl TestResponse {
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+
+    pub fn json<T: DeserializeOwned>(&self) -> T {
+        serde_json::from_slice(&self.body)
+            .unwrap_or_else(|e| panic!("{} is not the expected json: {}", self.text(), e))
+    }
+}
+
+// The json cargo publish sends for a small crate with normal dependencies.
+pub fn metadata(name: &str, vers: &str, deps: &[(&str, &str)]) -> Value {
+    let deps: Vec<Value> = deps
+        .iter()
+        .map(|(name, version_req)| {
+            serde_json::json!({
+                "name": name,
+                "version_req": version_req,
+                "features": [],
+                "optional": false,
+                "default_features": true,
+                "target": null,
+                "kind": "normal",
+                "registry": null,
+                "explicit_name_in_toml": null
+            })
+        })
+        .collect();
+
+    serde_json::json!({
+        "name": name,
+        "vers": vers,
+        "deps": deps,
+        "features": {},
+        "authors": ["Alice <alice@example.com>"],
+        "description": format!("The {} crate.", name),
+        "documentation": null,
+        "homepage": null,
+        "readme": null,
+        "readme_file": null,
+        "keywords": [],
+        "categories": [],
+        "license": "MIT",
+        "license_file": null,
+        "repository": null,
+        "badges": {},
+        "links": null
+    })
+}
+
+// The metadata json and the .crate file, each prefixed with its u32 LE length.
+pub fn publish_body(metadata: &Value, tarball: &[u8]) -> Vec<u8> {
+    let metadata = serde_json::to_vec(metadata).expect("metadata");
+
+    let mut body = vec![];
+    body.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+    body.extend_from_slice(&metadata);
+    body.extend_from_slice(&(tarball.len() as u32).to_le_bytes());
+    body.extend_from_slice(tarball);
+    body
+}
+
+fn users_body(users: &[&str]) -> Vec<u8> {
+    serde_json::to_vec(&serde_json::json!({ "users": users })).expect("users")
+}
+
+fn unique_suffix() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time")
+        .as_millis();
+    let count = HARNESS_COUNT.fetch_add(1, Ordering::SeqCst);
+
+    format!("{}-{}-{}", now, std::process::id(), count)
+}
+
+// The same keys and indexes as the tables in infra/.
+async fn create_tables(client: &DynamoDbClient, tables: &TablesConfig, db_config: &DbConfig) {
+    create_table(
+        client,
+        &tables.tokens,
+        &["user_id", "token_id"],
+        &[(&tables.token_hash_index[..], "token_hash")],
+    )
+    .await;
+    create_table(
+        client,
+        &tables.packages,
+        &["name", "version"],
+        &[
+            (&tables.package_name_index[..], "canonical_name"),
+            (&db_config.packages_pending_index[..], "index_status"),
+        ],
+    )
+    .await;
+    create_table(client, &tables.owners, &["name", "user_id"], &[]).await;
+    create_table(client, &tables.summaries, &["name"], &[]).await;
+    create_table(client, &db_config.registries_table, &["url"], &[]).await;
+}
+
+async fn create_table(
+    client: &DynamoDbClient,
+    table: &str,
+    keys: &[&str],
+    indexes: &[(&str, &str)],
+) {
+    let mut attribute_names: Vec<&str> = keys.to_vec();
+
+    let mut global_secondary_indexes = vec![];
+    for &(index_name, key) in indexes.iter() {
+        attribute_names.push(key);
+        global_secondary_indexes.push(GlobalSecondaryIndex {
+            index_name: index_name.to_string(),
+            key_schema: key_schema(&[key]),
+            projection: Projection {
+                projection_type: Some("ALL".to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+    }
+
+    let attribute_definitions = attribute_names
+        .into_iter()
+        .map(|name| AttributeDefinition {
+            attribute_name: name.to_string(),
+            attribute_type: "S".to_string(),
+        })
+        .collect();
+
+    client
+        .create_table(CreateTableInput {
+            table_name: table.to_string(),
+            attribute_definitions,
+            key_schema: key_schema(keys),
+            global_secondary_indexes: if global_secondary_indexes.is_empty() {
+                None
+            } else {
+                Some(global_secondary_indexes)
+            },
+            billing_mode: Some("PAY_PER_REQUEST".to_string()),
+            ..Default::default()
+        })
+        .await
+        .unwrap_or_else(|e| panic!("error creating table {}: {}", table, e));
+}
+
+// The first key is the hash key and the second, if any, the range key.
+fn key_schema(keys: &[&str]) -> Vec<KeySchemaElement> {
+    keys.iter()
+        .zip(&["HASH", "RANGE"])
+        .map(|(name, key_type)| KeySchemaElement {
+            attribute_name: name.to_string(),
+            key_type: key_type.to_string(),
+        })
+        .collect()
+}
@@ -0,0 +1,306 @@
+use api_types::create::CreateCrateOutput;
+use api_types::index::{index_path, parse_index_file, IndexConfig};
+use api_types::owners::{AddOwnerOutput, GetOwnersOutput, RemoveOwnerOutput};
+use api_types::search::SearchCrateOutput;
+use api_types::tokens::{TokenPermissions, TokenScope};
+use api_types::yank::{UnYankCrateOutput, YankCrateOutput};
+use hyper::StatusCode;
+use integration::{metadata, Harness, API_URL, DYNAMODB_LOCAL_ENV};
+
+// Every test runs in memory, and again against DynamoDB Local if it's configured.
+async fn harnesses() -> Vec<Harness> {
+    let mut harnesses = vec![Harness::memory()];
+    match Harness::dynamodb_local().await {
+        Some(harness) => harnesses.push(harness),
+        None => eprintln!("{} is not set, skipping dynamodb local", DYNAMODB_LOCAL_ENV),
+    }
+    harnesses
+}
+
+fn read_only() -> TokenPermissions {
+    TokenPermissions {
+        scopes: vec![TokenScope::Read],
+        crates: vec![],
+    }
+}
+
+fn owner_logins(output: GetOwnersOutput) -> Vec<String> {
+    let mut logins: Vec<String> = output.users.into_iter().map(|user| user.login).collect();
+    logins.sort();
+    logins
+}
+
+#[tokio::test]
+async fn test_publish_and_index() {
+    for h in harnesses().await {
+        let alice = h.token("alice", TokenPermissions::default()).await;
+
+        let response = h
+            .publish(
+                Some(&alice),
+                &metadata("foo", "0.1.0", &[("rand", "^0.6")]),
+                b"foo 0.1.0",
+            )
+            .await;
+        assert_eq!(
+            response.status,
+            StatusCode::OK,
+            "{}: {}",
+            h.name(),
+            response.text()
+        );
+        let output: CreateCrateOutput = response.json();
+        assert!(output.warnings.invalid_categories.is_empty());
+
+        let again = h
+            .publish(Some(&alice), &metadata("foo", "0.1.0", &[]), b"foo 0.1.0")
+            .await;
+        assert_eq!(again.status, StatusCode::CONFLICT, "{}", h.name());
+        let anonymous = h
+            .publish(None, &metadata("foo", "0.1.1", &[]), b"foo")
+            .await;
+        assert_eq!(anonymous.status, StatusCode::UNAUTHORIZED, "{}", h.name());
+        let unknown = h
+            .publish(Some("not a token"), &metadata("foo", "0.1.1", &[]), b"foo")
+            .await;
+        assert_eq!(unknown.status, StatusCode::UNAUTHORIZED, "{}", h.name());
+
+        // the sparse index has it straight away, git only once the indexer has run
+        let sparse = h.index_file(Some(&alice), "foo").await;
+        assert_eq!(sparse.status, StatusCode::OK, "{}", h.name());
+        let entries = parse_index_file(&sparse.text()).expect("index file");
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].vers, "0.1.0");
+        assert_eq!(entries[0].deps[0].name, "rand");
+        assert_eq!(entries[0].deps[0].req, "^0.6");
+        assert_eq!(h.remote_index_file("foo"), None, "{}", h.name());
+
+        h.run_indexer().await;
+
+        let config: IndexConfig =
+            serde_json::from_str(&h.remote_file("config.json").expect("config.json"))
+                .expect("index config");
+        assert_eq!(config, IndexConfig::new(API_URL, true));
+        let served: IndexConfig = h.get("/index/config.json", Some(&alice)).await.json();
+        assert_eq!(served, config);
+        assert_eq!(
+            h.remote_index_file("foo"),
+            Some(sparse.text()),
+            "{}",
+            h.name()
+        );
+
+        let response = h
+            .publish(Some(&alice), &metadata("foo", "0.2.0", &[]), b"foo 0.2.0")
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", h.name());
+        h.run_indexer().await;
+
+        let remote = h.remote_index_file("foo").expect("index file");
+        let versions: Vec<String> = parse_index_file(&remote)
+            .expect("index file")
+            .into_iter()
+            .map(|entry| entry.vers)
+            .collect();
+        assert_eq!(versions, vec!["0.1.0", "0.2.0"], "{}", h.name());
+        assert_eq!(h.index_file(Some(&alice), "foo").await.text(), remote);
+    }
+}
+
+// Only the indexer's dynamodb backend mirrors git back into the packages table.
+#[tokio::test]
+async fn test_removed_index_file_of_a_mixed_case_crate() {
+    let h = match Harness::dynamodb_local().await {
+        Some(h) => h,
+        None => return eprintln!("{} is not set, skipping", DYNAMODB_LOCAL_ENV),
+    };
+    let alice = h.token("alice", TokenPermissions::default()).await;
+    let response = h
+        .publish(
+            Some(&alice),
+            &metadata("MixedCase", "0.1.0", &[]),
+            b"MixedCase 0.1.0",
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    h.run_indexer().await;
+    assert!(h.remote_index_file("MixedCase").is_some());
+
+    h.remove_remote_file(&index_path("MixedCase"));
+    h.run_indexer().await;
+
+    let sparse = h.index_file(Some(&alice), "MixedCase").await;
+    assert_eq!(sparse.status, StatusCode::NOT_FOUND, "{}", sparse.text());
+    let output: SearchCrateOutput = h
+        .get("/api/v1/crates?q=mixedcase", Some(&alice))
+        .await
+        .json();
+    assert_eq!(output.meta.total, 0);
+}
+
+#[tokio::test]
+async fn test_yank_and_unyank() {
+    for h in harnesses().await {
+        let alice = h.token("alice", TokenPermissions::default()).await;
+        let reader = h.token("alice", read_only()).await;
+        let response = h
+            .publish(Some(&alice), &metadata("bar", "1.0.0", &[]), b"bar 1.0.0")
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", h.name());
+        h.run_indexer().await;
+
+        let yanked = |h: &Harness| {
+            let contents = h.remote_index_file("bar").expect("index file");
+            parse_index_file(&contents).expect("index file")[0].yanked
+        };
+        assert!(!yanked(&h), "{}", h.name());
+
+        let forbidden = h.yank(Some(&reader), "bar", "1.0.0").await;
+        assert_eq!(forbidden.status, StatusCode::FORBIDDEN, "{}", h.name());
+        let missing = h.yank(Some(&alice), "bar", "9.9.9").await;
+        assert_eq!(missing.status, StatusCode::NOT_FOUND, "{}", h.name());
+
+        let response = h.yank(Some(&alice), "bar", "1.0.0").await;
+        assert_eq!(response.status, StatusCode::OK, "{}", h.name());
+        assert_eq!(
+            response.json::<YankCrateOutput>(),
+            YankCrateOutput { ok: true }
+        );
+
+        // yanking is queued for the indexer like publishing is
+        let sparse = h.index_file(Some(&alice), "bar").await;
+        assert!(parse_index_file(&sparse.text()).expect("index file")[0].yanked);
+        assert!(!yanked(&h), "{}", h.name());
+        h.run_indexer().await;
+        assert!(yanked(&h), "{}", h.name());
+
+        let response = h.unyank(Some(&alice), "bar", "1.0.0").await;
+        assert_eq!(response.status, StatusCode::OK, "{}", h.name());
+        assert_eq!(
+            response.json::<UnYankCrateOutput>(),
+            UnYankCrateOutput { ok: true }
+        );
+        h.run_indexer().await;
+        assert!(!yanked(&h), "{}", h.name());
+    }
+}
+
+#[tokio::test]
+async fn test_owners() {
+    for h in harnesses().await {
+        let alice = h.token("alice", TokenPermissions::default()).await;
+        let bob = h.token("bob", TokenPermissions::default()).await;
+        let response = h
+            .publish(Some(&alice), &metadata("baz", "0.1.0", &[]), b"baz 0.1.0")
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", h.name());
+
+        let owners: GetOwnersOutput = h.owners(Some(&bob), "baz").await.json();
+        assert_eq!(owner_logins(owners), vec!["alice"], "{}", h.name());
+        let missing = h.owners(Some(&bob), "qux").await;
+        assert_eq!(missing.status, StatusCode::NOT_FOUND, "{}", h.name());
+
+        let response = h
+            .publish(Some(&bob), &metadata("baz", "0.2.0", &[]), b"baz 0.2.0")
+            .await;
+        assert_eq!(response.status, StatusCode::FORBIDDEN, "{}", h.name());
+        let response = h.add_owners(Some(&bob), "baz", &["bob"]).await;
+        assert_eq!(response.status, StatusCode::FORBIDDEN, "{}", h.name());
+
+        let response = h.add_owners(Some(&alice), "baz", &["bob"]).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", h.name());
+        assert!(response.json::<AddOwnerOutput>().ok);
+        let owners: GetOwnersOutput = h.owners(Some(&bob), "baz").await.json();
+        assert_eq!(owner_logins(owners), vec!["alice", "bob"], "{}", h.name());
+
+        let response = h
+            .publish(Some(&bob), &metadata("baz", "0.2.0", &[]), b"baz 0.2.0")
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", h.name());
+
+        let response = h.remove_owners(Some(&alice), "baz", &["bob"]).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", h.name());
+        assert_eq!(
+            response.json::<RemoveOwnerOutput>(),
+            RemoveOwnerOutput { ok: true }
+        );
+        let response = h.yank(Some(&bob), "baz", "0.2.0").await;
+        assert_eq!(response.status, StatusCode::FORBIDDEN, "{}", h.name());
+        let response = h.remove_owners(Some(&alice), "baz", &["alice"]).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST, "{}", h.name());
+    }
+}
+
+#[tokio::test]
+async fn test_search() {
+    for h in harnesses().await {
+        let alice = h.token("alice", TokenPermissions::default()).await;
+        for (name, vers) in &[
+            ("widgets", "0.1.0"),
+            ("widgets", "0.3.0"),
+            ("gadgets", "1.0.0"),
+        ] {
+            let response = h
+                .publish(Some(&alice), &metadata(name, vers, &[]), vers.as_bytes())
+                .await;
+            assert_eq!(response.status, StatusCode::OK, "{}", h.name());
+        }
+        let response = h.yank(Some(&alice), "widgets", "0.3.0").await;
+        assert_eq!(response.status, StatusCode::OK, "{}", h.name());
+
+        let output: SearchCrateOutput = h
+            .get("/api/v1/crates?q=widgets&per_page=10", Some(&alice))
+            .await
+            .json();
+        assert_eq!(output.meta.total, 1, "{}", h.name());
+        assert_eq!(output.crates[0].name, "widgets");
+        assert_eq!(output.crates[0].max_version, "0.1.0");
+        assert_eq!(output.crates[0].description, "The widgets crate.");
+
+        // descriptions match too
+        let output: SearchCrateOutput = h
+            .get("/api/v1/crates?q=crate&per_page=1", Some(&alice))
+            .await
+            .json();
+        assert_eq!(output.meta.total, 2, "{}", h.name());
+        assert_eq!(output.crates.len(), 1);
+
+        let anonymous = h.get("/api/v1/crates?q=widgets", None).await;
+        assert_eq!(anonymous.status, StatusCode::UNAUTHORIZED, "{}", h.name());
+    }
+}
+
+#[tokio::test]
+async fn test_download() {
+    for h in harnesses().await {
+        let alice = h.token("alice", TokenPermissions::default()).await;
+        let reader = h.token("bob", read_only()).await;
+        let response = h
+            .publish(
+                Some(&alice),
+                &metadata("dl", "0.1.0", &[]),
+                b"crate file contents",
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", h.name());
+        h.run_indexer().await;
+
+        // where cargo goes, following the dl template in the index's config.json
+        let config: IndexConfig =
+            serde_json::from_str(&h.remote_file("config.json").expect("config.json"))
+                .expect("index config");
+        let url = format!("{}/dl/0.1.0/download", config.dl);
+        let uri = url.strip_prefix(API_URL).expect("api url");
+
+        let response = h.get(uri, Some(&reader)).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", h.name());
+        assert_eq!(response.body, b"crate file contents");
+
+        let missing = h
+            .get("/api/v1/crates/dl/0.2.0/download", Some(&reader))
+            .await;
+        assert_eq!(missing.status, StatusCode::NOT_FOUND, "{}", h.name());
+        let anonymous = h.get(uri, None).await;
+        assert_eq!(anonymous.status, StatusCode::UNAUTHORIZED, "{}", h.name());
+    }
+}
@@ -0,0 +1,23 @@
+#!/bin/bash
+
+set -euxo pipefail
+
+# Generates the key api tokens are hashed with, encrypted under the
+# wagon/token-hash-key kms key, and stores the ciphertext where the stacks read it.
+# Run once after the tokens db stack is deployed. Changing it invalidates every token.
+
+aws ssm get-parameter --name /wagon/token-hash-key > /dev/null 2>&1 && {
+  echo "/wagon/token-hash-key already exists"
+  exit 1
+}
+
+ciphertext=$(aws kms generate-data-key-without-plaintext \
+  --key-id alias/wagon/token-hash-key \
+  --key-spec AES_256 \
+  --query CiphertextBlob \
+  --output text)
+
+aws ssm put-parameter \
+  --name /wagon/token-hash-key \
+  --type String \
+  --value "$ciphertext"
@@ -0,0 +1,111 @@
+use api::error::ApiError;
+use api::result::ApiResult;
+use api::state::AppState;
+use api::tokens;
+use api_types::auth::Identity;
+use authorizers::jwt::TrustedIssuers;
+use authorizers::token::AuthorizationHeader;
+
+use crate::config::AuthConfig;
+
+// The custom authorizer's job, done in-process since there's no api gateway in front of us.
+// There's no iam policy either: the handlers already check the identity's scopes and
+// crates, and refuse api keys on the token endpoints.
+pub struct TokenAuthenticator {
+    issuers: TrustedIssuers,
+}
+
+impl TokenAuthenticator {
+    pub fn new(config: &AuthConfig) -> ApiResult<Self> {
+        Ok(TokenAuthenticator {
+            issuers: TrustedIssuers::new(config.issuer_configs()?),
+        })
+    }
+
+    // None for anonymous requests, the handlers decide whether they need an identity.
+    pub async fn authenticate(
+        &self,
+        state: &AppState,
+        header: Option<&str>,
+    ) -> ApiResult<Option<Identity>> {
+        let header = match header {
+            Some(header) => AuthorizationHeader::from_value(header),
+            None => AuthorizationHeader::NotPresent,
+        };
+
+        match header {
+            AuthorizationHeader::NotPresent => Ok(None),
+            AuthorizationHeader::Empty => Err(ApiError::NotAuthorized(
+                "empty authorization header".to_string(),
+            )),
+            AuthorizationHeader::BearerToken(token) => {
+                if self.issuers.is_empty() {
+                    return Err(ApiError::NotAuthorized(
+                        "bearer tokens are not accepted".to_string(),
+                    ));
+                }
+
+                let (identity, _id_token) =
+                    self.issuers.authenticate(&token).await.map_err(|err| {
+                        log::info!("authentication failure: {:?}", err);
+                        ApiError::NotAuthorized("invalid bearer token".to_string())
+                    })?;
+
+                Ok(Some(identity))
+            }
+            AuthorizationHeader::ApiKey(key) => tokens::authenticate_api_key(state, &key)
+                .await?
+                .map(Some)
+                .ok_or_else(|| ApiError::NotAuthorized("api key not found".to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use api::config::Config;
+    use api_types::auth::AuthMethod;
+    use api_types::tokens::TokenPermissions;
+
+    #[tokio::test]
+    async fn test_authenticate_api_key() {
+        let state = AppState::in_memory(Config::default());
+        let authenticator = TokenAuthenticator::new(&AuthConfig {
+            token_hash_key: String::new(),
+            openid_configuration_uri: None,
+            openid_aud: None,
+            issuers: vec![],
+        })
+        .expect("authenticator");
+        let created =
+            tokens::create_user_token(&state, "user", "ci", TokenPermissions::default(), None)
+                .await
+                .expect("create");
+
+        let identity = authenticator
+            .authenticate(&state, Some(&created.token))
+            .await
+            .expect("authenticate")
+            .expect("identity");
+        assert_eq!(identity.principal_id, "user");
+        assert_eq!(identity.auth_method, AuthMethod::ApiKey);
+
+        assert_eq!(
+            authenticator
+                .authenticate(&state, None)
+                .await
+                .expect("anonymous"),
+            None
+        );
+        assert!(authenticator.authenticate(&state, Some("")).await.is_err());
+        assert!(authenticator
+            .authenticate(&state, Some("wrong"))
+            .await
+            .is_err());
+        assert!(authenticator
+            .authenticate(&state, Some("Bearer token"))
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,329 @@
+use rusoto_core::Region;
+use rusoto_dynamodb::DynamoDbClient;
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use api::config::{Config, DEFAULT_TOKEN_EXPIRY_WARNING_DAYS};
+use api::error::ApiError;
+use api::owners::{DynamoDbOwners, MemoryOwners};
+use api::packages::{DynamoDbPackages, MemoryPackages};
+use api::result::ApiResult;
+use api::secrets::{LocalSecrets, Secrets};
+use api::state::AppState;
+use api::storage::LocalStorage;
+use api::summaries::{DynamoDbSummaries, MemorySummaries};
+use api::tokens::{DynamoDbTokens, MemoryTokens};
+use api::trusted_publishing::GitHubOidc;
+use authorizers::jwt::IssuerConfig;
+
+// The toml file the server is started with.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct ServerConfig {
+    #[serde(default = "default_listen")]
+    pub listen: SocketAddr,
+    // Where cargo reaches the server, eg. https://wagon.example.com
+    pub api_url: String,
+    #[serde(default = "default_index_auth_required")]
+    pub index_auth_required: bool,
+    #[serde(default = "default_token_expiry_warning_days")]
+    pub token_expiry_warning_days: i64,
+    // github actions oidc tokens with this aud can be exchanged for publish tokens.
+    #[serde(default)]
+    pub trusted_publishing_audience: Option<String>,
+    pub storage: StorageConfig,
+    #[serde(default)]
+    pub database: DatabaseConfig,
+    pub auth: AuthConfig,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct StorageConfig {
+    // .crate files are kept under here
+    pub dir: PathBuf,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "backend", rename_all = "kebab-case")]
+pub enum DatabaseConfig {
+    // Everything is lost when the server stops.
+    Memory,
+    // DynamoDB Local, or real dynamodb through a regional endpoint.
+    Dynamodb {
+        endpoint: String,
+        #[serde(default = "default_region")]
+        region: String,
+        #[serde(default)]
+        tables: TablesConfig,
+    },
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        DatabaseConfig::Memory
+    }
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct TablesConfig {
+    pub tokens: String,
+    pub token_hash_index: String,
+    pub packages: String,
+    pub package_name_index: String,
+    pub owners: String,
+    pub summaries: String,
+}
+
+impl Default for TablesConfig {
+    fn default() -> Self {
+        TablesConfig {
+            tokens: "ApiTokens".to_string(),
+            token_hash_index: "TokenHashIndex".to_string(),
+            packages: "Packages".to_string(),
+            package_name_index: "NameIndex".to_string(),
+            owners: "Owners".to_string(),
+            summaries: "CrateSummaries".to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct AuthConfig {
+    // Base64 key tokens are hashed with. Unlike the lambda this is the key itself, not kms ciphertext.
+    pub token_hash_key: String,
+    // Bearer tokens are only accepted if an openid provider is configured,
+    // either as a single provider here or as a list of issuers.
+    pub openid_configuration_uri: Option<String>,
+    pub openid_aud: Option<String>,
+    #[serde(default)]
+    pub issuers: Vec<IssuerConfig>,
+}
+
+impl AuthConfig {
+    pub fn issuer_configs(&self) -> ApiResult<Vec<IssuerConfig>> {
+        for config in self.issuers.iter() {
+            config
+                .check()
+                .map_err(|e| ApiError::Other(format!("config auth.issuers: {}", e)))?;
+        }
+
+        let mut configs = self.issuers.clone();
+        if let (Some(uri), Some(aud)) = (&self.openid_configuration_uri, &self.openid_aud) {
+            configs.push(IssuerConfig::user_pool(uri, aud));
+        }
+        Ok(configs)
+    }
+}
+
+fn default_listen() -> SocketAddr {
+    ([127, 0, 0, 1], 8080).into()
+}
+
+fn default_index_auth_required() -> bool {
+    true
+}
+
+fn default_token_expiry_warning_days() -> i64 {
+    DEFAULT_TOKEN_EXPIRY_WARNING_DAYS
+}
+
+fn default_region() -> String {
+    "us-east-1".to_string()
+}
+
+impl ServerConfig {
+    pub fn load<P: AsRef<Path>>(path: P) -> ApiResult<ServerConfig> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path).map_err(|e| {
+            ApiError::Other(format!("error reading config {}: {}", path.display(), e))
+        })?;
+
+        Self::parse(&contents)
+    }
+
+    pub fn parse(contents: &str) -> ApiResult<ServerConfig> {
+        toml::from_str(contents).map_err(|e| ApiError::Other(format!("invalid config: {}", e)))
+    }
+
+    pub fn api_config(&self) -> Config {
+        let mut config = Config {
+            api_url: self.api_url.clone(),
+            index_auth_required: self.index_auth_required,
+            token_expiry_warning_days: self.token_expiry_warning_days,
+            trusted_publishing_audience: self.trusted_publishing_audience.clone(),
+            ..Default::default()
+        };
+
+        if let DatabaseConfig::Dynamodb { ref tables, .. } = self.database {
+            config.tokens_table = tables.tokens.clone();
+            config.packages_table = tables.packages.clone();
+            config.packages_name_index = tables.package_name_index.clone();
+            config.owners_table = tables.owners.clone();
+            config.summaries_table = tables.summaries.clone();
+        }
+
+        config
+    }
+
+    pub fn app_state(&self) -> ApiResult<AppState> {
+        let token_hash_key = base64::decode(&self.auth.token_hash_key)
+            .map_err(|e| ApiError::Other(format!("config key auth.token_hash_key: {}", e)))?;
+        let secrets: Arc<dyn Secrets> = Arc::new(LocalSecrets::new(&token_hash_key));
+        let storage = Box::new(LocalStorage::new(self.storage.dir.clone()));
+        let config = self.api_config();
+        let oidc_verifier = GitHubOidc::from_config(&config);
+
+        Ok(match self.database {
+            DatabaseConfig::Memory => AppState {
+                packages: Box::new(MemoryPackages::default()),
+                owners: Box::new(MemoryOwners::default()),
+                summaries: Box::new(MemorySummaries::default()),
+                tokens: Box::new(MemoryTokens::default()),
+                secrets,
+                storage,
+                oidc_verifier,
+                config,
+            },
+            DatabaseConfig::Dynamodb {
+                ref endpoint,
+                ref region,
+                ref tables,
+            } => {
+                let client = DynamoDbClient::new(Region::Custom {
+                    name: region.clone(),
+                    endpoint: endpoint.clone(),
+                });
+
+                AppState {
+                    packages: Box::new(
+                        DynamoDbPackages::new(client.clone(), &tables.packages)
+                            .with_name_index(&tables.package_name_index),
+                    ),
+                    owners: Box::new(DynamoDbOwners::new(client.clone(), &tables.owners)),
+                    summaries: Box::new(DynamoDbSummaries::new(client.clone(), &tables.summaries)),
+                    tokens: Box::new(
+                        DynamoDbTokens::new(client, &tables.tokens, None, secrets.clone())
+                            .with_token_hash_index(&tables.token_hash_index),
+                    ),
+                    secrets,
+                    storage,
+                    oidc_verifier,
+                    config,
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_memory_config() {
+        let config = ServerConfig::parse(
+            r#"
+            api_url = "http://localhost:8080"
+
+            [storage]
+            dir = "/var/lib/wagon/crates"
+
+            [auth]
+            token_hash_key = "c2VjcmV0"
+            "#,
+        )
+        .expect("parse");
+
+        assert_eq!(config.listen, default_listen());
+        assert!(config.index_auth_required);
+        assert_eq!(config.database, DatabaseConfig::Memory);
+        assert_eq!(config.auth.openid_configuration_uri, None);
+        assert_eq!(config.api_config().api_url, "http://localhost:8080");
+        assert!(config.app_state().is_ok());
+    }
+
+    #[test]
+    fn test_parse_dynamodb_config() {
+        let config = ServerConfig::parse(
+            r#"
+            listen = "0.0.0.0:80"
+            api_url = "https://wagon.example.com"
+            index_auth_required = false
+            trusted_publishing_audience = "https://wagon.example.com"
+
+            [storage]
+            dir = "/var/lib/wagon/crates"
+
+            [database]
+            backend = "dynamodb"
+            endpoint = "http://localhost:8000"
+
+            [database.tables]
+            packages = "WagonPackages"
+
+            [auth]
+            token_hash_key = "c2VjcmV0"
+            openid_configuration_uri = "https://example.com/.well-known/openid-configuration"
+            openid_aud = "wagon"
+
+            [[auth.issuers]]
+            openid_configuration_uri = "https://token.actions.githubusercontent.com/.well-known/openid-configuration"
+            audiences = ["wagon"]
+            principal_claim = "repository"
+            principal_prefix = "github:"
+            scopes = ["publish"]
+            required_claim_values = { repository_owner = ["octo-org"] }
+            "#,
+        )
+        .expect("parse");
+
+        assert_eq!(
+            config.database,
+            DatabaseConfig::Dynamodb {
+                endpoint: "http://localhost:8000".to_string(),
+                region: "us-east-1".to_string(),
+                tables: TablesConfig {
+                    packages: "WagonPackages".to_string(),
+                    ..Default::default()
+                },
+            }
+        );
+        let api_config = config.api_config();
+        assert!(!api_config.index_auth_required);
+        assert_eq!(api_config.packages_table, "WagonPackages");
+        assert_eq!(api_config.tokens_table, "ApiTokens");
+
+        assert_eq!(
+            api_config.trusted_publishing_audience.as_deref(),
+            Some("https://wagon.example.com")
+        );
+
+        let issuers = config.auth.issuer_configs().expect("issuers");
+        assert_eq!(issuers.len(), 2);
+        assert_eq!(issuers[0].principal_prefix, "github:");
+        assert!(!issuers[0].interactive);
+        assert_eq!(issuers[1].audiences, vec!["wagon".to_string()]);
+        assert!(issuers[1].interactive);
+
+        let mut auth = config.auth.clone();
+        auth.issuers[0].required_claim_values.clear();
+        assert!(auth.issuer_configs().is_err());
+    }
+
+    #[test]
+    fn test_invalid_token_hash_key() {
+        let mut config = ServerConfig::parse(
+            r#"
+            api_url = "http://localhost:8080"
+            storage = { dir = "crates" }
+            auth = { token_hash_key = "c2VjcmV0" }
+            "#,
+        )
+        .expect("parse");
+        config.auth.token_hash_key = "not base64!".to_string();
+
+        assert!(config.app_state().is_err());
+    }
+}
@@ -0,0 +1,35 @@
+use hyper::service::{make_service_fn, service_fn};
+use std::convert::Infallible;
+use std::env;
+use std::sync::Arc;
+
+use server::config::ServerConfig;
+use server::service::{self, Server};
+
+type ServerError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+// Runs the registry as a plain long-lived service, without lambda or api gateway.
+// The config file is the first argument, or WAGON_CONFIG, or wagon.toml.
+#[tokio::main]
+async fn main() -> Result<(), ServerError> {
+    drop(env_logger::try_init());
+
+    let path = env::args()
+        .nth(1)
+        .or_else(|| env::var("WAGON_CONFIG").ok())
+        .unwrap_or_else(|| "wagon.toml".to_string());
+    let config = ServerConfig::load(&path)?;
+    let server = Arc::new(Server::new(&config)?);
+
+    let make_service = make_service_fn(move |_conn| {
+        let server = server.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |req| service::handle(server.clone(), req))) }
+    });
+
+    log::info!("listening on {}", config.listen);
+    hyper::Server::bind(&config.listen)
+        .serve(make_service)
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,198 @@
+use api::error::ApiError;
+use api::handlers;
+use api::response::error_response;
+use api::result::ApiResult;
+use api::state::AppState;
+use api_types::auth::Identity;
+use aws_lambda_events::apigw::ApiGatewayProxyRequestContext;
+use hyper::body::HttpBody;
+use lambda_http::http::{self, header::AUTHORIZATION, header::CONTENT_LENGTH};
+use lambda_http::request::RequestContext;
+use lambda_http::{Body, Request, RequestExt, Response};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use crate::auth::TokenAuthenticator;
+use crate::config::ServerConfig;
+
+// The most lambda takes in a request, which is less than api gateway does.
+// Anything that works here has to work when deployed too.
+pub const MAX_BODY_SIZE: usize = 6 * 1024 * 1024;
+
+pub struct Server {
+    pub state: Arc<AppState>,
+    pub authenticator: TokenAuthenticator,
+}
+
+impl Server {
+    pub fn new(config: &ServerConfig) -> ApiResult<Server> {
+        Ok(Server {
+            state: Arc::new(config.app_state()?),
+            authenticator: TokenAuthenticator::new(&config.auth)?,
+        })
+    }
+}
+
+// Authenticates the request, then hands it to the same router the lambda uses,
+// dressed up the way api gateway would deliver it.
+pub async fn handle(
+    server: Arc<Server>,
+    req: hyper::Request<hyper::Body>,
+) -> Result<hyper::Response<hyper::Body>, Infallible> {
+    let response = match authenticated_request(&server, req).await {
+        Ok(req) => handlers::handle(server.state.clone(), req).await,
+        Err(err) => {
+            log::info!("error: {:?}", err);
+            error_response(&err)
+        }
+    };
+
+    Ok(hyper_response(response))
+}
+
+async fn authenticated_request(
+    server: &Server,
+    req: hyper::Request<hyper::Body>,
+) -> ApiResult<Request> {
+    let (parts, body) = req.into_parts();
+
+    let header = parts
+        .headers
+        .get(AUTHORIZATION)
+        .map(|value| {
+            value
+                .to_str()
+                .map_err(|_e| ApiError::NotAuthorized("invalid authorization header".to_string()))
+        })
+        .transpose()?;
+    let identity = server
+        .authenticator
+        .authenticate(&server.state, header)
+        .await?;
+
+    let body = read_body(&parts.headers, body).await?;
+
+    Ok(api_request(parts, body, identity.as_ref()))
+}
+
+// Refuses an oversized body up front if the client says how big it is,
+// and stops reading once it gets too big if not.
+async fn read_body(headers: &http::HeaderMap, mut body: hyper::Body) -> ApiResult<Vec<u8>> {
+    let too_large = || {
+        ApiError::InvalidInput(format!(
+            "request body is larger than {} bytes",
+            MAX_BODY_SIZE
+        ))
+    };
+
+    let content_length = headers
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+    if content_length.unwrap_or_default() > MAX_BODY_SIZE {
+        return Err(too_large());
+    }
+
+    let mut data = Vec::with_capacity(content_length.unwrap_or_default());
+    while let Some(chunk) = body.data().await {
+        let chunk =
+            chunk.map_err(|e| ApiError::InvalidInput(format!("error reading body: {}", e)))?;
+        if data.len() + chunk.len() > MAX_BODY_SIZE {
+            return Err(too_large());
+        }
+        data.extend_from_slice(&chunk);
+    }
+
+    Ok(data)
+}
+
+pub fn api_request(
+    parts: http::request::Parts,
+    body: Vec<u8>,
+    identity: Option<&Identity>,
+) -> Request {
+    let mut query: HashMap<String, Vec<String>> = HashMap::new();
+    for (key, value) in form_urlencoded::parse(parts.uri.query().unwrap_or_default().as_bytes()) {
+        query
+            .entry(key.into_owned())
+            .or_default()
+            .push(value.into_owned());
+    }
+
+    let authorizer = match identity.map(Identity::to_context) {
+        Some(Value::Object(context)) => context.into_iter().collect(),
+        _ => HashMap::new(),
+    };
+
+    Request::from_parts(parts, Body::from(body))
+        .with_query_string_parameters(query)
+        .with_request_context(RequestContext::ApiGatewayV1(
+            ApiGatewayProxyRequestContext {
+                authorizer,
+                ..Default::default()
+            },
+        ))
+}
+
+pub fn hyper_response(response: Response<Body>) -> hyper::Response<hyper::Body> {
+    let (parts, body) = response.into_parts();
+
+    let body = match body {
+        Body::Empty => hyper::Body::empty(),
+        Body::Text(text) => hyper::Body::from(text),
+        Body::Binary(data) => hyper::Body::from(data),
+    };
+
+    hyper::Response::from_parts(parts, body)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use api::ext::AuthContext;
+    use api_types::auth::AuthMethod;
+
+    #[test]
+    fn test_api_request() {
+        let (parts, _body) = http::Request::builder()
+            .method("GET")
+            .uri("/api/v1/crates?q=foo+bar&per_page=5")
+            .body(())
+            .expect("request")
+            .into_parts();
+        let identity = Identity::new("user", AuthMethod::ApiKey);
+
+        let req = api_request(parts, vec![], Some(&identity));
+        assert_eq!(req.uri().path(), "/api/v1/crates");
+        assert_eq!(req.query_string_parameters().first("q"), Some("foo bar"));
+        assert_eq!(req.query_string_parameters().first("per_page"), Some("5"));
+        assert_eq!(req.identity().expect("identity"), identity);
+
+        let (parts, _body) = http::Request::builder()
+            .uri("/index/config.json")
+            .body(())
+            .expect("request")
+            .into_parts();
+        let req = api_request(parts, vec![], None);
+        assert!(matches!(req.identity(), Err(ApiError::NotAuthorized(_))));
+    }
+
+    #[tokio::test]
+    async fn test_read_body_limit() {
+        let mut headers = http::HeaderMap::new();
+        let body = read_body(&headers, hyper::Body::from("body"))
+            .await
+            .expect("body");
+        assert_eq!(body, b"body");
+
+        let big = vec![0u8; MAX_BODY_SIZE + 1];
+        let err = read_body(&headers, hyper::Body::from(big)).await;
+        assert!(matches!(err, Err(ApiError::InvalidInput(_))));
+
+        headers.insert(CONTENT_LENGTH, (MAX_BODY_SIZE + 1).into());
+        let err = read_body(&headers, hyper::Body::from("body")).await;
+        assert!(matches!(err, Err(ApiError::InvalidInput(_))));
+    }
+}